@@ -0,0 +1,358 @@
+use anyhow::bail;
+use serde::Deserialize;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::{v0, AddressLookupTableAccount, Instruction, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::flash_providers::{select_provider, FlashLoan, FlashLoanProvider};
+use crate::inventory::Inventory;
+use crate::signer::TxSigner;
+use crate::utils::{set_compute_unit_limit, set_compute_unit_price};
+
+// Extra compute a flash borrow/repay pair costs on top of the swaps
+pub const FLASH_LOAN_COMPUTE_UNITS: u32 = 100_000;
+// Base fee of every signature in a transaction
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+// Most compute units a transaction can request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionMode {
+    /// Always borrow the input amount
+    FlashLoan,
+    /// Only trade with what the wallet holds
+    Inventory,
+    /// Use whichever nets more, inventory when it covers the amount
+    #[default]
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FeePolicy {
+    /// Compute units the swaps need, without a flash loan
+    pub compute_unit_limit: u32,
+    /// Priority fee in micro-lamports per compute unit
+    pub compute_unit_price: u64,
+    /// Lamports tipped to the block builder
+    pub tip: u64,
+}
+
+impl FeePolicy {
+    /// Priority fee in lamports for a transaction requesting `units`
+    pub fn priority_fee(&self, units: u32) -> u64 {
+        (units as u128 * self.compute_unit_price as u128).div_ceil(1_000_000) as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Funding {
+    Inventory,
+    FlashLoan { provider: &'static str, fee: u64 },
+}
+
+impl Funding {
+    pub fn compute_unit_limit(&self, fee_policy: &FeePolicy) -> u32 {
+        match self {
+            Funding::Inventory => fee_policy.compute_unit_limit,
+            Funding::FlashLoan { .. } => fee_policy.compute_unit_limit + FLASH_LOAN_COMPUTE_UNITS,
+        }
+    }
+
+    /// Lamports paid on top of the swaps, priority fee and tip
+    pub fn lamport_cost(&self, fee_policy: &FeePolicy) -> u64 {
+        fee_policy.priority_fee(self.compute_unit_limit(fee_policy)) + fee_policy.tip
+    }
+
+    /// Flash loan fee, in the borrowed mint
+    pub fn loan_fee(&self) -> u64 {
+        match self {
+            Funding::Inventory => 0,
+            Funding::FlashLoan { fee, .. } => *fee,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FundingPlan {
+    pub funding: Funding,
+    pub net_profit: i128,
+}
+
+/// Everything that decides how a trade is funded and what it costs
+pub struct FundingContext {
+    pub mode: ExecutionMode,
+    pub providers: Vec<Box<dyn FlashLoanProvider>>,
+    pub inventory: Inventory,
+    pub fee_policy: FeePolicy,
+}
+
+impl FundingContext {
+    /// Largest amount of `mint` we can trade in the current mode
+    pub fn max_amount(&self, mint: &Pubkey) -> u64 {
+        let inventory = match self.mode {
+            ExecutionMode::FlashLoan => 0,
+            _ => self.inventory.balance(mint),
+        };
+        let flash_loan = match self.mode {
+            ExecutionMode::Inventory => 0,
+            _ => self
+                .providers
+                .iter()
+                .map(|provider| provider.max_available(mint))
+                .max()
+                .unwrap_or(0),
+        };
+
+        inventory.max(flash_loan)
+    }
+
+    /// Pick how to fund a trade of `amount` of `mint` returning `expected_out`, None when
+    /// nothing can fund it. `lamport_rate` is what a lamport is worth in `mint`, 1 for WSOL
+    pub fn plan(
+        &self,
+        mint: &Pubkey,
+        amount: u64,
+        expected_out: u64,
+        lamport_rate: f64,
+    ) -> Option<FundingPlan> {
+        let gross_profit = expected_out as i128 - amount as i128;
+        let plan = |funding: Funding| {
+            let lamport_cost = funding.lamport_cost(&self.fee_policy) as f64 * lamport_rate;
+            FundingPlan {
+                funding,
+                net_profit: gross_profit - funding.loan_fee() as i128 - lamport_cost.ceil() as i128,
+            }
+        };
+
+        let inventory_plan = (self.mode != ExecutionMode::FlashLoan
+            && self.inventory.covers(mint, amount))
+        .then(|| plan(Funding::Inventory));
+
+        let flash_loan_plan = (self.mode != ExecutionMode::Inventory)
+            .then(|| select_provider(&self.providers, mint, amount))
+            .flatten()
+            .map(|(provider, fee)| {
+                plan(Funding::FlashLoan {
+                    provider: provider.name(),
+                    fee,
+                })
+            });
+
+        match (inventory_plan, flash_loan_plan) {
+            (Some(inventory_plan), Some(flash_loan_plan)) => {
+                if flash_loan_plan.net_profit > inventory_plan.net_profit {
+                    Some(flash_loan_plan)
+                } else {
+                    Some(inventory_plan)
+                }
+            }
+            (inventory_plan, flash_loan_plan) => inventory_plan.or(flash_loan_plan),
+        }
+    }
+
+    /// Build the transaction for a trade of `amount`, funded from inventory or a flash loan
+    /// as `plan` picked
+    pub fn assemble(
+        &self,
+        payer: &Pubkey,
+        user_token_account: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        plan: FundingPlan,
+        swaps: Vec<Instruction>,
+    ) -> Result<AssembledTransaction, anyhow::Error> {
+        let prefix = vec![
+            set_compute_unit_limit(plan.funding.compute_unit_limit(&self.fee_policy)),
+            set_compute_unit_price(self.fee_policy.compute_unit_price),
+        ];
+
+        let instructions = match plan.funding {
+            Funding::Inventory => {
+                let mut instructions = prefix;
+                instructions.extend(swaps);
+                instructions
+            }
+            Funding::FlashLoan { provider, .. } => {
+                let provider = self
+                    .providers
+                    .iter()
+                    .find(|candidate| candidate.name() == provider)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown flash loan provider {}", provider))?;
+
+                wrap_flash_loan(
+                    provider.as_ref(),
+                    payer,
+                    user_token_account,
+                    mint,
+                    amount,
+                    prefix,
+                    swaps,
+                )?
+            }
+        };
+
+        Ok(AssembledTransaction {
+            instructions,
+            funding: plan.funding,
+            net_profit: plan.net_profit,
+        })
+    }
+}
+
+pub struct AssembledTransaction {
+    pub instructions: Vec<Instruction>,
+    pub funding: Funding,
+    pub net_profit: i128,
+}
+
+impl AssembledTransaction {
+    pub fn sign(
+        &self,
+        signer: &dyn TxSigner,
+        recent_blockhash: Hash,
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> Result<VersionedTransaction, anyhow::Error> {
+        sign_transaction(signer, &self.instructions, recent_blockhash, lookup_tables)
+    }
+}
+
+/// Build a v0 transaction paid for and signed by `signer` alone, accounts held by
+/// `lookup_tables` are referenced through them
+pub fn sign_transaction(
+    signer: &dyn TxSigner,
+    instructions: &[Instruction],
+    recent_blockhash: Hash,
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<VersionedTransaction, anyhow::Error> {
+    let message = VersionedMessage::V0(v0::Message::try_compile(
+        &signer.pubkey(),
+        instructions,
+        lookup_tables,
+        recent_blockhash,
+    )?);
+
+    if message.header().num_required_signatures != 1 {
+        bail!(
+            "Transaction needs {} signatures, only the payer signs",
+            message.header().num_required_signatures
+        );
+    }
+    let signature = signer.sign_message(&message)?;
+
+    Ok(VersionedTransaction {
+        signatures: vec![signature],
+        message,
+    })
+}
+
+/// Wrap the swaps in a flash loan of `amount` from `provider`
+pub fn wrap_flash_loan(
+    provider: &dyn FlashLoanProvider,
+    payer: &Pubkey,
+    user_token_account: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+    prefix: Vec<Instruction>,
+    swaps: Vec<Instruction>,
+) -> Result<Vec<Instruction>, anyhow::Error> {
+    let (borrow_count, repay_count) = provider.instruction_counts();
+    let borrow_index = prefix.len();
+    let end_index = borrow_index + borrow_count + swaps.len() + repay_count - 1;
+
+    let loan = FlashLoan {
+        payer: *payer,
+        user_token_account: *user_token_account,
+        mint: *mint,
+        amount,
+        borrow_index: u8::try_from(borrow_index)?,
+        end_index: u8::try_from(end_index)?,
+    };
+
+    let mut instructions = prefix;
+    instructions.extend(provider.borrow_instructions(&loan));
+    instructions.extend(swaps);
+    instructions.extend(provider.repay_instructions(&loan));
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash_providers::tests::provider;
+
+    const FEE_POLICY: FeePolicy = FeePolicy {
+        compute_unit_limit: 200_000,
+        compute_unit_price: 10_000,
+        tip: 1_000,
+    };
+
+    /// Holding `balance` of `mint`, with a flash loan of up to 1e9 at `loan_fee`
+    fn context(mode: ExecutionMode, mint: Pubkey, balance: u64, loan_fee: u64) -> FundingContext {
+        let mut inventory = Inventory::new(Pubkey::new_unique());
+        inventory.set_balance(mint, balance);
+
+        FundingContext {
+            mode,
+            providers: vec![provider("kamino", mint, Some(loan_fee), 1_000_000_000)],
+            inventory,
+            fee_policy: FEE_POLICY,
+        }
+    }
+
+    #[test]
+    fn inventory_funds_what_it_covers() {
+        let mint = Pubkey::new_unique();
+        let funding = context(ExecutionMode::Auto, mint, 1_000_000, 500);
+
+        let plan = funding.plan(&mint, 1_000_000, 1_100_000, 1.0).unwrap();
+        assert_eq!(plan.funding, Funding::Inventory);
+        // 200k units at 10k micro-lamports each, and the tip
+        assert_eq!(plan.net_profit, 100_000 - 2_000 - 1_000);
+    }
+
+    #[test]
+    fn short_inventory_falls_back_to_a_flash_loan() {
+        let mint = Pubkey::new_unique();
+        let funding = context(ExecutionMode::Auto, mint, 999_999, 500);
+
+        let plan = funding.plan(&mint, 1_000_000, 1_100_000, 1.0).unwrap();
+        assert_eq!(
+            plan.funding,
+            Funding::FlashLoan {
+                provider: "kamino",
+                fee: 500
+            }
+        );
+        // The loan's extra compute costs a priority fee too
+        assert_eq!(plan.net_profit, 100_000 - 500 - 3_000 - 1_000);
+
+        let inventory_only = context(ExecutionMode::Inventory, mint, 999_999, 500);
+        assert!(inventory_only
+            .plan(&mint, 1_000_000, 1_100_000, 1.0)
+            .is_none());
+        assert_eq!(inventory_only.max_amount(&mint), 999_999);
+    }
+
+    #[test]
+    fn flash_loan_mode_borrows_even_when_inventory_covers() {
+        let mint = Pubkey::new_unique();
+        let funding = context(ExecutionMode::FlashLoan, mint, 5_000_000, 500);
+
+        let plan = funding.plan(&mint, 1_000_000, 1_100_000, 1.0).unwrap();
+        assert!(matches!(plan.funding, Funding::FlashLoan { fee: 500, .. }));
+        assert_eq!(funding.max_amount(&mint), 1_000_000_000);
+    }
+
+    #[test]
+    fn lamport_costs_are_priced_in_the_loan_mint() {
+        let mint = Pubkey::new_unique();
+        let funding = context(ExecutionMode::Inventory, mint, 1_000_000, 0);
+
+        // 3_000 lamports of fees and tip, at 0.15 units of the mint per lamport
+        let plan = funding.plan(&mint, 1_000_000, 1_001_000, 0.15).unwrap();
+        assert_eq!(plan.net_profit, 1_000 - 450);
+    }
+}
@@ -0,0 +1,61 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(about = "Solana CLMM arbitrage bot")]
+pub struct Cli {
+    /// Config file, `ARBITRAGE_CONFIG` or arbitrage.toml by default
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Stream pools, look for cycles and size trades
+    Run,
+    /// Stream pools and print their prices, without the strategy or a wallet
+    Watch,
+    /// Quote an exact-in swap through one pool at its current state
+    Quote {
+        /// Pool name from the config, or its address
+        pool: String,
+        /// Raw input amount
+        amount: u64,
+        /// Swap the pool's second mint for its first
+        #[arg(long)]
+        b_to_a: bool,
+    },
+    /// Quote, fund and simulate the route described in a JSON file
+    Simulate { opportunity_file: PathBuf },
+    /// Preflight checks of endpoints, wallet, token accounts, pools, lookup table and reserves
+    Doctor {
+        /// Create missing token accounts, recreate a non-native WSOL account and wrap
+        /// `wallet.wrap_lamports`
+        #[arg(long)]
+        fix: bool,
+        /// Show and simulate the fix transactions without sending them
+        #[arg(long, requires = "fix")]
+        dry_run: bool,
+    },
+    /// Wrap lamports into the wallet's WSOL account
+    Wrap { lamports: u64 },
+    /// Close the wallet's WSOL account, returning its SOL
+    Unwrap,
+    /// Manage the address lookup table of our static accounts
+    Lut {
+        #[command(subcommand)]
+        command: LutCommand,
+    },
+    /// List pools matching the discovery filter, as [[pools]] entries
+    Discover,
+}
+
+#[derive(Subcommand)]
+pub enum LutCommand {
+    /// Create a new table, save it as `lookup_table.address` in the config and fill it
+    Create,
+    /// Add whatever the configured table is missing
+    Extend,
+}
@@ -1,11 +1,14 @@
 use solana_client::{client_error, rpc_client::RpcClient};
 use solana_commitment_config::CommitmentConfig;
-use solana_sdk::{hash::Hash, pubkey::Pubkey, signer::Signer};
+use solana_sdk::message::Instruction;
+use solana_sdk::signature::Signature;
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
 use spl_associated_token_account::get_associated_token_address_with_program_id;
 use spl_token::ID as TOKEN_PROGRAM_ID;
 use std::str::FromStr;
 
-use crate::payer::get_payer;
+use crate::assembler::sign_transaction;
+use crate::signer::TxSigner;
 
 pub struct BotRpcClient {
     pub connection: RpcClient,
@@ -35,14 +38,31 @@ impl BotRpcClient {
         )
     }
 
-    pub fn get_associated_token_account(&self, token_address: &str) -> Option<Pubkey> {
-        let wallet = get_payer();
+    /// Sign `instructions` with `payer` alone, send them and wait for confirmation
+    pub fn send_instructions(
+        &self,
+        payer: &dyn TxSigner,
+        instructions: &[Instruction],
+    ) -> Result<Signature, anyhow::Error> {
+        let (recent_blockhash, _) = self.get_latest_block_hash(None)?;
+        let tx = sign_transaction(payer, instructions, recent_blockhash, &[])?;
+
+        let signature = self.connection.send_and_confirm_transaction(&tx)?;
+        println!("Tx signature: {}", signature);
 
+        Ok(signature)
+    }
+
+    pub fn get_associated_token_account(
+        &self,
+        wallet: &Pubkey,
+        token_address: &str,
+    ) -> Option<Pubkey> {
         match &Pubkey::from_str(token_address) {
             Ok(address) => {
                 let account_data = get_associated_token_address_with_program_id(
                     &spl_associated_token_account::solana_program::pubkey::Pubkey::new_from_array(
-                        wallet.pubkey().to_bytes(),
+                        wallet.to_bytes(),
                     ),
                     &spl_associated_token_account::solana_program::pubkey::Pubkey::new_from_array(
                         address.to_bytes(),
@@ -0,0 +1,34 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+use crate::config::Config;
+use crate::discovery::discover_pools;
+
+/// Print the pools matching the discovery filter as entries to paste into the config
+pub async fn discover(config: &Config) -> Result<(), anyhow::Error> {
+    let filter = config
+        .discovery
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("No [discovery] section in the config"))?;
+
+    let rpc_client = RpcClient::new(config.rpc_url.clone());
+    let pools = discover_pools(&rpc_client, filter).await?;
+
+    for pool in &pools {
+        let configured = config
+            .pools
+            .iter()
+            .any(|known| known.pool_id == pool.pool_id);
+
+        println!("[[pools]]{}", if configured { " # configured" } else { "" });
+        println!("name = {:?}", pool.name);
+        println!("address = \"{}\"", pool.pool_id);
+        println!("dex = \"{}\"", format!("{:?}", pool.pool).to_lowercase());
+        if let Some(fee_rate) = pool.fee_rate {
+            println!("fee_rate = {}", fee_rate);
+        }
+        println!();
+    }
+    eprintln!("Found {} pools", pools.len());
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::bail;
+use solana_sdk::message::Instruction;
+
+use crate::assembler::sign_transaction;
+use crate::client::BotRpcClient;
+use crate::config::Config;
+use crate::debug::{self, FixPlan, FixStep};
+use crate::signer::{load_signer, TxSigner};
+
+/// Run the preflight checks and, with `fix`, repair the wallet's token accounts. A dry
+/// run shows and simulates the transactions without sending them
+pub async fn doctor(config: &Config, fix: bool, dry_run: bool) -> Result<(), anyhow::Error> {
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let wallet = load_signer(&config.signer)?;
+
+    let mut report = debug::run_doctor(config, &rpc_client, &wallet.pubkey()).await;
+    println!("{}", report);
+
+    if fix {
+        let accounts = debug::required_token_accounts(config, &rpc_client, &wallet.pubkey())?;
+        let plan = debug::plan_fixes(&accounts, &wallet.pubkey(), config.wrap_lamports)?;
+
+        if apply_fixes(&rpc_client, wallet.as_ref(), &plan, dry_run)? {
+            report = debug::run_doctor(config, &rpc_client, &wallet.pubkey()).await;
+            println!("{}", report);
+        }
+    }
+
+    if !report.is_healthy() {
+        bail!("Preflight checks failed");
+    }
+    Ok(())
+}
+
+/// Show, simulate and unless `dry_run` send every transaction of the plan, returns
+/// whether anything was sent
+fn apply_fixes(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    plan: &FixPlan,
+    dry_run: bool,
+) -> Result<bool, anyhow::Error> {
+    for problem in &plan.unfixable {
+        println!("Can't fix: {}", problem);
+    }
+    if plan.is_empty() {
+        println!("Nothing to fix");
+        return Ok(false);
+    }
+
+    for (index, (label, steps)) in plan.transactions().into_iter().enumerate() {
+        let instructions = steps
+            .iter()
+            .flat_map(|step| step.instructions.iter().cloned())
+            .collect::<Vec<_>>();
+        print_transaction(&label, steps);
+
+        // Recreating an account or wrapping into it only simulates once the earlier
+        // transactions have landed
+        if dry_run && index > 0 {
+            println!(
+                "{} transaction simulates once the previous one lands",
+                label
+            );
+            continue;
+        }
+        simulate(rpc_client, payer, &instructions)?;
+
+        if !dry_run {
+            rpc_client.send_instructions(payer, &instructions)?;
+        }
+    }
+
+    if dry_run {
+        println!("Dry run, nothing sent");
+    }
+    Ok(!dry_run)
+}
+
+fn print_transaction(label: &str, steps: &[FixStep]) {
+    println!("{} transaction:", label);
+
+    for step in steps {
+        println!("  {}", step.description);
+        for instruction in &step.instructions {
+            println!(
+                "    program {}, {} bytes of data",
+                instruction.program_id,
+                instruction.data.len()
+            );
+            for account in &instruction.accounts {
+                println!(
+                    "      {}{}{}",
+                    account.pubkey,
+                    if account.is_signer { " signer" } else { "" },
+                    if account.is_writable { " writable" } else { "" }
+                );
+            }
+        }
+    }
+}
+
+fn simulate(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    instructions: &[Instruction],
+) -> Result<(), anyhow::Error> {
+    let (recent_blockhash, _) = rpc_client.get_latest_block_hash(None)?;
+    let tx = sign_transaction(payer, instructions, recent_blockhash, &[])?;
+
+    let result = rpc_client.connection.simulate_transaction(&tx)?.value;
+    for log in result.logs.unwrap_or_default() {
+        println!("    {}", log);
+    }
+    if let Some(err) = result.err {
+        bail!("Simulation failed: {}", err);
+    }
+    println!(
+        "  Simulated, {} compute units",
+        result.units_consumed.unwrap_or_default()
+    );
+
+    Ok(())
+}
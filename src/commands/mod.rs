@@ -0,0 +1,169 @@
+pub mod discover;
+pub mod doctor;
+pub mod quote;
+pub mod run;
+pub mod simulate;
+pub mod wallet;
+
+use anyhow::{anyhow, Context};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::assembler::FundingContext;
+use crate::client::BotRpcClient;
+use crate::config::Config;
+use crate::feed::MAX_MULTIPLE_ACCOUNTS;
+use crate::flash_providers::kamino::KaminoProvider;
+use crate::flash_providers::marginfi::{main_group, MarginfiProvider};
+use crate::flash_providers::save::SaveProvider;
+use crate::flash_providers::{refresh_providers, FlashLoanProvider};
+use crate::inventory::Inventory;
+use crate::kamino::{main_lending_market, ReserveCatalog};
+use crate::opportunity::PoolQuoter;
+use crate::pools_struct::structs::{DexType, Pool, PriceFetcher};
+use crate::utils::parse_account_data;
+
+/// Kamino reserves from the config, discovering the lending market if the loan mint isn't one
+pub fn reserve_catalog(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+) -> Result<ReserveCatalog, anyhow::Error> {
+    let mut reserves = ReserveCatalog::new(config.kamino_reserves.clone());
+
+    if reserves.get(&config.loan_mint).is_none() {
+        let discovered = reserves.discover(rpc_client, &main_lending_market())?;
+        println!("Discovered {} Kamino reserves", discovered);
+    }
+
+    Ok(reserves)
+}
+
+/// Flash loan providers and wallet balances to fund trades of the loan mint
+pub fn funding_context(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) -> Result<FundingContext, anyhow::Error> {
+    let reserves = reserve_catalog(config, rpc_client)?;
+
+    let mut providers: Vec<Box<dyn FlashLoanProvider>> = vec![
+        Box::new(KaminoProvider::new(reserves, config.kamino_referrer)),
+        Box::new(SaveProvider::new(config.save_reserves.clone())),
+    ];
+    if let Some(marginfi_account) = config.marginfi_account {
+        providers.push(Box::new(MarginfiProvider::new(
+            main_group(),
+            marginfi_account,
+            config.marginfi_banks.clone(),
+        )));
+    }
+    refresh_providers(&mut providers, rpc_client);
+
+    let mut inventory = Inventory::new(*wallet);
+    inventory.refresh(rpc_client, &[config.loan_mint])?;
+
+    Ok(FundingContext {
+        mode: config.execution_mode,
+        providers,
+        inventory,
+        fee_policy: config.fee_policy,
+    })
+}
+
+/// Pool by config name or address, an unknown address is looked up from its owner program.
+/// Unless the config sets one, the fee is read from the pool state once `fetch_quoters`
+/// loaded it, Raydium's from the pool's AmmConfig
+pub fn find_pool(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    name_or_address: &str,
+) -> Result<Pool, anyhow::Error> {
+    if let Some(pool) = config
+        .pools
+        .iter()
+        .find(|pool| pool.name == name_or_address)
+    {
+        return Ok(pool.clone());
+    }
+
+    let pool_id = Pubkey::from_str(name_or_address).map_err(|_| {
+        anyhow!(
+            "{} is neither a configured pool nor an address",
+            name_or_address
+        )
+    })?;
+    if let Some(pool) = config.pools.iter().find(|pool| pool.pool_id == pool_id) {
+        return Ok(pool.clone());
+    }
+
+    let account = rpc_client.connection.get_account(&pool_id)?;
+    let dex = DexType::from_program_id(&account.owner)
+        .ok_or_else(|| anyhow!("{} is owned by {}, not a known DEX", pool_id, account.owner))?;
+
+    Ok(Pool {
+        name: pool_id.to_string(),
+        pool_id,
+        pool: dex,
+        fee_rate: None,
+    })
+}
+
+/// Current state of `pools` and the accounts their quotes depend on, ready to quote
+pub fn fetch_quoters(
+    rpc_client: &BotRpcClient,
+    pools: &[Pool],
+) -> Result<Vec<PoolQuoter>, anyhow::Error> {
+    let pool_ids = pools.iter().map(|pool| pool.pool_id).collect::<Vec<_>>();
+    let response = rpc_client
+        .connection
+        .get_multiple_accounts_with_commitment(&pool_ids, CommitmentConfig::confirmed())?;
+    let slot = response.context.slot;
+
+    let mut states = pools
+        .iter()
+        .zip(response.value)
+        .map(|(pool, account)| {
+            let account = account.ok_or_else(|| anyhow!("Pool {} not found", pool.pool_id))?;
+            parse_account_data(&account.data, pool.pool)
+                .with_context(|| format!("Can't decode pool {}", pool.name))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    let dependent = pools
+        .iter()
+        .zip(&states)
+        .flat_map(|(pool, state)| state.get_dependent_accounts(&pool.pool_id))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let mut dependent_accounts = HashMap::new();
+    for chunk in dependent.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client
+            .connection
+            .get_multiple_accounts_with_commitment(chunk, CommitmentConfig::confirmed())?;
+        for (address, account) in chunk.iter().zip(accounts.value) {
+            if let Some(account) = account {
+                dependent_accounts.insert(*address, account.data);
+            }
+        }
+    }
+
+    pools
+        .iter()
+        .zip(states.iter_mut())
+        .for_each(|(pool, state)| {
+            state.load_dependent_accounts(&pool.pool_id, &dependent_accounts)
+        });
+
+    pools
+        .iter()
+        .zip(states)
+        .map(|(pool, state)| {
+            PoolQuoter::new(pool.clone(), Arc::<dyn PriceFetcher>::from(state), slot)
+                .ok_or_else(|| anyhow!("Pool {} can't be quoted", pool.name))
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+use crate::client::BotRpcClient;
+use crate::commands::{fetch_quoters, find_pool};
+use crate::config::Config;
+
+/// Print what `amount` buys through one pool at its current state
+pub fn quote(config: &Config, pool: &str, amount: u64, a_to_b: bool) -> Result<(), anyhow::Error> {
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let pool = find_pool(config, &rpc_client, pool)?;
+    let quoter = fetch_quoters(&rpc_client, &[pool])?.remove(0);
+
+    let (mint_a, mint_b) = quoter.mints;
+    let (input_mint, output_mint) = if a_to_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    };
+    let amount_out = quoter
+        .quote(amount, a_to_b)
+        .ok_or_else(|| anyhow::anyhow!("{} can't quote {} in", quoter.pool.name, amount))?;
+
+    println!(
+        "{} ({:?} {}) at slot {}, fee rate {}",
+        quoter.pool.name, quoter.pool.pool, quoter.pool.pool_id, quoter.slot, quoter.fee_rate
+    );
+    println!(
+        "{} {} -> {} {} (spot rate {:?})",
+        amount,
+        input_mint,
+        amount_out,
+        output_mint,
+        quoter.spot_rate(a_to_b)
+    );
+
+    Ok(())
+}
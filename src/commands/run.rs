@@ -0,0 +1,260 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::BotRpcClient;
+use crate::commands::funding_context;
+use crate::config::Config;
+use crate::discovery::{self, discover_pools};
+use crate::feed::grpc::GrpcSource;
+use crate::feed::polling::{self, PollingSource};
+use crate::feed::queue::UpdateQueue;
+use crate::feed::websocket::WebsocketSource;
+use crate::feed::{self, AccountSource, FeedSink};
+use crate::market::{MarketEntry, MarketState};
+use crate::opportunity::OpportunityEngine;
+use crate::pools_struct::structs::Pool;
+use crate::signer::load_signer;
+
+/// Market state fed by every configured source, and the pools it updated
+struct Feed {
+    markets: Arc<MarketState>,
+    queue: Arc<UpdateQueue>,
+}
+
+/// Stream pools, look for cycles and size trades until Ctrl-C
+pub async fn run(config: &Config) -> Result<(), anyhow::Error> {
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let wallet = load_signer(&config.signer)?;
+    let loan_mint = config.loan_mint;
+
+    let funding = Arc::new(funding_context(config, &rpc_client, &wallet.pubkey())?);
+
+    println!(
+        "Up to {} of {} available in {:?} mode",
+        funding.max_amount(&loan_mint),
+        loan_mint,
+        funding.mode
+    );
+
+    let Feed { markets, queue } = start_feed(config).await;
+
+    let mut engine = OpportunityEngine::new(
+        loan_mint,
+        config.max_trade_size,
+        config.max_slot_spread,
+        funding.clone(),
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let pool_id = queue.recv().await;
+            let Some(entry) = markets.get(&pool_id) else {
+                continue;
+            };
+            print_price(&markets, &entry);
+
+            let trades = engine.update(&entry.pool, entry.state, entry.slot, |pool_id| {
+                markets.confirmed_slot(pool_id)
+            });
+            println!(
+                "Evaluated {} of {} cycles in {:?} (avg {:?}, max {:?}), {} stale skipped",
+                engine.stats.last_cycles,
+                engine.cycle_count(),
+                engine.stats.last,
+                engine.stats.average(),
+                engine.stats.max,
+                engine.stats.stale
+            );
+            let queue_stats = queue.stats();
+            println!(
+                "Queue: {} pending, {} of {} updates coalesced",
+                queue_stats.pending, queue_stats.coalesced, queue_stats.pushed
+            );
+
+            for trade in trades {
+                let opportunity = &trade.opportunity;
+                let route = opportunity
+                    .legs
+                    .iter()
+                    .map(|leg| leg.pool.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                println!(
+                    "Opportunity: {}, {} in, {:?} out, profit {} gross {} net funded by {:?}",
+                    route,
+                    opportunity.amount_in,
+                    opportunity.amounts,
+                    opportunity.expected_profit,
+                    trade.net_profit,
+                    trade.funding
+                );
+                println!("Profit curve: {:?}", trade.curve);
+            }
+        }
+    });
+
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+/// Stream pools and print every price change until Ctrl-C
+pub async fn watch(config: &Config) -> Result<(), anyhow::Error> {
+    let Feed { markets, queue } = start_feed(config).await;
+
+    tokio::spawn(async move {
+        loop {
+            let pool_id = queue.recv().await;
+            if let Some(entry) = markets.get(&pool_id) {
+                print_price(&markets, &entry);
+            }
+        }
+    });
+
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+fn print_price(markets: &MarketState, entry: &MarketEntry) {
+    let pair_pools = entry.state.get_mints().map_or(0, |(mint_a, mint_b)| {
+        markets.by_mints(&mint_a, &mint_b).len()
+    });
+
+    match entry.state.get_price() {
+        Ok(price) => println!(
+            "{} {} ({}) -> {}, {} pools on the pair",
+            entry.state.get_dex_name(),
+            entry.pool.name,
+            entry.pool.pool_id,
+            price,
+            pair_pools
+        ),
+        Err(err) => eprintln!("Error:{}", err),
+    }
+}
+
+/// Configured and discovered pools, deduplicated by address
+async fn pools(config: &Config) -> Vec<Pool> {
+    let mut pools = config.pools.clone();
+
+    if let Some(discovery_filter) = &config.discovery {
+        let discovery_rpc = RpcClient::new(config.rpc_url.clone());
+        match discover_pools(&discovery_rpc, discovery_filter).await {
+            Ok(discovered) => {
+                let configured = pools.len();
+                for pool in discovered {
+                    if !pools.iter().any(|known| known.pool_id == pool.pool_id) {
+                        pools.push(pool);
+                    }
+                }
+                println!("Discovered {} pools", pools.len() - configured);
+            }
+            Err(err) => eprintln!("Error discovering pools: {}", err),
+        }
+    }
+
+    pools
+}
+
+/// Subscribe every source, the polling fallback and the program watcher, and log a
+/// summary of the feed every 10s
+async fn start_feed(config: &Config) -> Feed {
+    let addresses = pools(config).await;
+    let rpc_url = config.rpc_url.as_str();
+
+    let markets = Arc::new(MarketState::new());
+    let queue = Arc::new(UpdateQueue::new());
+    let sink = Arc::new(FeedSink::new(&addresses, markets.clone(), queue.clone()));
+
+    let mut sources: Vec<Arc<dyn AccountSource>> = config
+        .ws_endpoints
+        .iter()
+        .cloned()
+        .map(|url| Arc::new(WebsocketSource::new(url)) as Arc<dyn AccountSource>)
+        .collect();
+    if let Some(grpc) = &config.grpc {
+        sources.push(Arc::new(GrpcSource::new(
+            grpc.endpoint.clone(),
+            grpc.x_token.clone(),
+            grpc.owners.clone(),
+        )));
+    }
+    for source in sources {
+        tokio::spawn(feed::supervise(source, rpc_url.to_string(), sink.clone()));
+    }
+
+    if let (Some(ws_url), Some(discovery_filter)) =
+        (config.ws_endpoints.first(), config.discovery.clone())
+    {
+        tokio::spawn(discovery::watch_programs(
+            ws_url.clone(),
+            rpc_url.to_string(),
+            discovery_filter,
+            sink.clone(),
+        ));
+    }
+
+    let polling = Arc::new(PollingSource::new(
+        rpc_url.to_string(),
+        config.polling_interval,
+    ));
+    tokio::spawn(polling::fallback(
+        polling,
+        sink.clone(),
+        config.stream_stale_after,
+        config.consistency_check_interval,
+    ));
+
+    let mut dexes = vec![];
+    for pool in addresses.iter() {
+        if !dexes.contains(&pool.pool) {
+            dexes.push(pool.pool);
+        }
+    }
+    let summary_markets = markets.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+
+            for dex in dexes.iter() {
+                for entry in summary_markets.by_dex(*dex) {
+                    let Some(age) = summary_markets.age(&entry.pool.pool_id) else {
+                        continue;
+                    };
+
+                    println!(
+                        "{:?} {}: slot {}, confirmed at {} ({} behind), received {:?} ago",
+                        dex,
+                        entry.pool.name,
+                        entry.slot,
+                        entry.confirmed_slot,
+                        age.slots,
+                        age.elapsed
+                    );
+                }
+            }
+
+            for (source, stats) in sink.stats() {
+                println!(
+                    "{}: {} updates, {} first, {} duplicates (avg {:?} behind, max {:?}), {} stale",
+                    source,
+                    stats.updates,
+                    stats.first,
+                    stats.duplicates,
+                    stats.average_behind(),
+                    stats.max_behind,
+                    stats.stale
+                );
+            }
+            let (tracked, received) = sink.dependent_account_count();
+            println!(
+                "{} dependent accounts tracked, {} received",
+                tracked, received
+            );
+        }
+    });
+
+    Feed { markets, queue }
+}
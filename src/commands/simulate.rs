@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::client::BotRpcClient;
+use crate::commands::{fetch_quoters, find_pool, funding_context};
+use crate::config::Config;
+use crate::graph::TokenGraph;
+use crate::lut::fetch_lookup_table;
+use crate::opportunity::{quote_route, swap_instructions};
+use crate::signer::load_signer;
+use crate::sizing::size_route;
+use crate::utils::get_associated_token_address;
+
+/// Route through `pools` starting and ending in the loan mint, e.g.
+/// `{ "pools": ["orca", "raydium"], "amount_in": 1000000000 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OpportunityFile {
+    /// Config names or addresses, in swap order
+    pools: Vec<String>,
+    /// Sized like the live bot would when omitted
+    amount_in: Option<u64>,
+}
+
+/// Quote the route at current pool states, then simulate the transaction trading it
+pub fn simulate(config: &Config, opportunity_file: &Path) -> Result<(), anyhow::Error> {
+    let contents = fs::read_to_string(opportunity_file)
+        .with_context(|| format!("can't read {}", opportunity_file.display()))?;
+    let file = serde_json::from_str::<OpportunityFile>(&contents)
+        .with_context(|| format!("invalid opportunity {}", opportunity_file.display()))?;
+
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let wallet = load_signer(&config.signer)?;
+    let loan_mint = config.loan_mint;
+
+    let pools = file
+        .pools
+        .iter()
+        .map(|pool| find_pool(config, &rpc_client, pool))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut graph = TokenGraph::new(loan_mint);
+    for quoter in fetch_quoters(&rpc_client, &pools)? {
+        graph.insert(quoter);
+    }
+
+    let mut legs = Vec::with_capacity(pools.len());
+    let mut mint = loan_mint;
+    for pool in &pools {
+        let leg = graph
+            .pool(&pool.pool_id)
+            .and_then(|quoter| quoter.leg(&mint))
+            .ok_or_else(|| anyhow!("{} doesn't trade {}", pool.name, mint))?;
+        mint = leg.output_mint;
+        legs.push(leg);
+    }
+    if mint != loan_mint {
+        return Err(anyhow!("Route ends in {}, not {}", mint, loan_mint));
+    }
+
+    let funding = funding_context(config, &rpc_client, &wallet.pubkey())?;
+    let opportunity = match file.amount_in {
+        Some(amount_in) => quote_route(&graph, &legs, amount_in),
+        None => size_route(&graph, &legs, &funding, config.max_trade_size)
+            .map(|trade| trade.opportunity),
+    }
+    .ok_or_else(|| anyhow!("Route can't be quoted"))?;
+    println!(
+        "{} in, {:?} out, profit {}",
+        opportunity.amount_in, opportunity.amounts, opportunity.expected_profit
+    );
+
+    let lamport_rate = graph
+        .lamport_rate()
+        .ok_or_else(|| anyhow!("No pool prices {} in SOL to net fees against", loan_mint))?;
+
+    let plan = funding
+        .plan(
+            &loan_mint,
+            opportunity.amount_in,
+            opportunity.expected_out,
+            lamport_rate,
+        )
+        .ok_or_else(|| {
+            anyhow!(
+                "Can't fund {} of {} in {:?} mode",
+                opportunity.amount_in,
+                loan_mint,
+                funding.mode
+            )
+        })?;
+
+    let swaps = swap_instructions(&graph, &opportunity, &wallet.pubkey())?;
+    let assembled = funding.assemble(
+        &wallet.pubkey(),
+        &get_associated_token_address(&wallet.pubkey(), &loan_mint),
+        &loan_mint,
+        opportunity.amount_in,
+        plan,
+        swaps,
+    )?;
+    println!(
+        "Funded by {:?}, net profit {}",
+        assembled.funding, assembled.net_profit
+    );
+
+    let lookup_tables = match &config.lookup_table {
+        Some(lookup_table) => vec![fetch_lookup_table(&rpc_client, lookup_table)?],
+        None => {
+            println!("No lookup table configured, simulating without one");
+            vec![]
+        }
+    };
+
+    let (recent_blockhash, _) = rpc_client.get_latest_block_hash(None)?;
+    let tx = assembled.sign(wallet.as_ref(), recent_blockhash, &lookup_tables)?;
+    let result = rpc_client.connection.simulate_transaction(&tx)?.value;
+
+    for log in result.logs.unwrap_or_default() {
+        println!("{}", log);
+    }
+    println!("Units consumed: {:?}", result.units_consumed);
+    match result.err {
+        Some(err) => Err(anyhow!("Simulation failed: {}", err)),
+        None => {
+            println!("Simulation succeeded");
+            Ok(())
+        }
+    }
+}
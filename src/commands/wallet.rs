@@ -0,0 +1,53 @@
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::instruction::close_account;
+
+use crate::client::BotRpcClient;
+use crate::config::Config;
+use crate::signer::load_signer;
+use crate::utils::{
+    from_program_instruction, get_associated_token_address, program_pubkey, wrap_sol, wsol_mint,
+};
+
+/// Move `lamports` into the wallet's WSOL account, creating it if needed
+pub fn wrap(config: &Config, lamports: u64) -> Result<(), anyhow::Error> {
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let wallet = load_signer(&config.signer)?;
+    let owner = wallet.pubkey();
+    let wsol_account = get_associated_token_address(&owner, &wsol_mint());
+
+    let mut instructions = vec![from_program_instruction(
+        create_associated_token_account_idempotent(
+            &program_pubkey(&owner),
+            &program_pubkey(&owner),
+            &spl_token::native_mint::ID,
+            &spl_token::ID,
+        ),
+    )];
+    instructions.extend(wrap_sol(&owner, &wsol_account, lamports)?);
+
+    rpc_client.send_instructions(wallet.as_ref(), &instructions)?;
+    println!("Wrapped {} lamports into {}", lamports, wsol_account);
+
+    Ok(())
+}
+
+/// Close the wallet's WSOL account, its lamports go back to the wallet
+pub fn unwrap(config: &Config) -> Result<(), anyhow::Error> {
+    let rpc_client = BotRpcClient::new(&config.rpc_url);
+    let wallet = load_signer(&config.signer)?;
+    let owner = wallet.pubkey();
+    let wsol_account = get_associated_token_address(&owner, &wsol_mint());
+
+    let instruction = from_program_instruction(close_account(
+        &spl_token::ID,
+        &program_pubkey(&wsol_account),
+        &program_pubkey(&owner),
+        &program_pubkey(&owner),
+        &[],
+    )?);
+
+    rpc_client.send_instructions(wallet.as_ref(), &[instruction])?;
+    println!("Closed {}", wsol_account);
+
+    Ok(())
+}
@@ -0,0 +1,802 @@
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::assembler::{ExecutionMode, FeePolicy, MAX_COMPUTE_UNIT_LIMIT};
+use crate::discovery::DiscoveryFilter;
+use crate::kamino::KaminoReserve;
+use crate::pools_struct::structs::{DexType, Pool};
+use crate::signer::{
+    default_allowed_programs, RemoteSignerConfig, SignerConfig, SignerTransport, SigningPolicy,
+};
+
+pub const CONFIG_FILE: &str = "arbitrage.toml";
+// Overrides the config file path
+pub const CONFIG_ENV: &str = "ARBITRAGE_CONFIG";
+// ARBITRAGE__FEES__COMPUTE_UNIT_PRICE=50000 overrides `fees.compute_unit_price`
+pub const ENV_OVERRIDE_PREFIX: &str = "ARBITRAGE__";
+
+/// `~/` is expanded like the Solana CLI does for its default keypair
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(relative), Some(home)) => PathBuf::from(home).join(relative),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// A value kept out of the config file: inline, or read from an env var or a file
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    Inline(String),
+    Env { env: String },
+    File { file: PathBuf },
+}
+
+impl Secret {
+    /// Resolve the value, errors name where it was looked up but never the value itself
+    pub fn resolve(&self) -> Result<String, anyhow::Error> {
+        self.resolve_with(|name| std::env::var(name).ok())
+    }
+
+    /// Resolve the value, reading env vars through `env`
+    pub fn resolve_with(
+        &self,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<String, anyhow::Error> {
+        match self {
+            Secret::Inline(value) => Ok(value.clone()),
+            Secret::Env { env: name } => {
+                env(name).ok_or_else(|| anyhow!("env var {} is not set", name))
+            }
+            Secret::File { file } => fs::read_to_string(expand_home(file))
+                .map(|value| value.trim().to_string())
+                .with_context(|| format!("can't read {}", file.display())),
+        }
+    }
+}
+
+/// Where the value comes from, never the value itself
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Inline(_) => write!(f, "inline value"),
+            Secret::Env { env } => write!(f, "env var {}", env),
+            Secret::File { file } => write!(f, "{}", file.display()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    rpc: RpcSection,
+    grpc: Option<GrpcSection>,
+    #[serde(default)]
+    feed: FeedSection,
+    wallet: WalletSection,
+    #[serde(default)]
+    pools: Vec<PoolSection>,
+    discovery: Option<DiscoverySection>,
+    #[serde(default)]
+    flash_loans: FlashLoanSection,
+    fees: FeePolicySection,
+    strategy: StrategySection,
+    #[serde(default)]
+    lookup_table: LookupTableSection,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RpcSection {
+    url: Secret,
+    /// Every pool is subscribed through each endpoint, the first update for a slot wins
+    ws: Vec<Secret>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GrpcSection {
+    endpoint: Secret,
+    x_token: Option<Secret>,
+    /// Programs to stream every account of, on top of the pools
+    #[serde(default)]
+    owners: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct FeedSection {
+    polling_interval_ms: u64,
+    stream_stale_after_ms: u64,
+    consistency_check_interval_secs: u64,
+}
+
+impl Default for FeedSection {
+    fn default() -> Self {
+        Self {
+            polling_interval_ms: 400,
+            stream_stale_after_ms: 5_000,
+            consistency_check_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WalletSection {
+    keypair: Option<Secret>,
+    /// Signing service holding the key, instead of a keypair on this host
+    remote: Option<RemoteSignerSection>,
+    #[serde(default)]
+    wrap_lamports: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteSignerSection {
+    url: Option<Secret>,
+    socket: Option<PathBuf>,
+    pubkey: String,
+    /// Programs the signer may be asked to sign for, every program the bot uses by default
+    allowed_programs: Option<Vec<String>>,
+    max_lamports: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PoolSection {
+    name: String,
+    address: String,
+    dex: DexType,
+    fee_rate: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiscoverySection {
+    dexes: Vec<DexType>,
+    mints: Vec<String>,
+    min_liquidity: u64,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct FlashLoanSection {
+    mode: ExecutionMode,
+    kamino_reserves: Vec<KaminoReserveSection>,
+    kamino_referrer: Option<String>,
+    marginfi_account: Option<String>,
+    marginfi_banks: Vec<String>,
+    save_reserves: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KaminoReserveSection {
+    lending_market: String,
+    reserve: String,
+    mint: String,
+    liquidity_supply: String,
+    fee_receiver: String,
+    token_program: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeePolicySection {
+    compute_unit_limit: u32,
+    compute_unit_price: u64,
+    tip: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrategySection {
+    loan_mint: String,
+    max_trade_size: u64,
+    max_slot_spread: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct LookupTableSection {
+    /// Printed by `lut create`
+    address: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct GrpcConfig {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    pub owners: Vec<Pubkey>,
+}
+
+/// Everything the bot runs with, validated and with secrets resolved. Not `Debug` since
+/// endpoint URLs may hold API keys
+#[derive(Clone)]
+pub struct Config {
+    pub rpc_url: String,
+    pub ws_endpoints: Vec<String>,
+    /// Streamed alongside the websockets when set
+    pub grpc: Option<GrpcConfig>,
+    pub signer: SignerConfig,
+    /// WSOL balance `doctor --fix` tops the wallet up to, nothing is wrapped when 0
+    pub wrap_lamports: u64,
+    pub pools: Vec<Pool>,
+    /// Pools trading any two of these mints are discovered on top of `pools`
+    pub discovery: Option<DiscoveryFilter>,
+    pub execution_mode: ExecutionMode,
+    /// Kamino reserves known up front, anything else is discovered from the lending market
+    pub kamino_reserves: Vec<KaminoReserve>,
+    /// Referrer credited with the flash loan fees, if any
+    pub kamino_referrer: Option<Pubkey>,
+    /// Our marginfi account, MarginFi flash loans are skipped without one
+    pub marginfi_account: Option<Pubkey>,
+    pub marginfi_banks: Vec<Pubkey>,
+    pub save_reserves: Vec<Pubkey>,
+    pub fee_policy: FeePolicy,
+    /// Always WSOL, so profit and lamport costs share a unit
+    pub loan_mint: Pubkey,
+    /// Most we trade in one transaction, whatever the flash loan providers could lend
+    pub max_trade_size: u64,
+    /// Skip routes whose pools were last known current more than this many slots apart
+    pub max_slot_spread: u64,
+    /// How often the polling fallback fetches every pool
+    pub polling_interval: Duration,
+    /// Poll once no stream has delivered anything for this long
+    pub stream_stale_after: Duration,
+    /// How often streamed state is compared with a fresh snapshot
+    pub consistency_check_interval: Duration,
+    /// Table our transactions compile against, none until `lut create` made one
+    pub lookup_table: Option<Pubkey>,
+}
+
+impl Config {
+    /// Load the file named by `ARBITRAGE_CONFIG`, `arbitrage.toml` by default
+    pub fn load() -> Result<Self, anyhow::Error> {
+        Self::from_file(&config_path())
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("can't read {}", path.display()))?;
+
+        Self::parse(&contents, std::env::vars())
+            .with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Parse `contents` with the `ARBITRAGE__` overrides found in `vars` applied on top,
+    /// secrets kept in env vars are read from `vars` too
+    pub fn parse(
+        contents: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, anyhow::Error> {
+        let vars = vars.into_iter().collect::<Vec<_>>();
+        let mut table = toml::from_str::<toml::Table>(contents)?;
+        apply_env_overrides(&mut table, vars.iter().cloned())?;
+
+        let file = ConfigFile::deserialize(table)?;
+        file.validate(&|name| {
+            vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value.clone())
+        })
+    }
+}
+
+/// File named by `ARBITRAGE_CONFIG`, `arbitrage.toml` by default
+pub fn config_path() -> PathBuf {
+    PathBuf::from(std::env::var(CONFIG_ENV).unwrap_or_else(|_| CONFIG_FILE.to_string()))
+}
+
+/// Set `lookup_table.address` in the config, keeping its comments and layout
+pub fn set_lookup_table_address(contents: &str, address: &Pubkey) -> Result<String, anyhow::Error> {
+    let mut document = contents.parse::<toml_edit::DocumentMut>()?;
+
+    let section = document
+        .entry("lookup_table")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("lookup_table is not a section"))?;
+    section.insert("address", toml_edit::value(address.to_string()));
+
+    Ok(document.to_string())
+}
+
+/// Save the lookup table `lut create` made into the config file at `path`
+pub fn save_lookup_table(path: &Path, address: &Pubkey) -> Result<(), anyhow::Error> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("can't read {}", path.display()))?;
+
+    fs::write(path, set_lookup_table_address(&contents, address)?)
+        .with_context(|| format!("can't write {}", path.display()))
+}
+
+/// Set `section.key` from every `ARBITRAGE__SECTION__KEY`, as a TOML value when it parses
+/// as one and as a string otherwise
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), anyhow::Error> {
+    for (name, value) in vars {
+        let Some(path) = name.strip_prefix(ENV_OVERRIDE_PREFIX) else {
+            continue;
+        };
+        let keys = path.split("__").map(str::to_lowercase).collect::<Vec<_>>();
+        let Some((last, sections)) = keys.split_last() else {
+            continue;
+        };
+
+        let mut current = &mut *table;
+        for section in sections {
+            current = current
+                .entry(section.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("{}: {} is not a section", name, section))?;
+        }
+
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or(toml::Value::String(value));
+        current.insert(last.clone(), value);
+    }
+
+    Ok(())
+}
+
+fn parse_pubkey(field: &str, value: &str) -> Result<Pubkey, anyhow::Error> {
+    Pubkey::from_str(value).map_err(|_| anyhow!("{}: {:?} is not a valid pubkey", field, value))
+}
+
+fn parse_pubkeys(field: &str, values: &[String]) -> Result<Vec<Pubkey>, anyhow::Error> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| parse_pubkey(&format!("{}[{}]", field, index), value))
+        .collect()
+}
+
+/// Check the scheme of an endpoint that may hold an API key, without echoing it
+fn check_url(field: &str, value: &str, schemes: &[&str]) -> Result<(), anyhow::Error> {
+    match url::Url::parse(value) {
+        Ok(url) if schemes.contains(&url.scheme()) => Ok(()),
+        _ => bail!("{}: expected a {} URL", field, schemes.join(" or ")),
+    }
+}
+
+fn resolve(
+    field: &str,
+    secret: &Secret,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, anyhow::Error> {
+    secret.resolve_with(env).with_context(|| field.to_string())
+}
+
+impl ConfigFile {
+    fn validate(self, env: &dyn Fn(&str) -> Option<String>) -> Result<Config, anyhow::Error> {
+        let rpc_url = resolve("rpc.url", &self.rpc.url, env)?;
+        check_url("rpc.url", &rpc_url, &["http", "https"])?;
+
+        let ws_endpoints = self
+            .rpc
+            .ws
+            .iter()
+            .enumerate()
+            .map(|(index, secret)| {
+                let field = format!("rpc.ws[{}]", index);
+                let url = resolve(&field, secret, env)?;
+                check_url(&field, &url, &["ws", "wss"])?;
+                Ok(url)
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        let grpc = self
+            .grpc
+            .map(|grpc| {
+                let endpoint = resolve("grpc.endpoint", &grpc.endpoint, env)?;
+                check_url("grpc.endpoint", &endpoint, &["http", "https"])?;
+                let x_token = grpc
+                    .x_token
+                    .map(|x_token| resolve("grpc.x_token", &x_token, env))
+                    .transpose()?;
+                let owners = parse_pubkeys("grpc.owners", &grpc.owners)?;
+                Ok::<_, anyhow::Error>(GrpcConfig {
+                    endpoint,
+                    x_token,
+                    owners,
+                })
+            })
+            .transpose()?;
+
+        if ws_endpoints.is_empty() && grpc.is_none() {
+            bail!("rpc.ws: at least one websocket or a grpc endpoint is needed");
+        }
+
+        let mut names = HashSet::new();
+        let mut addresses = HashSet::new();
+        let pools = self
+            .pools
+            .iter()
+            .enumerate()
+            .map(|(index, pool)| {
+                let field = format!("pools[{}]", index);
+                let pool_id = parse_pubkey(&format!("{}.address", field), &pool.address)?;
+                if !names.insert(pool.name.clone()) {
+                    bail!("{}.name: {:?} is used by another pool", field, pool.name);
+                }
+                if !addresses.insert(pool_id) {
+                    bail!("{}.address: {} is listed twice", field, pool_id);
+                }
+
+                Ok(Pool {
+                    name: pool.name.clone(),
+                    pool_id,
+                    pool: pool.dex,
+                    fee_rate: pool.fee_rate,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        let discovery = self
+            .discovery
+            .map(|discovery| {
+                let mints = parse_pubkeys("discovery.mints", &discovery.mints)?;
+                if mints.len() < 2 {
+                    bail!("discovery.mints: at least two mints are needed to form a pair");
+                }
+
+                Ok(DiscoveryFilter {
+                    dexes: discovery.dexes,
+                    mints,
+                    min_liquidity: discovery.min_liquidity as u128,
+                })
+            })
+            .transpose()?;
+
+        let flash_loans = self.flash_loans;
+        let kamino_reserves = flash_loans
+            .kamino_reserves
+            .iter()
+            .enumerate()
+            .map(|(index, reserve)| {
+                let field = |name: &str| format!("flash_loans.kamino_reserves[{}].{}", index, name);
+                Ok(KaminoReserve {
+                    lending_market: parse_pubkey(
+                        &field("lending_market"),
+                        &reserve.lending_market,
+                    )?,
+                    reserve: parse_pubkey(&field("reserve"), &reserve.reserve)?,
+                    mint: parse_pubkey(&field("mint"), &reserve.mint)?,
+                    liquidity_supply: parse_pubkey(
+                        &field("liquidity_supply"),
+                        &reserve.liquidity_supply,
+                    )?,
+                    fee_receiver: parse_pubkey(&field("fee_receiver"), &reserve.fee_receiver)?,
+                    token_program: parse_pubkey(&field("token_program"), &reserve.token_program)?,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let kamino_referrer = flash_loans
+            .kamino_referrer
+            .map(|referrer| parse_pubkey("flash_loans.kamino_referrer", &referrer))
+            .transpose()?;
+        let marginfi_account = flash_loans
+            .marginfi_account
+            .map(|account| parse_pubkey("flash_loans.marginfi_account", &account))
+            .transpose()?;
+        let marginfi_banks =
+            parse_pubkeys("flash_loans.marginfi_banks", &flash_loans.marginfi_banks)?;
+        let save_reserves = parse_pubkeys("flash_loans.save_reserves", &flash_loans.save_reserves)?;
+
+        if self.fees.compute_unit_limit == 0
+            || self.fees.compute_unit_limit > MAX_COMPUTE_UNIT_LIMIT
+        {
+            bail!(
+                "fees.compute_unit_limit: must be between 1 and {}",
+                MAX_COMPUTE_UNIT_LIMIT
+            );
+        }
+
+        let loan_mint = parse_pubkey("strategy.loan_mint", &self.strategy.loan_mint)?;
+        let lookup_table = self
+            .lookup_table
+            .address
+            .as_deref()
+            .map(|address| parse_pubkey("lookup_table.address", address))
+            .transpose()?;
+        if self.strategy.max_trade_size == 0 {
+            bail!("strategy.max_trade_size: must be positive");
+        }
+
+        let signer = match (self.wallet.keypair, self.wallet.remote) {
+            (Some(keypair), None) => SignerConfig::Local(keypair),
+            (None, Some(remote)) => {
+                let transport = match (remote.url, remote.socket) {
+                    (Some(url), None) => {
+                        let url = resolve("wallet.remote.url", &url, env)?;
+                        check_url("wallet.remote.url", &url, &["http", "https"])?;
+                        SignerTransport::Http(url::Url::parse(&url)?)
+                    }
+                    (None, Some(socket)) => SignerTransport::Unix(socket),
+                    _ => bail!("wallet.remote: set either url or socket"),
+                };
+                let allowed_programs = match remote.allowed_programs {
+                    Some(programs) => parse_pubkeys("wallet.remote.allowed_programs", &programs)?
+                        .into_iter()
+                        .collect(),
+                    None => default_allowed_programs(),
+                };
+                if remote.max_lamports == 0 {
+                    bail!("wallet.remote.max_lamports: must be positive");
+                }
+
+                SignerConfig::Remote(RemoteSignerConfig {
+                    transport,
+                    pubkey: parse_pubkey("wallet.remote.pubkey", &remote.pubkey)?,
+                    policy: SigningPolicy {
+                        allowed_programs,
+                        max_lamports: remote.max_lamports,
+                    },
+                })
+            }
+            _ => bail!("wallet: set either keypair or remote"),
+        };
+
+        if self.feed.polling_interval_ms == 0 {
+            bail!("feed.polling_interval_ms: must be positive");
+        }
+        if self.feed.stream_stale_after_ms == 0 {
+            bail!("feed.stream_stale_after_ms: must be positive");
+        }
+        if self.feed.consistency_check_interval_secs == 0 {
+            bail!("feed.consistency_check_interval_secs: must be positive");
+        }
+
+        Ok(Config {
+            rpc_url,
+            ws_endpoints,
+            grpc,
+            signer,
+            wrap_lamports: self.wallet.wrap_lamports,
+            pools,
+            discovery,
+            execution_mode: flash_loans.mode,
+            kamino_reserves,
+            kamino_referrer,
+            marginfi_account,
+            marginfi_banks,
+            save_reserves,
+            fee_policy: FeePolicy {
+                compute_unit_limit: self.fees.compute_unit_limit,
+                compute_unit_price: self.fees.compute_unit_price,
+                tip: self.fees.tip,
+            },
+            loan_mint,
+            max_trade_size: self.strategy.max_trade_size,
+            max_slot_spread: self.strategy.max_slot_spread,
+            polling_interval: Duration::from_millis(self.feed.polling_interval_ms),
+            stream_stale_after: Duration::from_millis(self.feed.stream_stale_after_ms),
+            consistency_check_interval: Duration::from_secs(
+                self.feed.consistency_check_interval_secs,
+            ),
+            lookup_table,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::wsol_mint;
+
+    const MINIMAL: &str = r#"
+        [rpc]
+        url = "https://rpc.example.com/?api-key=rpc-secret"
+        ws = ["wss://ws.example.com/?api-key=ws-secret"]
+
+        [wallet]
+        keypair = { file = "wallet.txt" }
+
+        [fees]
+        compute_unit_limit = 200000
+        compute_unit_price = 1000
+        tip = 0
+
+        [strategy]
+        loan_mint = "So11111111111111111111111111111111111111112"
+        max_trade_size = 1000000000
+        max_slot_spread = 2
+    "#;
+
+    fn parse(contents: &str, vars: &[(&str, &str)]) -> Result<Config, anyhow::Error> {
+        Config::parse(
+            contents,
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+    }
+
+    /// Full error chain, as `main` would print it
+    fn error(contents: &str, vars: &[(&str, &str)]) -> String {
+        format!("{:#}", parse(contents, vars).err().unwrap())
+    }
+
+    fn with_pools(pools: &[(&str, &str)]) -> String {
+        let mut contents = MINIMAL.to_string();
+        for (name, address) in pools {
+            contents.push_str(&format!(
+                "[[pools]]\nname = \"{}\"\naddress = \"{}\"\ndex = \"orca\"\n",
+                name, address
+            ));
+        }
+        contents
+    }
+
+    #[test]
+    fn parses_the_shipped_config() {
+        let config = parse(include_str!("../arbitrage.toml"), &[]).unwrap();
+
+        assert_eq!(config.loan_mint, wsol_mint());
+        assert!(!config.pools.is_empty());
+    }
+
+    #[test]
+    fn loan_mints_other_than_wsol_are_accepted() {
+        let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let config = parse(MINIMAL, &[("ARBITRAGE__STRATEGY__LOAN_MINT", usdc)]).unwrap();
+
+        assert_eq!(config.loan_mint, Pubkey::from_str(usdc).unwrap());
+    }
+
+    #[test]
+    fn overrides_are_typed_like_the_file() {
+        let config = parse(
+            MINIMAL,
+            &[
+                ("ARBITRAGE__FEES__COMPUTE_UNIT_PRICE", "50000"),
+                ("ARBITRAGE__FEED__STREAM_STALE_AFTER_MS", "250"),
+                ("ARBITRAGE__RPC__URL", "https://other.example.com"),
+                ("UNRELATED__FEES__TIP", "not a number"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.fee_policy.compute_unit_price, 50_000);
+        assert_eq!(config.stream_stale_after, Duration::from_millis(250));
+        assert_eq!(config.rpc_url, "https://other.example.com");
+
+        // A number where a string is expected stays a type error
+        assert!(parse(MINIMAL, &[("ARBITRAGE__STRATEGY__LOAN_MINT", "12")]).is_err());
+    }
+
+    #[test]
+    fn overrides_create_nested_sections() {
+        let lookup_table = Pubkey::new_unique();
+        let config = parse(
+            MINIMAL,
+            &[(
+                "ARBITRAGE__LOOKUP_TABLE__ADDRESS",
+                lookup_table.to_string().as_str(),
+            )],
+        )
+        .unwrap();
+        assert_eq!(config.lookup_table, Some(lookup_table));
+
+        let err = error(MINIMAL, &[("ARBITRAGE__FEES__TIP__LAMPORTS", "1")]);
+        assert!(err.contains("tip is not a section"), "{}", err);
+    }
+
+    #[test]
+    fn secrets_resolve_from_env_and_files() {
+        let token_file = std::env::temp_dir().join(format!(
+            "arbitrage-config-test-{}.token",
+            std::process::id()
+        ));
+        fs::write(&token_file, "x-token-value\n").unwrap();
+        let vars = [("ARBITRAGE_CONFIG_TEST_GRPC", "https://grpc.example.com:443")];
+
+        let contents = format!(
+            "{}\n[grpc]\nendpoint = {{ env = \"ARBITRAGE_CONFIG_TEST_GRPC\" }}\nx_token = {{ file = {:?} }}\n",
+            MINIMAL, token_file
+        );
+        let grpc = parse(&contents, &vars).unwrap().grpc.unwrap();
+        assert_eq!(grpc.endpoint, "https://grpc.example.com:443");
+        assert_eq!(grpc.x_token.as_deref(), Some("x-token-value"));
+        fs::remove_file(&token_file).unwrap();
+
+        let err = error(&contents, &vars);
+        assert!(err.contains("grpc.x_token"), "{}", err);
+
+        let missing = MINIMAL.replace(
+            "url = \"https://rpc.example.com/?api-key=rpc-secret\"",
+            "url = { env = \"ARBITRAGE_CONFIG_TEST_UNSET\" }",
+        );
+        let err = error(&missing, &[]);
+        assert!(
+            err.contains("ARBITRAGE_CONFIG_TEST_UNSET is not set"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn duplicate_pools_are_rejected() {
+        let address = Pubkey::new_unique().to_string();
+        let other = Pubkey::new_unique().to_string();
+
+        assert_eq!(
+            parse(&with_pools(&[("a", &address), ("b", &other)]), &[])
+                .unwrap()
+                .pools
+                .len(),
+            2
+        );
+        let err = error(&with_pools(&[("a", &address), ("a", &other)]), &[]);
+        assert!(err.contains("pools[1].name"), "{}", err);
+        let err = error(&with_pools(&[("a", &address), ("b", &address)]), &[]);
+        assert!(err.contains("pools[1].address"), "{}", err);
+    }
+
+    #[test]
+    fn bad_urls_are_rejected_without_echoing_them() {
+        for (name, value, field) in [
+            (
+                "ARBITRAGE__RPC__URL",
+                "ftp://rpc.example.com/?api-key=leaked",
+                "rpc.url",
+            ),
+            ("ARBITRAGE__RPC__URL", "not a url api-key=leaked", "rpc.url"),
+            (
+                "ARBITRAGE__RPC__WS",
+                "[\"https://ws.example.com/?api-key=leaked\"]",
+                "rpc.ws[0]",
+            ),
+        ] {
+            let err = error(MINIMAL, &[(name, value)]);
+            assert!(err.contains(field), "{}", err);
+            assert!(!err.contains("leaked"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn zero_intervals_are_rejected() {
+        for field in [
+            "POLLING_INTERVAL_MS",
+            "STREAM_STALE_AFTER_MS",
+            "CONSISTENCY_CHECK_INTERVAL_SECS",
+        ] {
+            let err = error(MINIMAL, &[(&format!("ARBITRAGE__FEED__{}", field), "0")]);
+            assert!(err.contains(&field.to_lowercase()), "{}", err);
+        }
+    }
+
+    #[test]
+    fn lookup_table_address_is_saved_keeping_the_file() {
+        let address = Pubkey::new_unique();
+        let shipped = include_str!("../arbitrage.toml");
+
+        let saved = set_lookup_table_address(shipped, &address).unwrap();
+        assert_eq!(parse(&saved, &[]).unwrap().lookup_table, Some(address));
+        // Only the address line is new, comments and the rest stay as they were
+        let added = saved
+            .lines()
+            .filter(|line| !shipped.lines().any(|shipped| shipped == *line))
+            .collect::<Vec<_>>();
+        assert_eq!(added, [format!("address = \"{}\"", address)]);
+
+        // Replaced when set, the section is added when missing
+        let replaced = set_lookup_table_address(&saved, &address).unwrap();
+        assert_eq!(replaced, saved);
+        let added = set_lookup_table_address(MINIMAL, &address).unwrap();
+        assert_eq!(parse(&added, &[]).unwrap().lookup_table, Some(address));
+    }
+}
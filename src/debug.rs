@@ -1,179 +1,1084 @@
-use solana_client::rpc_client::RpcClient;
-use spl_token::state::Account as TokenAccount;
-
-pub fn diagnose_wsol_ata(
-    rpc_client: &RpcClient,
-    wallet_pubkey: &Pubkey,
-    ata_address: &Pubkey,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("=== WSOL ATA DIAGNOSTIC ===");
-    println!("Wallet: {}", wallet_pubkey);
-    println!("ATA: {}", ata_address);
-
-    // 1. Fetch account info
-    let account_info = rpc_client.get_account(ata_address)?;
-    println!("✓ Account exists");
-    println!("  Owner: {}", account_info.owner);
-    println!("  Lamports: {}", account_info.lamports);
-    println!("  Data length: {}", account_info.data.len());
-
-    // 2. Verify Token Program ownership
-    if account_info.owner != Pubkey::new_from_array(spl_token::ID.to_bytes()) {
-        println!(
-            "✗ WRONG OWNER - Expected: {}, Got: {}",
-            spl_token::ID,
-            account_info.owner
-        );
-        return Err("Wrong owner".into());
-    }
-    println!("✓ Owned by Token Program");
-
-    // 3. Deserialize token account data
-    let token_account = TokenAccount::unpack(&account_info.data)?;
-    println!("✓ Successfully deserialized as token account");
-
-    // 4. Check critical fields
-    println!("=== TOKEN ACCOUNT DETAILS ===");
-    println!("Mint: {}", token_account.mint);
-    println!("Owner: {}", token_account.owner);
-    println!("Amount: {}", token_account.amount);
-    println!("Delegate: {:?}", token_account.delegate);
-    println!("State: {:?}", token_account.state);
-    println!("Is Native: {:?}", token_account.is_native);
-    println!("Delegated Amount: {}", token_account.delegated_amount);
-    println!("Close Authority: {:?}", token_account.close_authority);
-
-    // 5. CRITICAL CHECKS for flash loans
-    let wsol_mint = Pubkey::from_str("So11111111111111111111111111111111111111112")?;
-
-    // Check mint
-    if token_account.mint
-        != spl_token::solana_program::pubkey::Pubkey::new_from_array(wsol_mint.to_bytes())
+use futures_util::StreamExt;
+use solana_address_lookup_table_interface::state::AddressLookupTable;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::account::Account;
+use solana_sdk::message::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::instruction::close_account;
+use spl_token::solana_program::program_option::COption;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::{Account as TokenAccount, AccountState};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::geyser::CommitmentLevel;
+
+use crate::assembler::{Funding, LAMPORTS_PER_SIGNATURE};
+use crate::client::BotRpcClient;
+use crate::config::{Config, GrpcConfig};
+use crate::feed::endpoint_label;
+use crate::flash_providers::marginfi::bank_mint;
+use crate::flash_providers::save::SaveReserveState;
+use crate::kamino::ReserveState;
+use crate::utils::{
+    from_program_instruction, parse_account_data, program_pubkey, wrap_sol, wsol_mint,
+};
+
+// Warn when the payer can't cover this many transactions
+const MIN_FUNDED_TRANSACTIONS: u64 = 100;
+// Give up on an endpoint after this long
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Outcome of every preflight check, in the order they ran
+#[derive(Debug, Default)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+
+    /// Nothing failed, warnings don't stop the bot
+    pub fn is_healthy(&self) -> bool {
+        self.count(CheckStatus::Fail) == 0
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAIL",
+            };
+            writeln!(f, "[{}] {}: {}", status, check.name, check.detail)?;
+        }
+
+        write!(
+            f,
+            "{} passed, {} warnings, {} failed",
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail)
+        )
+    }
+}
+
+/// What the wallet's token account for a mint looks like
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenAccountStatus {
+    Ready {
+        amount: u64,
+    },
+    Missing,
+    /// A WSOL account that isn't native, its balance doesn't follow lamports
+    NotNative {
+        amount: u64,
+    },
+    Invalid(String),
+}
+
+/// Token account of `wallet` for `mint`, expected at the ATA of `token_program`
+#[derive(Debug, Clone)]
+pub struct RequiredTokenAccount {
+    pub mint: Pubkey,
+    pub token_program: Pubkey,
+    pub address: Pubkey,
+    pub status: TokenAccountStatus,
+}
+
+pub fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    let address = spl_associated_token_account::get_associated_token_address_with_program_id(
+        &program_pubkey(wallet),
+        &program_pubkey(mint),
+        &program_pubkey(token_program),
+    );
+
+    Pubkey::new_from_array(address.to_bytes())
+}
+
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == wsol_mint()
+}
+
+/// Check the wallet's token account for a mint, WSOL has to be a native account
+pub fn inspect_token_account(
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    account: Option<&Account>,
+) -> TokenAccountStatus {
+    let Some(account) = account else {
+        return TokenAccountStatus::Missing;
+    };
+    if account.owner != *token_program {
+        return TokenAccountStatus::Invalid(format!(
+            "owned by {}, not {}",
+            account.owner, token_program
+        ));
+    }
+
+    // Token-2022 accounts carry extensions after the base layout
+    let Some(token_account) = account
+        .data
+        .get(..TokenAccount::LEN)
+        .and_then(|data| TokenAccount::unpack_from_slice(data).ok())
+    else {
+        return TokenAccountStatus::Invalid("not a token account".to_string());
+    };
+
+    if token_account.mint != program_pubkey(mint) {
+        return TokenAccountStatus::Invalid(format!("holds {}", token_account.mint));
+    }
+    if token_account.owner != program_pubkey(wallet) {
+        return TokenAccountStatus::Invalid(format!("belongs to {}", token_account.owner));
+    }
+    if token_account.state != AccountState::Initialized {
+        return TokenAccountStatus::Invalid(format!("{:?}", token_account.state));
+    }
+    if is_native_mint(mint) && token_account.is_native == COption::None {
+        return TokenAccountStatus::NotNative {
+            amount: token_account.amount,
+        };
+    }
+
+    TokenAccountStatus::Ready {
+        amount: token_account.amount,
+    }
+}
+
+/// Loan mint, the mints of every flash loan reserve and bank and of every configured
+/// pool, with their token program
+pub fn required_mints(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+) -> Result<Vec<(Pubkey, Pubkey)>, anyhow::Error> {
+    let mut mints = vec![config.loan_mint];
+    mints.extend(config.kamino_reserves.iter().map(|reserve| reserve.mint));
+
+    let save_reserves = rpc_client
+        .connection
+        .get_multiple_accounts(&config.save_reserves)?;
+    mints.extend(
+        save_reserves
+            .into_iter()
+            .flatten()
+            .filter_map(|account| SaveReserveState::decode(&account).ok())
+            .map(|state| state.liquidity_mint),
+    );
+    let marginfi_banks = rpc_client
+        .connection
+        .get_multiple_accounts(&config.marginfi_banks)?;
+    mints.extend(
+        marginfi_banks
+            .into_iter()
+            .flatten()
+            .filter_map(|account| bank_mint(&account.data)),
+    );
+
+    let pool_ids = config
+        .pools
+        .iter()
+        .map(|pool| pool.pool_id)
+        .collect::<Vec<_>>();
+    let pool_accounts = rpc_client.connection.get_multiple_accounts(&pool_ids)?;
+    for (pool, account) in config.pools.iter().zip(pool_accounts) {
+        let Some(account) = account else {
+            continue;
+        };
+        if let Some((mint_a, mint_b)) = parse_account_data(&account.data, pool.pool)
+            .ok()
+            .and_then(|state| state.get_mints())
+        {
+            mints.extend([mint_a, mint_b]);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    mints.retain(|mint| seen.insert(*mint));
+
+    let mint_accounts = rpc_client.connection.get_multiple_accounts(&mints)?;
+    Ok(mints
+        .into_iter()
+        .zip(mint_accounts)
+        .map(|(mint, account)| {
+            let token_program = account.map_or(
+                Pubkey::new_from_array(spl_token::ID.to_bytes()),
+                |account| account.owner,
+            );
+            (mint, token_program)
+        })
+        .collect())
+}
+
+/// Wallet token accounts for every required mint and what state they're in
+pub fn required_token_accounts(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) -> Result<Vec<RequiredTokenAccount>, anyhow::Error> {
+    let mints = required_mints(config, rpc_client)?;
+    let addresses = mints
+        .iter()
+        .map(|(mint, token_program)| associated_token_address(wallet, mint, token_program))
+        .collect::<Vec<_>>();
+    let accounts = rpc_client.connection.get_multiple_accounts(&addresses)?;
+
+    Ok(mints
+        .into_iter()
+        .zip(addresses)
+        .zip(accounts)
+        .map(
+            |(((mint, token_program), address), account)| RequiredTokenAccount {
+                mint,
+                token_program,
+                address,
+                status: inspect_token_account(wallet, &mint, &token_program, account.as_ref()),
+            },
+        )
+        .collect())
+}
+
+/// Check everything the bot needs before trading: endpoints, payer balance, token
+/// accounts, pools, the lookup table and Kamino reserves
+pub async fn run_doctor(
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) -> DoctorReport {
+    let mut report = DoctorReport::default();
+
+    check_rpc(&mut report, config, rpc_client);
+    for url in &config.ws_endpoints {
+        check_websocket(&mut report, url).await;
+    }
+    if let Some(grpc) = &config.grpc {
+        check_grpc(&mut report, grpc).await;
+    }
+    check_payer_balance(&mut report, config, rpc_client, wallet);
+    check_token_accounts(&mut report, config, rpc_client, wallet);
+    check_pools(&mut report, config, rpc_client);
+    check_lookup_table(&mut report, config, rpc_client, wallet);
+    check_kamino_reserves(&mut report, config, rpc_client);
+
+    report
+}
+
+fn check_rpc(report: &mut DoctorReport, config: &Config, rpc_client: &BotRpcClient) {
+    let name = format!("RPC {}", endpoint_label(&config.rpc_url));
+    let started = Instant::now();
+
+    match rpc_client
+        .connection
+        .get_slot_with_commitment(CommitmentConfig::confirmed())
     {
-        println!(
-            "✗ WRONG MINT - Expected WSOL: {}, Got: {}",
-            wsol_mint, token_account.mint
-        );
-        return Err("Wrong mint".into());
+        Ok(slot) => report.push(
+            name,
+            CheckStatus::Pass,
+            format!("slot {} in {:?}", slot, started.elapsed()),
+        ),
+        Err(err) => report.push(name, CheckStatus::Fail, err.to_string()),
+    }
+}
+
+/// Time to connect and receive the first slot notification
+async fn check_websocket(report: &mut DoctorReport, url: &str) {
+    let name = format!("Websocket {}", endpoint_label(url));
+    let started = Instant::now();
+
+    let first_slot = tokio::time::timeout(ENDPOINT_TIMEOUT, async {
+        let client = PubsubClient::new(url).await?;
+        let (mut slots, unsubscribe) = client.slot_subscribe().await?;
+        let slot = slots.next().await.map(|info| info.slot);
+        drop(slots);
+        unsubscribe().await;
+
+        Ok::<_, anyhow::Error>(slot)
+    })
+    .await;
+
+    match first_slot {
+        Ok(Ok(Some(slot))) => report.push(
+            name,
+            CheckStatus::Pass,
+            format!("slot {} in {:?}", slot, started.elapsed()),
+        ),
+        Ok(Ok(None)) => report.push(name, CheckStatus::Fail, "subscription closed"),
+        Ok(Err(err)) => report.push(name, CheckStatus::Fail, err.to_string()),
+        Err(_) => report.push(
+            name,
+            CheckStatus::Fail,
+            format!("no slot within {:?}", ENDPOINT_TIMEOUT),
+        ),
+    }
+}
+
+/// Time to connect and read the confirmed slot
+async fn check_grpc(report: &mut DoctorReport, grpc: &GrpcConfig) {
+    let name = format!("gRPC {}", endpoint_label(&grpc.endpoint));
+    let started = Instant::now();
+
+    let slot = tokio::time::timeout(ENDPOINT_TIMEOUT, async {
+        let mut client = GeyserGrpcClient::build_from_shared(grpc.endpoint.clone())?
+            .x_token(grpc.x_token.clone())?
+            .connect()
+            .await?;
+        let response = client.get_slot(Some(CommitmentLevel::Confirmed)).await?;
+
+        Ok::<_, anyhow::Error>(response.slot)
+    })
+    .await;
+
+    match slot {
+        Ok(Ok(slot)) => report.push(
+            name,
+            CheckStatus::Pass,
+            format!("slot {} in {:?}", slot, started.elapsed()),
+        ),
+        Ok(Err(err)) => report.push(name, CheckStatus::Fail, err.to_string()),
+        Err(_) => report.push(
+            name,
+            CheckStatus::Fail,
+            format!("no slot within {:?}", ENDPOINT_TIMEOUT),
+        ),
+    }
+}
+
+fn check_payer_balance(
+    report: &mut DoctorReport,
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) {
+    let name = format!("Payer {}", wallet);
+    let transaction_cost =
+        Funding::Inventory.lamport_cost(&config.fee_policy) + LAMPORTS_PER_SIGNATURE;
+
+    match rpc_client.connection.get_balance(wallet) {
+        Ok(balance) => {
+            let funded = balance / transaction_cost.max(1);
+            let status = if funded == 0 {
+                CheckStatus::Fail
+            } else if funded < MIN_FUNDED_TRANSACTIONS {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Pass
+            };
+            report.push(
+                name,
+                status,
+                format!(
+                    "{} lamports, enough for {} transactions at {} each",
+                    balance, funded, transaction_cost
+                ),
+            );
+        }
+        Err(err) => report.push(name, CheckStatus::Fail, err.to_string()),
+    }
+}
+
+fn check_token_accounts(
+    report: &mut DoctorReport,
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) {
+    let accounts = match required_token_accounts(config, rpc_client, wallet) {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            report.push("Token accounts", CheckStatus::Fail, err.to_string());
+            return;
+        }
+    };
+
+    for account in accounts {
+        let name = format!("Token account {} ({})", account.address, account.mint);
+        match account.status {
+            TokenAccountStatus::Ready { amount: 0 } if is_native_mint(&account.mint) => report
+                .push(
+                    name,
+                    CheckStatus::Warn,
+                    "native WSOL account with no balance",
+                ),
+            TokenAccountStatus::Ready { amount } => {
+                report.push(name, CheckStatus::Pass, format!("balance {}", amount))
+            }
+            TokenAccountStatus::Missing => report.push(
+                name,
+                CheckStatus::Fail,
+                format!("no {} account", account.token_program),
+            ),
+            TokenAccountStatus::NotNative { .. } => report.push(
+                name,
+                CheckStatus::Fail,
+                "WSOL account isn't native, wrapped SOL won't show up in it",
+            ),
+            TokenAccountStatus::Invalid(reason) => report.push(name, CheckStatus::Fail, reason),
+        }
+    }
+}
+
+fn check_pools(report: &mut DoctorReport, config: &Config, rpc_client: &BotRpcClient) {
+    let pool_ids = config
+        .pools
+        .iter()
+        .map(|pool| pool.pool_id)
+        .collect::<Vec<_>>();
+    let accounts = match rpc_client.connection.get_multiple_accounts(&pool_ids) {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            report.push("Pools", CheckStatus::Fail, err.to_string());
+            return;
+        }
+    };
+
+    for (pool, account) in config.pools.iter().zip(accounts) {
+        let name = format!("Pool {} ({})", pool.name, pool.pool_id);
+        let program_id = pool.pool.program_id();
+
+        match account {
+            None => report.push(name, CheckStatus::Fail, "doesn't exist"),
+            Some(account) if account.owner != program_id => report.push(
+                name,
+                CheckStatus::Fail,
+                format!(
+                    "owned by {}, not {:?} {}",
+                    account.owner, pool.pool, program_id
+                ),
+            ),
+            Some(account) => match parse_account_data(&account.data, pool.pool) {
+                Ok(_) => report.push(name, CheckStatus::Pass, format!("{:?} pool", pool.pool)),
+                Err(err) => report.push(name, CheckStatus::Fail, format!("can't decode: {}", err)),
+            },
+        }
     }
-    println!("✓ Correct WSOL mint");
+}
+
+fn check_lookup_table(
+    report: &mut DoctorReport,
+    config: &Config,
+    rpc_client: &BotRpcClient,
+    wallet: &Pubkey,
+) {
+    let Some(lookup_table) = config.lookup_table else {
+        report.push(
+            "Lookup table",
+            CheckStatus::Warn,
+            "lookup_table.address isn't set, transactions won't be compressed",
+        );
+        return;
+    };
+    let name = format!("Lookup table {}", lookup_table);
 
-    // Check owner
-    if token_account.owner
-        != spl_token::solana_program::pubkey::Pubkey::new_from_array(wallet_pubkey.to_bytes())
+    let (account, slot) = match rpc_client
+        .connection
+        .get_account(&lookup_table)
+        .and_then(|account| Ok((account, rpc_client.connection.get_slot()?)))
     {
-        println!(
-            "✗ WRONG TOKEN ACCOUNT OWNER - Expected: {}, Got: {}",
-            wallet_pubkey, token_account.owner
+        Ok(found) => found,
+        Err(err) => {
+            report.push(name, CheckStatus::Fail, err.to_string());
+            return;
+        }
+    };
+    let table = match AddressLookupTable::deserialize(&account.data) {
+        Ok(table) => table,
+        Err(err) => {
+            report.push(name, CheckStatus::Fail, format!("can't decode: {}", err));
+            return;
+        }
+    };
+
+    if table.meta.deactivation_slot != u64::MAX {
+        report.push(
+            name,
+            CheckStatus::Fail,
+            format!("deactivated at slot {}", table.meta.deactivation_slot),
+        );
+    } else if table.meta.last_extended_slot >= slot {
+        report.push(
+            name,
+            CheckStatus::Warn,
+            format!(
+                "extended at slot {}, new addresses not active yet",
+                table.meta.last_extended_slot
+            ),
+        );
+    } else if table.meta.authority != Some(*wallet) {
+        report.push(
+            name,
+            CheckStatus::Warn,
+            format!(
+                "{} addresses, authority {:?} isn't the payer so it can't be extended",
+                table.addresses.len(),
+                table.meta.authority
+            ),
+        );
+    } else {
+        report.push(
+            name,
+            CheckStatus::Pass,
+            format!("active with {} addresses", table.addresses.len()),
         );
-        return Err("Wrong token account owner".into());
     }
-    println!("✓ Correct owner");
+}
 
-    // Check state
-    if token_account.state != spl_token::state::AccountState::Initialized {
-        println!(
-            "✗ ACCOUNT NOT INITIALIZED - State: {:?}",
-            token_account.state
+fn check_kamino_reserves(report: &mut DoctorReport, config: &Config, rpc_client: &BotRpcClient) {
+    if !config
+        .kamino_reserves
+        .iter()
+        .any(|reserve| reserve.mint == config.loan_mint)
+    {
+        report.push(
+            "Kamino reserves",
+            CheckStatus::Warn,
+            format!(
+                "none configured for {}, it's discovered from the lending market at startup",
+                config.loan_mint
+            ),
         );
-        return Err("Account not initialized".into());
     }
-    println!("✓ Account initialized");
 
-    // Check if it's native (this is crucial for WSOL)
-    match token_account.is_native {
-        spl_token::solana_program::program_option::COption::Some(lamports) => {
-            println!("✓ Native WSOL account with {} lamports", lamports);
-            if lamports == 0 {
-                println!("⚠ WARNING: Native account has 0 lamports - this might cause issues");
+    let addresses = config
+        .kamino_reserves
+        .iter()
+        .map(|reserve| reserve.reserve)
+        .collect::<Vec<_>>();
+    let accounts = match rpc_client.connection.get_multiple_accounts(&addresses) {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            report.push("Kamino reserves", CheckStatus::Fail, err.to_string());
+            return;
+        }
+    };
+
+    for (reserve, account) in config.kamino_reserves.iter().zip(accounts) {
+        let name = format!("Kamino reserve {} ({})", reserve.reserve, reserve.mint);
+        let state = match account.map(|account| ReserveState::decode(&account.data)) {
+            None => {
+                report.push(name, CheckStatus::Fail, "doesn't exist");
+                continue;
+            }
+            Some(Err(err)) => {
+                report.push(name, CheckStatus::Fail, err.to_string());
+                continue;
             }
+            Some(Ok(state)) => state,
+        };
+
+        if state.liquidity.mint_pubkey != reserve.mint {
+            report.push(
+                name,
+                CheckStatus::Fail,
+                format!("lends {}", state.liquidity.mint_pubkey),
+            );
+        } else if !state.flash_loans_enabled() {
+            report.push(name, CheckStatus::Fail, "flash loans disabled");
+        } else if state.max_flash_loan() == 0 {
+            report.push(name, CheckStatus::Warn, "no liquidity to borrow");
+        } else {
+            report.push(
+                name,
+                CheckStatus::Pass,
+                format!(
+                    "{} available, fee {} on 1e9",
+                    state.max_flash_loan(),
+                    state.flash_loan_fee(1_000_000_000).unwrap_or_default()
+                ),
+            );
         }
-        spl_token::solana_program::program_option::COption::None => {
-            println!("✗ NOT A NATIVE WSOL ACCOUNT - This is likely the problem!");
-            println!("  This account exists but isn't properly wrapped SOL");
-            println!("  You need to wrap some SOL first");
-            return Err("Not a native WSOL account".into());
+    }
+}
+
+// A created token account brings two new keys, 8 keep a setup transaction well under the
+// packet limit without a lookup table
+pub const SETUP_CHUNK_SIZE: usize = 8;
+
+/// One remediation and the instructions that carry it out
+pub struct FixStep {
+    pub description: String,
+    pub instructions: Vec<Instruction>,
+}
+
+/// How `doctor --fix` repairs the wallet's token accounts. An account has to be closed in
+/// an earlier transaction than the one recreating it at the same address
+#[derive(Default)]
+pub struct FixPlan {
+    pub close: Vec<FixStep>,
+    pub setup: Vec<FixStep>,
+    /// Problems that need a human
+    pub unfixable: Vec<String>,
+}
+
+impl FixPlan {
+    pub fn is_empty(&self) -> bool {
+        self.close.is_empty() && self.setup.is_empty()
+    }
+
+    /// Labelled steps of each transaction, in the order they have to land
+    pub fn transactions(&self) -> Vec<(String, &[FixStep])> {
+        let setup = self.setup.chunks(SETUP_CHUNK_SIZE).collect::<Vec<_>>();
+        let mut transactions = Vec::new();
+
+        if !self.close.is_empty() {
+            transactions.push(("Close".to_string(), self.close.as_slice()));
+        }
+        for (index, steps) in setup.iter().enumerate() {
+            let label = if setup.len() == 1 {
+                "Setup".to_string()
+            } else {
+                format!("Setup {}/{}", index + 1, setup.len())
+            };
+            transactions.push((label, *steps));
+        }
+        transactions
+    }
+}
+
+fn create_token_account_step(wallet: &Pubkey, account: &RequiredTokenAccount) -> FixStep {
+    FixStep {
+        description: format!("Create {} for {}", account.address, account.mint),
+        instructions: vec![from_program_instruction(
+            create_associated_token_account_idempotent(
+                &program_pubkey(wallet),
+                &program_pubkey(wallet),
+                &program_pubkey(&account.mint),
+                &program_pubkey(&account.token_program),
+            ),
+        )],
+    }
+}
+
+/// Create missing token accounts, recreate an empty non-native WSOL account and top the
+/// WSOL balance up to `wrap_lamports`
+pub fn plan_fixes(
+    accounts: &[RequiredTokenAccount],
+    wallet: &Pubkey,
+    wrap_lamports: u64,
+) -> Result<FixPlan, anyhow::Error> {
+    let mut plan = FixPlan::default();
+    let mut wsol_balance = None;
+
+    for account in accounts {
+        match &account.status {
+            TokenAccountStatus::Ready { amount } => {
+                if is_native_mint(&account.mint) {
+                    wsol_balance = Some(*amount);
+                }
+            }
+            TokenAccountStatus::Missing => {
+                plan.setup.push(create_token_account_step(wallet, account));
+                if is_native_mint(&account.mint) {
+                    wsol_balance = Some(0);
+                }
+            }
+            TokenAccountStatus::NotNative { amount: 0 } => {
+                plan.close.push(FixStep {
+                    description: format!("Close non-native WSOL account {}", account.address),
+                    instructions: vec![from_program_instruction(close_account(
+                        &program_pubkey(&account.token_program),
+                        &program_pubkey(&account.address),
+                        &program_pubkey(wallet),
+                        &program_pubkey(wallet),
+                        &[],
+                    )?)],
+                });
+                plan.setup.push(create_token_account_step(wallet, account));
+                wsol_balance = Some(0);
+            }
+            TokenAccountStatus::NotNative { amount } => plan.unfixable.push(format!(
+                "{} holds {} non-native WSOL, move it out before it can be closed",
+                account.address, amount
+            )),
+            TokenAccountStatus::Invalid(reason) => plan.unfixable.push(format!(
+                "{} ({}): {}",
+                account.address, account.mint, reason
+            )),
         }
     }
 
-    // Check delegate
-    if token_account.delegate.is_some() {
-        println!(
-            "⚠ WARNING: Account has delegate: {:?}",
-            token_account.delegate
+    if wrap_lamports > 0 {
+        let token_program = Pubkey::new_from_array(spl_token::ID.to_bytes());
+        let address = associated_token_address(wallet, &wsol_mint(), &token_program);
+
+        // Not one of the required mints, it still needs an account to wrap into
+        if !accounts.iter().any(|account| account.address == address) {
+            plan.setup.push(create_token_account_step(
+                wallet,
+                &RequiredTokenAccount {
+                    mint: wsol_mint(),
+                    token_program,
+                    address,
+                    status: TokenAccountStatus::Missing,
+                },
+            ));
+            wsol_balance = Some(0);
+        }
+
+        match wsol_balance {
+            Some(balance) if balance < wrap_lamports => plan.setup.push(FixStep {
+                description: format!("Wrap {} lamports into {}", wrap_lamports - balance, address),
+                instructions: wrap_sol(wallet, &address, wrap_lamports - balance)?,
+            }),
+            Some(_) => {}
+            None => plan
+                .unfixable
+                .push(format!("Can't wrap SOL until {} is fixed", address)),
+        }
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TOKEN_2022_PROGRAM_ID;
+
+    fn token_program() -> Pubkey {
+        Pubkey::new_from_array(spl_token::ID.to_bytes())
+    }
+
+    /// Initialized account of `owner` for `mint`, native when `rent` is set
+    fn token_account(
+        owner: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        rent: Option<u64>,
+        program: Pubkey,
+    ) -> Account {
+        let mut data = vec![0u8; TokenAccount::LEN];
+        TokenAccount {
+            mint: program_pubkey(mint),
+            owner: program_pubkey(owner),
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: rent.map_or(COption::None, COption::Some),
+            delegated_amount: 0,
+            close_authority: COption::None,
+        }
+        .pack_into_slice(&mut data);
+
+        Account {
+            lamports: 2_039_280 + amount,
+            data,
+            owner: program,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn token_accounts_are_inspected() {
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let frozen = {
+            let mut account = token_account(&wallet, &mint, 5, None, token_program());
+            let mut state = TokenAccount::unpack_from_slice(&account.data).unwrap();
+            state.state = AccountState::Frozen;
+            state.pack_into_slice(&mut account.data);
+            account
+        };
+        // Token-2022 accounts carry their account type and extensions after the base layout
+        let with_extensions = {
+            let mut account = token_account(&wallet, &mint, 7, None, TOKEN_2022_PROGRAM_ID);
+            account.data.extend([2, 7, 0, 0, 0]);
+            account
+        };
+        let truncated = {
+            let mut account = token_account(&wallet, &mint, 1, None, token_program());
+            account.data.truncate(100);
+            account
+        };
+
+        let cases = [
+            (mint, token_program(), None, "missing"),
+            (
+                mint,
+                token_program(),
+                Some(token_account(&wallet, &mint, 5, None, token_program())),
+                "ready 5",
+            ),
+            (
+                mint,
+                TOKEN_2022_PROGRAM_ID,
+                Some(token_account(&wallet, &mint, 5, None, token_program())),
+                "invalid owned by",
+            ),
+            (
+                mint,
+                token_program(),
+                Some(token_account(&wallet, &other, 5, None, token_program())),
+                "invalid holds",
+            ),
+            (
+                mint,
+                token_program(),
+                Some(token_account(&other, &mint, 5, None, token_program())),
+                "invalid belongs to",
+            ),
+            (mint, token_program(), Some(frozen), "invalid Frozen"),
+            (
+                mint,
+                token_program(),
+                Some(truncated),
+                "invalid not a token account",
+            ),
+            (
+                wsol_mint(),
+                token_program(),
+                Some(token_account(
+                    &wallet,
+                    &wsol_mint(),
+                    9,
+                    None,
+                    token_program(),
+                )),
+                "not native 9",
+            ),
+            (
+                wsol_mint(),
+                token_program(),
+                Some(token_account(
+                    &wallet,
+                    &wsol_mint(),
+                    9,
+                    Some(2_039_280),
+                    token_program(),
+                )),
+                "ready 9",
+            ),
+            (
+                mint,
+                TOKEN_2022_PROGRAM_ID,
+                Some(with_extensions),
+                "ready 7",
+            ),
+        ];
+
+        for (mint, program, account, expected) in cases {
+            let status = match inspect_token_account(&wallet, &mint, &program, account.as_ref()) {
+                TokenAccountStatus::Missing => "missing".to_string(),
+                TokenAccountStatus::Ready { amount } => format!("ready {}", amount),
+                TokenAccountStatus::NotNative { amount } => format!("not native {}", amount),
+                TokenAccountStatus::Invalid(reason) => format!("invalid {}", reason),
+            };
+            assert!(
+                status.starts_with(expected),
+                "{} instead of {}",
+                status,
+                expected
+            );
+        }
+    }
+
+    fn required(wallet: &Pubkey, mint: Pubkey, status: TokenAccountStatus) -> RequiredTokenAccount {
+        RequiredTokenAccount {
+            mint,
+            token_program: token_program(),
+            address: associated_token_address(wallet, &mint, &token_program()),
+            status,
+        }
+    }
+
+    fn descriptions(steps: &[FixStep]) -> Vec<String> {
+        steps.iter().map(|step| step.description.clone()).collect()
+    }
+
+    #[test]
+    fn missing_accounts_are_created() {
+        let wallet = Pubkey::new_unique();
+        let ready = required(
+            &wallet,
+            Pubkey::new_unique(),
+            TokenAccountStatus::Ready { amount: 1 },
+        );
+        let missing = required(&wallet, Pubkey::new_unique(), TokenAccountStatus::Missing);
+
+        let plan = plan_fixes(&[ready, missing.clone()], &wallet, 0).unwrap();
+
+        assert!(plan.close.is_empty() && plan.unfixable.is_empty());
+        assert_eq!(
+            descriptions(&plan.setup),
+            [format!("Create {} for {}", missing.address, missing.mint)]
+        );
+        assert_eq!(
+            plan.setup[0].instructions[0].program_id.to_bytes(),
+            spl_associated_token_account::ID.to_bytes()
         );
-        println!("  Delegated amount: {}", token_account.delegated_amount);
-        println!("  This might interfere with flash loans");
     }
 
-    // Check close authority
-    if token_account.close_authority.is_some() {
-        println!(
-            "⚠ INFO: Account has close authority: {:?}",
-            token_account.close_authority
+    #[test]
+    fn empty_non_native_wsol_is_closed_before_being_recreated() {
+        let wallet = Pubkey::new_unique();
+        let wsol = required(
+            &wallet,
+            wsol_mint(),
+            TokenAccountStatus::NotNative { amount: 0 },
+        );
+
+        let plan = plan_fixes(std::slice::from_ref(&wsol), &wallet, 0).unwrap();
+
+        assert_eq!(
+            descriptions(&plan.close),
+            [format!("Close non-native WSOL account {}", wsol.address)]
+        );
+        assert_eq!(
+            descriptions(&plan.setup),
+            [format!("Create {} for {}", wsol.address, wsol.mint)]
+        );
+        assert_eq!(
+            plan.transactions()
+                .iter()
+                .map(|(label, _)| label.as_str())
+                .collect::<Vec<_>>(),
+            ["Close", "Setup"]
         );
     }
 
-    // 6. Check if account has sufficient balance for flash loan
-    println!("=== BALANCE CHECK ===");
-    println!(
-        "Token balance: {} lamports ({} SOL)",
-        token_account.amount,
-        token_account.amount as f64 / 1_000_000_000.0
-    );
+    #[test]
+    fn funded_non_native_wsol_is_left_to_a_human() {
+        let wallet = Pubkey::new_unique();
+        let wsol = required(
+            &wallet,
+            wsol_mint(),
+            TokenAccountStatus::NotNative { amount: 5 },
+        );
+
+        let plan = plan_fixes(std::slice::from_ref(&wsol), &wallet, 1_000).unwrap();
 
-    if token_account.amount == 0 {
-        println!("⚠ WARNING: Account has zero balance");
-        println!("  Flash loans might still work, but you need balance for fees");
+        assert!(plan.is_empty());
+        assert_eq!(
+            plan.unfixable,
+            [
+                format!(
+                    "{} holds 5 non-native WSOL, move it out before it can be closed",
+                    wsol.address
+                ),
+                format!("Can't wrap SOL until {} is fixed", wsol.address),
+            ]
+        );
     }
 
-    // 7. Verify this is the correct ATA
-    let expected_ata = spl_associated_token_account::get_associated_token_address(
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::new_from_array(
-            wallet_pubkey.to_bytes(),
-        ),
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::from_str(
-            "So11111111111111111111111111111111111111112",
-        )?,
-    );
-    let expected_ata_sdk = Pubkey::new_from_array(expected_ata.to_bytes());
+    #[test]
+    fn wsol_is_topped_up_to_the_wrap_amount() {
+        let wallet = Pubkey::new_unique();
+        let wsol = required(
+            &wallet,
+            wsol_mint(),
+            TokenAccountStatus::Ready { amount: 400 },
+        );
 
-    if *ata_address != expected_ata_sdk {
-        println!("✗ THIS IS NOT YOUR STANDARD ATA!");
-        println!("  Expected: {}", expected_ata_sdk);
-        println!("  Using:    {}", ata_address);
-        println!("  This could be the source of your problems!");
-    } else {
-        println!("✓ This is your correct standard ATA");
+        let plan = plan_fixes(std::slice::from_ref(&wsol), &wallet, 1_000).unwrap();
+        assert!(plan.close.is_empty());
+        assert_eq!(
+            descriptions(&plan.setup),
+            [format!("Wrap 600 lamports into {}", wsol.address)]
+        );
+        assert_eq!(
+            plan.setup[0].instructions,
+            wrap_sol(&wallet, &wsol.address, 600).unwrap()
+        );
+
+        // Enough wrapped already
+        assert!(plan_fixes(std::slice::from_ref(&wsol), &wallet, 400)
+            .unwrap()
+            .is_empty());
+
+        // Without a required WSOL account one is created before wrapping into it
+        let plan = plan_fixes(&[], &wallet, 1_000).unwrap();
+        assert_eq!(
+            descriptions(&plan.setup),
+            [
+                format!("Create {} for {}", wsol.address, wsol_mint()),
+                format!("Wrap 1000 lamports into {}", wsol.address),
+            ]
+        );
     }
 
-    println!("=== DIAGNOSTIC COMPLETE ===");
+    #[test]
+    fn setup_is_split_into_transactions_in_order() {
+        let wallet = Pubkey::new_unique();
+        let accounts = (0..SETUP_CHUNK_SIZE + 1)
+            .map(|_| required(&wallet, Pubkey::new_unique(), TokenAccountStatus::Missing))
+            .collect::<Vec<_>>();
 
-    Ok(())
-}
+        let plan = plan_fixes(&accounts, &wallet, 1_000).unwrap();
+        let transactions = plan.transactions();
 
-// Usage in your main function
-pub fn run_diagnostic(rpc_client: &RpcClient, wallet: &Keypair) {
-    // Your calculated ATA
-    let uu = spl_associated_token_account::get_associated_token_address(
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::new_from_array(
-            wallet.pubkey().to_bytes(),
-        ),
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::from_str(
-            "So11111111111111111111111111111111111111112",
-        )
-        .unwrap(),
-    );
-    let ata_pubkey = Pubkey::new_from_array(uu.to_bytes());
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|(label, steps)| (label.as_str(), steps.len()))
+                .collect::<Vec<_>>(),
+            [("Setup 1/2", SETUP_CHUNK_SIZE), ("Setup 2/2", 3)]
+        );
+        // The wrap lands after the account it wraps into
+        assert!(transactions[1].1[2].description.starts_with("Wrap 1000"));
+    }
 
-    match diagnose_wsol_ata(rpc_client, &wallet.pubkey(), &ata_pubkey) {
-        Ok(_) => println!("✓ All WSOL ATA checks passed!"),
-        Err(e) => {
-            println!("✗ WSOL ATA diagnostic failed: {}", e);
-            println!("\nTO FIX:");
-            println!("1. Make sure your ATA is properly created");
-            println!("2. Wrap some SOL using: spl-token wrap <amount>");
-            println!("3. Or use system transfer + sync_native instructions");
-        }
+    #[test]
+    fn report_is_healthy_unless_something_failed() {
+        let mut report = DoctorReport::default();
+        report.push("RPC", CheckStatus::Pass, "slot 1");
+        report.push("Lookup table", CheckStatus::Warn, "not set");
+        assert!(report.is_healthy());
+
+        report.push("Pool orca", CheckStatus::Fail, "doesn't exist");
+        assert!(!report.is_healthy());
+        assert_eq!(
+            [CheckStatus::Pass, CheckStatus::Warn, CheckStatus::Fail].map(|s| report.count(s)),
+            [1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn report_lists_checks_in_order_with_a_summary() {
+        let mut report = DoctorReport::default();
+        report.push("RPC", CheckStatus::Pass, "slot 1");
+        report.push("Pool orca", CheckStatus::Fail, "doesn't exist");
+        report.push("Pool raydium", CheckStatus::Fail, "can't decode");
+
+        assert_eq!(
+            report.to_string(),
+            "[PASS] RPC: slot 1\n\
+             [FAIL] Pool orca: doesn't exist\n\
+             [FAIL] Pool raydium: can't decode\n\
+             1 passed, 0 warnings, 2 failed"
+        );
     }
 }
@@ -0,0 +1,344 @@
+use borsh::BorshDeserialize;
+use futures_util::stream::{select_all, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::feed::{endpoint_label, AccountUpdate, Backoff, FeedSink};
+use crate::pools_struct::raydium::{amm_config_trade_fee_rate, RaydiumPoolState};
+use crate::pools_struct::structs::{DexType, Pool};
+use crate::utils::parse_account_data;
+
+/// Where the two mints of a pool sit in its account, discriminator included
+#[derive(Debug, Clone, Copy)]
+pub struct PoolLayout {
+    pub data_size: u64,
+    pub mint_a_offset: usize,
+    pub mint_b_offset: usize,
+}
+
+pub fn pool_layout(dex: DexType) -> Option<PoolLayout> {
+    match dex {
+        DexType::Orca => Some(PoolLayout {
+            data_size: 653,
+            mint_a_offset: 101,
+            mint_b_offset: 181,
+        }),
+        DexType::Raydium => Some(PoolLayout {
+            data_size: 1544,
+            mint_a_offset: 73,
+            mint_b_offset: 105,
+        }),
+        DexType::Meteora => Some(PoolLayout {
+            data_size: 904,
+            mint_a_offset: 88,
+            mint_b_offset: 120,
+        }),
+        _ => None,
+    }
+}
+
+/// Which pools to look for and how much liquidity they need
+#[derive(Debug, Clone)]
+pub struct DiscoveryFilter {
+    pub dexes: Vec<DexType>,
+    /// Pools trading any two of these mints are kept
+    pub mints: Vec<Pubkey>,
+    /// Pools whose model doesn't report liquidity are kept regardless
+    pub min_liquidity: u128,
+}
+
+impl DiscoveryFilter {
+    /// getProgramAccounts/programSubscribe filters, one set per DEX and ordered mint pair
+    fn queries(&self) -> Vec<(DexType, Vec<RpcFilterType>)> {
+        let mut queries = vec![];
+
+        for dex in self.dexes.iter().copied() {
+            let Some(layout) = pool_layout(dex) else {
+                continue;
+            };
+
+            for mint_a in self.mints.iter() {
+                for mint_b in self.mints.iter().filter(|mint_b| *mint_b != mint_a) {
+                    queries.push((
+                        dex,
+                        vec![
+                            RpcFilterType::DataSize(layout.data_size),
+                            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                                layout.mint_a_offset,
+                                mint_a.to_bytes().to_vec(),
+                            )),
+                            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                                layout.mint_b_offset,
+                                mint_b.to_bytes().to_vec(),
+                            )),
+                        ],
+                    ));
+                }
+            }
+        }
+
+        queries
+    }
+
+    fn has_liquidity(&self, data: &[u8], dex: DexType) -> bool {
+        match parse_account_data(data, dex) {
+            Ok(state) => state
+                .get_liquidity()
+                .is_none_or(|liquidity| liquidity >= self.min_liquidity),
+            Err(_) => false,
+        }
+    }
+}
+
+fn program_accounts_config(filters: Vec<RpcFilterType>) -> RpcProgramAccountsConfig {
+    RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64Zstd),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Name a discovered pool after its DEX and address
+fn pool_name(dex: DexType, pool_id: &Pubkey) -> String {
+    let address = pool_id.to_string();
+    format!("{:?}_{}", dex, &address[..8]).to_lowercase()
+}
+
+/// Fee rate of pools that don't hold it in their own account
+async fn fee_rate(
+    rpc_client: &RpcClient,
+    dex: DexType,
+    data: &[u8],
+) -> Result<Option<u32>, anyhow::Error> {
+    match dex {
+        DexType::Raydium => {
+            let state = RaydiumPoolState::try_from_slice(data.get(8..).unwrap_or_default())?;
+            let config = rpc_client.get_account_data(&state.amm_config).await?;
+            let fee_rate = amm_config_trade_fee_rate(&config)
+                .ok_or_else(|| anyhow::anyhow!("AmmConfig {} too short", state.amm_config))?;
+
+            Ok(Some(fee_rate))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Turn a program account into a pool if it passes the liquidity filter
+async fn to_pool(
+    rpc_client: &RpcClient,
+    filter: &DiscoveryFilter,
+    dex: DexType,
+    pool_id: Pubkey,
+    data: &[u8],
+) -> Option<Pool> {
+    if !filter.has_liquidity(data, dex) {
+        return None;
+    }
+
+    match fee_rate(rpc_client, dex, data).await {
+        Ok(fee_rate) => Some(Pool {
+            name: pool_name(dex, &pool_id),
+            pool_id,
+            pool: dex,
+            fee_rate,
+        }),
+        Err(err) => {
+            eprintln!("Error reading fee of {}: {}", pool_id, err);
+            None
+        }
+    }
+}
+
+/// Every pool of the configured DEXes trading two of the configured mints with enough liquidity
+pub async fn discover_pools(
+    rpc_client: &RpcClient,
+    filter: &DiscoveryFilter,
+) -> Result<Vec<Pool>, anyhow::Error> {
+    let mut pools = HashMap::new();
+
+    for (dex, filters) in filter.queries() {
+        let accounts = rpc_client
+            .get_program_accounts_with_config(&dex.program_id(), program_accounts_config(filters))
+            .await?;
+
+        for (pool_id, account) in accounts {
+            if let Some(pool) = to_pool(rpc_client, filter, dex, pool_id, &account.data).await {
+                pools.insert(pool_id, pool);
+            }
+        }
+    }
+
+    Ok(pools.into_values().collect())
+}
+
+/// Follow the DEX programs so pools created after startup are picked up, and publish
+/// the updates of every matching pool, resubscribing with backoff when the connection drops
+pub async fn watch_programs(
+    ws_url: String,
+    rpc_url: String,
+    filter: DiscoveryFilter,
+    sink: Arc<FeedSink>,
+) {
+    let name = format!("{} (programs)", endpoint_label(&ws_url));
+    let rpc_client = RpcClient::new(rpc_url);
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+
+    loop {
+        match follow_programs(&ws_url, &name, &rpc_client, &filter, &sink, &mut backoff).await {
+            Ok(()) => eprintln!("Feed {} closed", name),
+            Err(err) => eprintln!("Feed {} failed: {}", name, err),
+        }
+
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+}
+
+async fn follow_programs(
+    ws_url: &str,
+    name: &str,
+    rpc_client: &RpcClient,
+    filter: &DiscoveryFilter,
+    sink: &FeedSink,
+    backoff: &mut Backoff,
+) -> Result<(), anyhow::Error> {
+    let client = PubsubClient::new(ws_url).await?;
+
+    let mut streams = vec![];
+    for (dex, filters) in filter.queries() {
+        let (stream, _) = client
+            .program_subscribe(&dex.program_id(), Some(program_accounts_config(filters)))
+            .await?;
+        streams.push(stream.map(move |response| (dex, response)).boxed());
+    }
+    backoff.reset();
+
+    let mut updates = select_all(streams);
+    while let Some((dex, response)) = updates.next().await {
+        let Ok(pool_id) = Pubkey::from_str(&response.value.pubkey) else {
+            continue;
+        };
+        let Some(data) = response.value.account.data.decode() else {
+            continue;
+        };
+
+        if !sink.is_tracked(&pool_id) {
+            let Some(pool) = to_pool(rpc_client, filter, dex, pool_id, &data).await else {
+                continue;
+            };
+
+            println!("Discovered {} ({})", pool.name, pool_id);
+            sink.add_pool(pool);
+        }
+
+        sink.publish(
+            name,
+            AccountUpdate {
+                pubkey: pool_id,
+                slot: response.context.slot,
+                write_version: None,
+                data,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pools_struct::orca::WhirlpoolState;
+
+    fn whirlpool(mint_a: Pubkey, mint_b: Pubkey, liquidity: u128) -> Vec<u8> {
+        let mut state = WhirlpoolState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        state.token_mint_a = mint_a;
+        state.token_mint_b = mint_b;
+        state.liquidity = liquidity;
+        borsh::to_vec(&state).unwrap()
+    }
+
+    /// Raydium accounts start with an 8 byte discriminator the state doesn't hold
+    fn raydium_pool(mint_a: Pubkey, mint_b: Pubkey, liquidity: u128) -> Vec<u8> {
+        let mut state = RaydiumPoolState::deserialize(&mut &[0u8; 2048][..]).unwrap();
+        state.token_mint_0 = mint_a;
+        state.token_mint_1 = mint_b;
+        state.liquidity = liquidity;
+
+        let mut data = vec![0u8; 8];
+        data.extend(borsh::to_vec(&state).unwrap());
+        data.resize(pool_layout(DexType::Raydium).unwrap().data_size as usize, 0);
+        data
+    }
+
+    fn filter(mints: Vec<Pubkey>, min_liquidity: u128) -> DiscoveryFilter {
+        DiscoveryFilter {
+            dexes: vec![DexType::Orca, DexType::Raydium],
+            mints,
+            min_liquidity,
+        }
+    }
+
+    /// DEXes whose queries would return an account holding `data`
+    fn matching(filter: &DiscoveryFilter, data: &[u8]) -> Vec<DexType> {
+        filter
+            .queries()
+            .into_iter()
+            .filter(|(_, filters)| {
+                filters.iter().all(|filter| match filter {
+                    RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+                    RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+                    _ => false,
+                })
+            })
+            .map(|(dex, _)| dex)
+            .collect()
+    }
+
+    #[test]
+    fn queries_every_ordered_pair_of_distinct_mints() {
+        let filter = filter([(); 3].map(|_| Pubkey::new_unique()).to_vec(), 0);
+
+        // Two DEXes, three mints in six orders
+        assert_eq!(filter.queries().len(), 12);
+    }
+
+    #[test]
+    fn memcmp_filters_match_pools_of_the_configured_mints() {
+        let [a, b, other] = [(); 3].map(|_| Pubkey::new_unique());
+        let filter = filter(vec![a, b], 0);
+
+        assert_eq!(matching(&filter, &whirlpool(a, b, 0)), vec![DexType::Orca]);
+        assert_eq!(matching(&filter, &whirlpool(b, a, 0)), vec![DexType::Orca]);
+        assert_eq!(
+            matching(&filter, &raydium_pool(a, b, 0)),
+            vec![DexType::Raydium]
+        );
+        assert!(matching(&filter, &whirlpool(a, other, 0)).is_empty());
+        assert!(matching(&filter, &raydium_pool(other, b, 0)).is_empty());
+    }
+
+    #[test]
+    fn pools_below_the_minimum_liquidity_are_dropped() {
+        let [a, b] = [(); 2].map(|_| Pubkey::new_unique());
+        let filter = filter(vec![a, b], 1_000);
+
+        assert!(filter.has_liquidity(&whirlpool(a, b, 1_000), DexType::Orca));
+        assert!(!filter.has_liquidity(&whirlpool(a, b, 999), DexType::Orca));
+        assert!(filter.has_liquidity(&raydium_pool(a, b, 5_000), DexType::Raydium));
+        assert!(!filter.has_liquidity(&raydium_pool(a, b, 0), DexType::Raydium));
+        assert!(!filter.has_liquidity(&[0u8; 16], DexType::Orca));
+    }
+}
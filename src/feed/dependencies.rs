@@ -0,0 +1,148 @@
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// Accounts added and dropped by a dependency update
+#[derive(Debug, Default)]
+pub struct DependencyChange {
+    pub added: Vec<Pubkey>,
+    pub removed: Vec<Pubkey>,
+}
+
+impl DependencyChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Auxiliary accounts each pool needs, counted so one shared by several pools stays
+/// subscribed until none of them needs it
+#[derive(Default)]
+pub struct DependencyTracker {
+    by_pool: HashMap<Pubkey, Vec<Pubkey>>,
+    needed_by: HashMap<Pubkey, usize>,
+}
+
+impl DependencyTracker {
+    /// Replace what `pool_id` needs, returning the accounts no pool needed before and
+    /// the ones no pool needs anymore
+    pub fn set(&mut self, pool_id: Pubkey, accounts: Vec<Pubkey>) -> DependencyChange {
+        let previous = self.by_pool.remove(&pool_id).unwrap_or_default();
+        if previous == accounts {
+            self.by_pool.insert(pool_id, accounts);
+            return DependencyChange::default();
+        }
+
+        let mut change = DependencyChange::default();
+
+        for account in &accounts {
+            let count = self.needed_by.entry(*account).or_default();
+            *count += 1;
+            if *count == 1 {
+                change.added.push(*account);
+            }
+        }
+
+        for account in &previous {
+            if let Some(count) = self.needed_by.get_mut(account) {
+                *count -= 1;
+                if *count == 0 {
+                    self.needed_by.remove(account);
+                    change.removed.push(*account);
+                }
+            }
+        }
+
+        self.by_pool.insert(pool_id, accounts);
+        change
+    }
+
+    /// Pools whose quotes read `account`
+    pub fn pools_needing(&self, account: &Pubkey) -> Vec<Pubkey> {
+        self.by_pool
+            .iter()
+            .filter(|(_, accounts)| accounts.contains(account))
+            .map(|(pool_id, _)| *pool_id)
+            .collect()
+    }
+
+    pub fn contains(&self, account: &Pubkey) -> bool {
+        self.needed_by.contains_key(account)
+    }
+
+    pub fn accounts(&self) -> Vec<Pubkey> {
+        self.needed_by.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.needed_by.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut accounts: Vec<Pubkey>) -> Vec<Pubkey> {
+        accounts.sort();
+        accounts
+    }
+
+    #[test]
+    fn reports_accounts_once_per_first_and_last_user() {
+        let (pool_a, pool_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (shared, only_a, only_b) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut tracker = DependencyTracker::default();
+
+        let change = tracker.set(pool_a, vec![shared, only_a]);
+        assert_eq!(sorted(change.added), sorted(vec![shared, only_a]));
+        assert!(change.removed.is_empty());
+
+        // Already subscribed for pool A
+        let change = tracker.set(pool_b, vec![shared, only_b]);
+        assert_eq!(change.added, vec![only_b]);
+        assert!(change.removed.is_empty());
+        assert_eq!(tracker.len(), 3);
+
+        // Pool B still needs the shared account
+        let change = tracker.set(pool_a, vec![]);
+        assert!(change.added.is_empty());
+        assert_eq!(change.removed, vec![only_a]);
+        assert!(tracker.contains(&shared));
+
+        assert_eq!(tracker.pools_needing(&shared), vec![pool_b]);
+        assert!(tracker.pools_needing(&only_a).is_empty());
+
+        let change = tracker.set(pool_b, vec![]);
+        assert_eq!(sorted(change.removed), sorted(vec![shared, only_b]));
+        assert_eq!(tracker.len(), 0);
+    }
+
+    #[test]
+    fn unchanged_accounts_are_no_change() {
+        let pool = Pubkey::new_unique();
+        let accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut tracker = DependencyTracker::default();
+
+        tracker.set(pool, accounts.clone());
+        assert!(tracker.set(pool, accounts).is_empty());
+        assert_eq!(tracker.len(), 2);
+    }
+
+    #[test]
+    fn moving_window_swaps_only_the_edges() {
+        let pool = Pubkey::new_unique();
+        let arrays = (0..4).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let mut tracker = DependencyTracker::default();
+
+        tracker.set(pool, arrays[0..3].to_vec());
+        let change = tracker.set(pool, arrays[1..4].to_vec());
+
+        assert_eq!(change.added, vec![arrays[3]]);
+        assert_eq!(change.removed, vec![arrays[0]]);
+        assert_eq!(sorted(tracker.accounts()), sorted(arrays[1..4].to_vec()));
+    }
+}
@@ -0,0 +1,440 @@
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, StreamExt};
+use futures_util::SinkExt;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestPing,
+    SubscribeUpdateAccount,
+};
+
+use crate::feed::{endpoint_label, AccountSource, AccountUpdate, Subscription, SubscriptionChange};
+
+/// Yellowstone (Geyser) gRPC stream of every pool in one subscription, at processed
+/// commitment so writes arrive ahead of the other sources. Account changes replace the
+/// filter on the open stream, Geyser has no incremental one
+pub struct GrpcSource {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    /// Programs whose accounts we also want, on top of the pools themselves
+    pub owners: Vec<Pubkey>,
+}
+
+impl GrpcSource {
+    pub fn new(endpoint: String, x_token: Option<String>, owners: Vec<Pubkey>) -> Self {
+        Self {
+            endpoint,
+            x_token,
+            owners,
+        }
+    }
+}
+
+fn subscribe_request(accounts: &HashSet<Pubkey>, owners: &[Pubkey]) -> SubscribeRequest {
+    let mut filters = HashMap::new();
+    filters.insert(
+        "pools".to_string(),
+        SubscribeRequestFilterAccounts {
+            account: accounts.iter().map(Pubkey::to_string).collect(),
+            ..Default::default()
+        },
+    );
+    if !owners.is_empty() {
+        filters.insert(
+            "owners".to_string(),
+            SubscribeRequestFilterAccounts {
+                owner: owners.iter().map(Pubkey::to_string).collect(),
+                ..Default::default()
+            },
+        );
+    }
+
+    SubscribeRequest {
+        accounts: filters,
+        commitment: Some(CommitmentLevel::Processed as i32),
+        ..Default::default()
+    }
+}
+
+impl AccountSource for GrpcSource {
+    fn name(&self) -> String {
+        endpoint_label(&self.endpoint)
+    }
+
+    fn subscribe(
+        &self,
+        accounts: Vec<Pubkey>,
+    ) -> BoxFuture<'_, Result<Subscription, anyhow::Error>> {
+        Box::pin(async move {
+            let owners = self.owners.clone();
+            let accounts = accounts.into_iter().collect::<HashSet<_>>();
+            let (changes_tx, changes) = mpsc::unbounded_channel::<SubscriptionChange>();
+            let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
+                .x_token(self.x_token.clone())?
+                .connect()
+                .await?;
+            let (requests, updates) = client
+                .subscribe_with_request(Some(subscribe_request(&accounts, &owners)))
+                .await?;
+
+            // The client and request sink stay alive with the stream
+            let stream = stream::unfold(
+                (client, requests, updates, changes, accounts, owners),
+                |(client, mut requests, mut updates, mut changes, mut accounts, owners)| async move {
+                    loop {
+                        let update = tokio::select! {
+                            update = updates.next() => match update? {
+                                Ok(update) => update,
+                                Err(status) => {
+                                    eprintln!("Error:{}", status);
+                                    return None;
+                                }
+                            },
+                            Some(change) = changes.recv() => {
+                                for account in change.removed {
+                                    accounts.remove(&account);
+                                }
+                                accounts.extend(change.added);
+                                // A dead connection also ends `updates`, which reconnects
+                                if let Err(err) = requests.send(subscribe_request(&accounts, &owners)).await {
+                                    eprintln!("Error:{}", err);
+                                }
+                                continue;
+                            }
+                        };
+
+                        match update.update_oneof {
+                            Some(UpdateOneof::Account(account)) => {
+                                if let Some(update) = account_update(account) {
+                                    return Some((
+                                        update,
+                                        (client, requests, updates, changes, accounts, owners),
+                                    ));
+                                }
+                            }
+                            // Load balancers close streams that stay silent
+                            Some(UpdateOneof::Ping(_)) => {
+                                let pong = SubscribeRequest {
+                                    ping: Some(SubscribeRequestPing { id: 1 }),
+                                    ..Default::default()
+                                };
+                                if let Err(err) = requests.send(pong).await {
+                                    eprintln!("Error:{}", err);
+                                    return None;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                },
+            );
+
+            Ok(Subscription {
+                updates: stream.boxed(),
+                changes: changes_tx,
+            })
+        })
+    }
+}
+
+fn account_update(update: SubscribeUpdateAccount) -> Option<AccountUpdate> {
+    let account = update.account?;
+
+    Some(AccountUpdate {
+        pubkey: Pubkey::try_from(account.pubkey.as_slice()).ok()?,
+        slot: update.slot,
+        write_version: Some(account.write_version),
+        data: account.data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream::BoxStream;
+    use std::sync::{Arc, Mutex};
+    use yellowstone_grpc_proto::geyser::geyser_server::{Geyser, GeyserServer};
+    use yellowstone_grpc_proto::geyser::*;
+    use yellowstone_grpc_proto::tonic::transport::server::TcpIncoming;
+    use yellowstone_grpc_proto::tonic::transport::Server;
+    use yellowstone_grpc_proto::tonic::{self, Request, Response, Status, Streaming};
+
+    /// Replays recorded updates to whoever subscribes and keeps the requests it got
+    struct MockGeyser {
+        updates: Vec<SubscribeUpdate>,
+        requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+        /// Leave the stream open after the recorded updates, like a live server
+        keep_open: bool,
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for MockGeyser {
+        type SubscribeStream = BoxStream<'static, Result<SubscribeUpdate, Status>>;
+        type SubscribeDeshredStream = BoxStream<'static, Result<SubscribeUpdateDeshred, Status>>;
+        type SubscribeGossipStream = BoxStream<'static, Result<SubscribeUpdateGossip, Status>>;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Self::SubscribeStream>, Status> {
+            let mut requests = request.into_inner();
+            let received = self.requests.clone();
+
+            // Keep reading like a real server would, pings and filter changes come in on
+            // the same stream
+            tokio::spawn(async move {
+                while let Ok(Some(request)) = requests.message().await {
+                    received.lock().unwrap().push(request);
+                }
+            });
+
+            let updates = stream::iter(self.updates.clone().into_iter().map(Ok));
+            if self.keep_open {
+                Ok(Response::new(updates.chain(stream::pending()).boxed()))
+            } else {
+                Ok(Response::new(updates.boxed()))
+            }
+        }
+
+        async fn subscribe_deshred(
+            &self,
+            _request: Request<Streaming<SubscribeDeshredRequest>>,
+        ) -> Result<Response<Self::SubscribeDeshredStream>, Status> {
+            Err(Status::unimplemented("subscribe_deshred"))
+        }
+
+        async fn subscribe_gossip(
+            &self,
+            _request: Request<SubscribeGossipRequest>,
+        ) -> Result<Response<Self::SubscribeGossipStream>, Status> {
+            Err(Status::unimplemented("subscribe_gossip"))
+        }
+
+        async fn subscribe_replay_info(
+            &self,
+            _request: Request<SubscribeReplayInfoRequest>,
+        ) -> Result<Response<SubscribeReplayInfoResponse>, Status> {
+            Err(Status::unimplemented("subscribe_replay_info"))
+        }
+
+        async fn ping(
+            &self,
+            _request: Request<PingRequest>,
+        ) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _request: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _request: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(
+            &self,
+            _request: Request<GetSlotRequest>,
+        ) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _request: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(
+            &self,
+            _request: Request<GetVersionRequest>,
+        ) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    fn recorded_account(
+        pubkey: Pubkey,
+        slot: u64,
+        write_version: u64,
+        data: Vec<u8>,
+    ) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["pools".to_string()],
+            update_oneof: Some(UpdateOneof::Account(SubscribeUpdateAccount {
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: pubkey.to_bytes().to_vec(),
+                    data,
+                    write_version,
+                    ..Default::default()
+                }),
+                slot,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Serve `updates` on a random local port, returning its endpoint and the captured requests
+    async fn serve(
+        updates: Vec<SubscribeUpdate>,
+        keep_open: bool,
+    ) -> (String, Arc<Mutex<Vec<SubscribeRequest>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let geyser = MockGeyser {
+            updates,
+            requests: requests.clone(),
+            keep_open,
+        };
+
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let endpoint = format!("http://{}", incoming.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(GeyserServer::new(geyser))
+                .serve_with_incoming(incoming),
+        );
+
+        (endpoint, requests)
+    }
+
+    /// Wait until the server got `count` requests
+    async fn received(
+        requests: &Mutex<Vec<SubscribeRequest>>,
+        count: usize,
+    ) -> Vec<SubscribeRequest> {
+        for _ in 0..100 {
+            let received = requests.lock().unwrap().clone();
+            if received.len() >= count {
+                return received;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("server got fewer than {} requests", count);
+    }
+
+    #[tokio::test]
+    async fn streams_recorded_account_updates() {
+        let pool = Pubkey::new_unique();
+        let (endpoint, _) = serve(
+            vec![
+                recorded_account(pool, 100, 7, vec![1, 2, 3]),
+                SubscribeUpdate {
+                    update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+                    ..Default::default()
+                },
+                recorded_account(pool, 101, 9, vec![4, 5]),
+            ],
+            false,
+        )
+        .await;
+
+        let source = GrpcSource::new(endpoint, None, vec![]);
+        let updates = source
+            .subscribe(vec![pool])
+            .await
+            .unwrap()
+            .updates
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].pubkey, pool);
+        assert_eq!(updates[0].slot, 100);
+        assert_eq!(updates[0].write_version, Some(7));
+        assert_eq!(updates[0].data, vec![1, 2, 3]);
+        assert_eq!(updates[1].slot, 101);
+        assert_eq!(updates[1].write_version, Some(9));
+    }
+
+    #[tokio::test]
+    async fn subscribes_by_pubkey_and_owner_at_processed() {
+        let pool = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let (endpoint, requests) = serve(vec![], false).await;
+
+        let source = GrpcSource::new(endpoint, None, vec![owner]);
+        let subscription = source.subscribe(vec![pool]).await.unwrap();
+        assert_eq!(subscription.updates.count().await, 0);
+
+        let request = received(&requests, 1).await.remove(0);
+        assert_eq!(request.commitment, Some(CommitmentLevel::Processed as i32));
+        assert_eq!(request.accounts["pools"].account, vec![pool.to_string()]);
+        assert_eq!(request.accounts["owners"].owner, vec![owner.to_string()]);
+    }
+
+    #[test]
+    fn owner_filter_is_left_out_without_owners() {
+        let request = subscribe_request(&HashSet::from([Pubkey::new_unique()]), &[]);
+        assert_eq!(request.accounts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn skips_updates_with_invalid_pubkeys() {
+        let mut invalid = recorded_account(Pubkey::new_unique(), 100, 1, vec![]);
+        if let Some(UpdateOneof::Account(account)) = invalid.update_oneof.as_mut() {
+            account.account.as_mut().unwrap().pubkey = vec![1, 2, 3];
+        }
+        let pool = Pubkey::new_unique();
+        let (endpoint, _) =
+            serve(vec![invalid, recorded_account(pool, 101, 2, vec![])], false).await;
+
+        let source = GrpcSource::new(endpoint, None, vec![]);
+        let updates = source
+            .subscribe(vec![pool])
+            .await
+            .unwrap()
+            .updates
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].pubkey, pool);
+    }
+
+    #[tokio::test]
+    async fn applies_changes_on_the_open_stream() {
+        let pool = Pubkey::new_unique();
+        let tick_array = Pubkey::new_unique();
+        let (endpoint, requests) = serve(vec![recorded_account(pool, 100, 1, vec![])], true).await;
+
+        let source = GrpcSource::new(endpoint, None, vec![]);
+        let mut subscription = source.subscribe(vec![pool]).await.unwrap();
+        assert_eq!(subscription.updates.next().await.unwrap().pubkey, pool);
+
+        subscription
+            .changes
+            .send(SubscriptionChange {
+                added: vec![tick_array],
+                removed: vec![pool],
+            })
+            .unwrap();
+        // The change is applied while waiting for the next update
+        let next = tokio::time::timeout(
+            std::time::Duration::from_millis(200),
+            subscription.updates.next(),
+        );
+        assert!(next.await.is_err());
+
+        let requests = received(&requests, 2).await;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].accounts["pools"].account,
+            vec![tick_array.to_string()]
+        );
+        assert_eq!(
+            requests[1].commitment,
+            Some(CommitmentLevel::Processed as i32)
+        );
+    }
+}
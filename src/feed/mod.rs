@@ -0,0 +1,680 @@
+pub mod dependencies;
+pub mod grpc;
+pub mod polling;
+pub mod queue;
+pub mod websocket;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{BoxStream, StreamExt};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+use crate::feed::dependencies::DependencyTracker;
+use crate::feed::queue::UpdateQueue;
+use crate::market::{hash_data, Arrival, MarketEntry, MarketState};
+use crate::pools_struct::structs::{Pool, PriceFetcher};
+use crate::utils::parse_account_data;
+
+// getMultipleAccounts accepts at most 100 addresses per call
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+// A connection streaming this long is healthy again, the next reconnect starts from the
+// shortest delay
+const HEALTHY_AFTER: Duration = Duration::from_secs(30);
+
+/// Raw account write, whatever source it came from
+#[derive(Debug, Clone)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+    /// Only sources that report it (gRPC) set this
+    pub write_version: Option<u64>,
+    pub data: Vec<u8>,
+}
+
+pub type UpdateStream = BoxStream<'static, AccountUpdate>;
+
+/// Accounts to start and stop following on a live subscription
+#[derive(Debug, Default, Clone)]
+pub struct SubscriptionChange {
+    pub added: Vec<Pubkey>,
+    pub removed: Vec<Pubkey>,
+}
+
+impl SubscriptionChange {
+    /// What moves a subscription covering `previous` to `current`
+    pub fn between(previous: &HashSet<Pubkey>, current: &HashSet<Pubkey>) -> Self {
+        Self {
+            added: current.difference(previous).copied().collect(),
+            removed: previous.difference(current).copied().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Updates of a live subscription, and where to send changes of the accounts it covers.
+/// The source applies changes on the same connection and logs those it fails to apply,
+/// the stream keeps going either way
+pub struct Subscription {
+    pub updates: UpdateStream,
+    pub changes: mpsc::UnboundedSender<SubscriptionChange>,
+}
+
+/// Somewhere account updates come from (websocket, Geyser gRPC, polling)
+pub trait AccountSource: Send + Sync {
+    /// Label for logs, never the full URL since it may hold an API key
+    fn name(&self) -> String;
+    /// Subscribe to `accounts`, resolving once updates flow; the stream ends when the connection drops
+    fn subscribe(
+        &self,
+        accounts: Vec<Pubkey>,
+    ) -> BoxFuture<'_, Result<Subscription, anyhow::Error>>;
+}
+
+/// What one source delivered and how far behind the fastest source it was
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SourceStats {
+    pub updates: u64,
+    /// Updates this source delivered before any other
+    pub first: u64,
+    pub duplicates: u64,
+    pub stale: u64,
+    /// Time behind the first arrival, summed over duplicates
+    pub total_behind: Duration,
+    pub max_behind: Duration,
+    pub last_update: Option<Instant>,
+}
+
+impl SourceStats {
+    fn record(&mut self, arrival: Arrival) {
+        self.updates += 1;
+        self.last_update = Some(Instant::now());
+
+        match arrival {
+            Arrival::First => self.first += 1,
+            Arrival::Duplicate { behind } => {
+                self.duplicates += 1;
+                self.total_behind += behind;
+                self.max_behind = self.max_behind.max(behind);
+            }
+            Arrival::Stale => self.stale += 1,
+        }
+    }
+
+    pub fn average_behind(&self) -> Duration {
+        if self.duplicates == 0 {
+            return Duration::ZERO;
+        }
+        self.total_behind.div_f64(self.duplicates as f64)
+    }
+}
+
+/// Decodes updates of the pools we track into the market state and wakes the strategy,
+/// keeping only the first arrival of each (pool, slot) when several sources feed it
+pub struct FeedSink {
+    pools: DashMap<Pubkey, Pool>,
+    pub markets: Arc<MarketState>,
+    queue: Arc<UpdateQueue>,
+    stats: DashMap<String, SourceStats>,
+    dependencies: Mutex<DependencyTracker>,
+    /// Latest write of every dependent account
+    dependent_accounts: DashMap<Pubkey, AccountUpdate>,
+    /// Write each pool's market state was decoded from, to decode it again when the
+    /// accounts it depends on change
+    pool_updates: DashMap<Pubkey, AccountUpdate>,
+    /// Bumped whenever the set of accounts to subscribe changes
+    accounts_changed: watch::Sender<u64>,
+}
+
+impl FeedSink {
+    pub fn new(pools: &[Pool], markets: Arc<MarketState>, queue: Arc<UpdateQueue>) -> Self {
+        Self {
+            pools: pools
+                .iter()
+                .map(|pool| (pool.pool_id, pool.clone()))
+                .collect(),
+            markets,
+            queue,
+            stats: DashMap::new(),
+            dependencies: Mutex::new(DependencyTracker::default()),
+            dependent_accounts: DashMap::new(),
+            pool_updates: DashMap::new(),
+            accounts_changed: watch::Sender::new(0),
+        }
+    }
+
+    /// Per source stats, sorted by source
+    pub fn stats(&self) -> Vec<(String, SourceStats)> {
+        let mut stats = self
+            .stats
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect::<Vec<_>>();
+        stats.sort_by(|(a, _), (b, _)| a.cmp(b));
+        stats
+    }
+
+    pub fn pool_accounts(&self) -> Vec<Pubkey> {
+        self.pools.iter().map(|entry| *entry.key()).collect()
+    }
+
+    /// Every pool and the accounts their quotes currently depend on
+    pub fn accounts(&self) -> Vec<Pubkey> {
+        let mut accounts = self.pool_accounts();
+        accounts.extend(self.dependencies.lock().unwrap().accounts());
+        accounts
+    }
+
+    /// Notified whenever `accounts` changes, so subscriptions can follow
+    pub fn watch_accounts(&self) -> watch::Receiver<u64> {
+        self.accounts_changed.subscribe()
+    }
+
+    /// Dependent accounts pools need and how many of them we hold a write of
+    pub fn dependent_account_count(&self) -> (usize, usize) {
+        (
+            self.dependencies.lock().unwrap().len(),
+            self.dependent_accounts.len(),
+        )
+    }
+
+    /// Start tracking a pool found after startup, returns whether it was new. Subscriptions
+    /// pick up a new pool along with the accounts it depends on
+    pub fn add_pool(&self, pool: Pool) -> bool {
+        let added = self.pools.insert(pool.pool_id, pool).is_none();
+        if added {
+            self.accounts_changed
+                .send_modify(|generation| *generation += 1);
+        }
+        added
+    }
+
+    pub fn is_tracked(&self, pubkey: &Pubkey) -> bool {
+        self.pools.contains_key(pubkey)
+    }
+
+    /// Most recent update from any source other than `source`
+    pub fn last_update_except(&self, source: &str) -> Option<Instant> {
+        self.stats
+            .iter()
+            .filter(|entry| entry.key() != source)
+            .filter_map(|entry| entry.value().last_update)
+            .max()
+    }
+
+    /// Store the update from `source` if it's the first arrival of a newer state
+    pub fn publish(&self, source: &str, update: AccountUpdate) {
+        let Some(pool) = self.pools.get(&update.pubkey).map(|pool| pool.clone()) else {
+            self.store_dependent_account(update);
+            return;
+        };
+
+        // Skip decoding what another source already delivered
+        let arrival = self
+            .markets
+            .classify(&update.pubkey, update.slot, update.write_version);
+        if arrival != Arrival::First {
+            self.record(source, arrival);
+            return;
+        }
+
+        match parse_account_data(&update.data, pool.pool) {
+            Ok(mut state) => {
+                self.track_dependencies(&pool, state.get_dependent_accounts(&pool.pool_id));
+                self.load_dependencies(&pool, state.as_mut());
+
+                let entry = MarketEntry {
+                    pool: pool.clone(),
+                    state: Arc::<dyn PriceFetcher>::from(state),
+                    slot: update.slot,
+                    write_version: update.write_version,
+                    data_hash: hash_data(&update.data),
+                    confirmed_slot: update.slot,
+                    received_at: Instant::now(),
+                };
+
+                let arrival = self.markets.insert(entry);
+                self.record(source, arrival);
+
+                if arrival == Arrival::First {
+                    self.pool_updates.insert(pool.pool_id, update);
+                    self.queue.push(pool.pool_id);
+                }
+            }
+            Err(err) => {
+                eprintln!("Error:{}", err)
+            }
+        }
+    }
+
+    /// Hand `state` the latest writes we hold of the accounts its quotes read
+    fn load_dependencies(&self, pool: &Pool, state: &mut dyn PriceFetcher) {
+        let accounts = state
+            .get_dependent_accounts(&pool.pool_id)
+            .into_iter()
+            .filter_map(|account| {
+                let update = self.dependent_accounts.get(&account)?;
+                Some((account, update.data.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+
+        state.load_dependent_accounts(&pool.pool_id, &accounts);
+    }
+
+    /// Keep the newest write of an account some pool depends on, and quote the pools
+    /// reading it again if it changed
+    fn store_dependent_account(&self, update: AccountUpdate) {
+        if !self.dependencies.lock().unwrap().contains(&update.pubkey) {
+            return;
+        }
+
+        let pubkey = update.pubkey;
+        let changed = match self.dependent_accounts.entry(pubkey) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().slot > update.slot || entry.get().data == update.data {
+                    false
+                } else {
+                    entry.insert(update);
+                    true
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(update);
+                true
+            }
+        };
+
+        if changed {
+            let pools = self.dependencies.lock().unwrap().pools_needing(&pubkey);
+            for pool_id in pools {
+                self.reload_pool(&pool_id);
+            }
+        }
+    }
+
+    /// Decode the pool's current write again with the latest dependent accounts
+    fn reload_pool(&self, pool_id: &Pubkey) {
+        let (Some(pool), Some(update)) = (
+            self.pools.get(pool_id).map(|pool| pool.clone()),
+            self.pool_updates.get(pool_id).map(|update| update.clone()),
+        ) else {
+            return;
+        };
+        let Ok(mut state) = parse_account_data(&update.data, pool.pool) else {
+            return;
+        };
+        self.load_dependencies(&pool, state.as_mut());
+
+        if self.markets.replace_state(
+            pool_id,
+            update.slot,
+            update.write_version,
+            Arc::<dyn PriceFetcher>::from(state),
+        ) {
+            self.queue.push(*pool_id);
+        }
+    }
+
+    /// Record what `pool` needs at its current price, waking the subscriptions if that changed
+    fn track_dependencies(&self, pool: &Pool, accounts: Vec<Pubkey>) {
+        let change = self
+            .dependencies
+            .lock()
+            .unwrap()
+            .set(pool.pool_id, accounts);
+        if change.is_empty() {
+            return;
+        }
+
+        for account in &change.removed {
+            self.dependent_accounts.remove(account);
+        }
+        println!(
+            "{} dependencies changed: {} added, {} removed",
+            pool.name,
+            change.added.len(),
+            change.removed.len()
+        );
+        self.accounts_changed
+            .send_modify(|generation| *generation += 1);
+    }
+
+    fn record(&self, source: &str, arrival: Arrival) {
+        self.stats
+            .entry(source.to_string())
+            .or_default()
+            .record(arrival);
+    }
+}
+
+/// Host of an endpoint, to label it in logs without leaking API keys in the URL
+pub fn endpoint_label(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Current state of every account, fetched in batches through getMultipleAccounts
+pub async fn fetch_snapshot(
+    rpc_client: &RpcClient,
+    accounts: &[Pubkey],
+) -> Result<Vec<AccountUpdate>, anyhow::Error> {
+    let mut updates = Vec::with_capacity(accounts.len());
+
+    for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let response = rpc_client
+            .get_multiple_accounts_with_commitment(chunk, CommitmentConfig::confirmed())
+            .await?;
+
+        for (pubkey, account) in chunk.iter().zip(response.value) {
+            if let Some(account) = account {
+                updates.push(AccountUpdate {
+                    pubkey: *pubkey,
+                    slot: response.context.slot,
+                    write_version: None,
+                    data: account.data,
+                });
+            }
+        }
+    }
+
+    Ok(updates)
+}
+
+/// When the connection dropped and the newest slot we had seen by then
+struct Gap {
+    since: Instant,
+    last_slot: u64,
+}
+
+/// Keep every pool subscribed through `source`, reconnecting with backoff whenever the
+/// connection drops and catching up from a getMultipleAccounts snapshot
+pub async fn supervise(source: Arc<dyn AccountSource>, rpc_url: String, sink: Arc<FeedSink>) {
+    let name = source.name();
+    let rpc_client = RpcClient::new(rpc_url);
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+    let mut gap = None;
+
+    loop {
+        match stream_accounts(
+            source.as_ref(),
+            &name,
+            &rpc_client,
+            &sink,
+            &mut backoff,
+            gap.take(),
+        )
+        .await
+        {
+            Ok(()) => eprintln!("Feed {} closed", name),
+            Err(err) => eprintln!("Feed {} failed: {}", name, err),
+        }
+
+        gap = Some(Gap {
+            since: Instant::now(),
+            last_slot: sink.markets.latest_slot(),
+        });
+
+        let delay = backoff.next_delay();
+        eprintln!("Reconnecting to {} in {:?}", name, delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Subscribe to every pool and publish updates until the stream ends
+async fn stream_accounts(
+    source: &dyn AccountSource,
+    name: &str,
+    rpc_client: &RpcClient,
+    sink: &FeedSink,
+    backoff: &mut Backoff,
+    gap: Option<Gap>,
+) -> Result<(), anyhow::Error> {
+    let mut accounts_changed = sink.watch_accounts();
+    accounts_changed.mark_unchanged();
+    let mut accounts = sink.accounts().into_iter().collect::<HashSet<_>>();
+    let mut subscription = source.subscribe(accounts.iter().copied().collect()).await?;
+
+    // Subscriptions are live, so nothing written after the snapshot is missed
+    let snapshot =
+        fetch_snapshot(rpc_client, &accounts.iter().copied().collect::<Vec<_>>()).await?;
+    if let Some(gap) = gap {
+        let snapshot_slot = snapshot.iter().map(|update| update.slot).max().unwrap_or(0);
+        eprintln!(
+            "Reconnected to {} after {:?}, {} slots missed (last seen {}, snapshot at {})",
+            name,
+            gap.since.elapsed(),
+            snapshot_slot.saturating_sub(gap.last_slot),
+            gap.last_slot,
+            snapshot_slot
+        );
+    }
+    for update in snapshot {
+        sink.publish(name, update);
+    }
+
+    // Connections that drop right after accepting keep backing off
+    let healthy = tokio::time::sleep(HEALTHY_AFTER);
+    tokio::pin!(healthy);
+    let mut is_healthy = false;
+
+    loop {
+        tokio::select! {
+            () = &mut healthy, if !is_healthy => {
+                is_healthy = true;
+                backoff.reset();
+            }
+            update = subscription.updates.next() => match update {
+                Some(update) => sink.publish(name, update),
+                None => return Ok(()),
+            },
+            Ok(()) = accounts_changed.changed() => {
+                // Follow the accounts pools depend on as prices move
+                let added = follow_accounts(&subscription, &mut accounts, sink);
+                if added.is_empty() {
+                    continue;
+                }
+
+                match fetch_snapshot(rpc_client, &added).await {
+                    Ok(snapshot) => {
+                        for update in snapshot {
+                            sink.publish(name, update);
+                        }
+                    }
+                    // They still arrive with their next write
+                    Err(err) => eprintln!(
+                        "Feed {} snapshot of {} added accounts failed: {}",
+                        name,
+                        added.len(),
+                        err
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Move `subscription` from `accounts` to what the sink needs now, returning the accounts
+/// it didn't cover before
+pub fn follow_accounts(
+    subscription: &Subscription,
+    accounts: &mut HashSet<Pubkey>,
+    sink: &FeedSink,
+) -> Vec<Pubkey> {
+    let current = sink.accounts().into_iter().collect::<HashSet<_>>();
+    let change = SubscriptionChange::between(accounts, &current);
+    *accounts = current;
+    if change.is_empty() {
+        return Vec::new();
+    }
+
+    let added = change.added.clone();
+    // Only fails once the source is gone, its stream ends right after
+    let _ = subscription.changes.send(change);
+    added
+}
+
+/// Exponential delay between reconnection attempts
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Delay to wait now, doubling the next one
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pools_struct::orca::WhirlpoolState;
+    use crate::pools_struct::structs::DexType;
+    use borsh::BorshDeserialize;
+
+    fn whirlpool() -> Pool {
+        Pool {
+            name: "SOL-USDC".to_string(),
+            pool_id: Pubkey::new_unique(),
+            pool: DexType::Orca,
+            fee_rate: None,
+        }
+    }
+
+    fn update(pubkey: Pubkey, slot: u64, write_version: Option<u64>) -> AccountUpdate {
+        let state = WhirlpoolState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        AccountUpdate {
+            pubkey,
+            slot,
+            write_version,
+            data: borsh::to_vec(&state).unwrap(),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+
+        let delays = (0..5).map(|_| backoff.next_delay()).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000].map(Duration::from_millis)
+        );
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn subscription_change_lists_added_and_removed_accounts() {
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+        let previous = HashSet::from([a, b]);
+        let current = HashSet::from([b, c]);
+
+        let change = SubscriptionChange::between(&previous, &current);
+        assert_eq!(change.added, vec![c]);
+        assert_eq!(change.removed, vec![a]);
+        assert!(SubscriptionChange::between(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn publish_keeps_the_first_arrival_of_each_slot() {
+        let pool = whirlpool();
+        let queue = Arc::new(UpdateQueue::new());
+        let sink = FeedSink::new(
+            std::slice::from_ref(&pool),
+            Arc::new(MarketState::new()),
+            queue.clone(),
+        );
+
+        sink.publish("grpc", update(pool.pool_id, 10, Some(1)));
+        assert_eq!(queue.try_recv(), Some(pool.pool_id));
+
+        // The same slot from another source and an older one are recorded, not queued
+        sink.publish("websocket", update(pool.pool_id, 10, None));
+        sink.publish("websocket", update(pool.pool_id, 9, None));
+        assert_eq!(queue.try_recv(), None);
+
+        // Accounts no pool depends on are dropped without a trace
+        sink.publish("websocket", update(Pubkey::new_unique(), 11, None));
+
+        let stats = sink.stats();
+        assert_eq!(
+            stats
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["grpc", "websocket"]
+        );
+        let (grpc, websocket) = (stats[0].1, stats[1].1);
+        assert_eq!((grpc.updates, grpc.first), (1, 1));
+        assert_eq!(
+            (
+                websocket.updates,
+                websocket.first,
+                websocket.duplicates,
+                websocket.stale
+            ),
+            (2, 0, 1, 1)
+        );
+        assert!(sink.last_update_except("grpc").is_some());
+    }
+
+    #[test]
+    fn added_pools_wake_the_subscriptions() {
+        let pool = whirlpool();
+        let sink = FeedSink::new(
+            &[],
+            Arc::new(MarketState::new()),
+            Arc::new(UpdateQueue::new()),
+        );
+        let mut accounts_changed = sink.watch_accounts();
+
+        assert!(sink.add_pool(pool.clone()));
+        assert!(accounts_changed.has_changed().unwrap());
+        assert!(sink.accounts().contains(&pool.pool_id));
+
+        accounts_changed.mark_unchanged();
+        assert!(!sink.add_pool(pool));
+        assert!(!accounts_changed.has_changed().unwrap());
+    }
+
+    #[test]
+    fn average_behind_divides_by_every_duplicate() {
+        let duplicates = u64::from(u32::MAX) * 2;
+        let stats = SourceStats {
+            duplicates,
+            total_behind: Duration::from_millis(duplicates),
+            ..SourceStats::default()
+        };
+        assert_eq!(stats.average_behind(), Duration::from_millis(1));
+        assert_eq!(SourceStats::default().average_behind(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,373 @@
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, StreamExt};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::feed::{
+    endpoint_label, fetch_snapshot, follow_accounts, AccountSource, AccountUpdate, FeedSink,
+    Subscription, SubscriptionChange,
+};
+use crate::market::hash_data;
+
+/// getMultipleAccounts snapshots of every account, in batches of 100, at a fixed interval
+pub struct PollingSource {
+    rpc_url: String,
+    rpc_client: Arc<RpcClient>,
+    pub interval: Duration,
+}
+
+impl PollingSource {
+    pub fn new(rpc_url: String, interval: Duration) -> Self {
+        Self {
+            rpc_client: Arc::new(RpcClient::new(rpc_url.clone())),
+            rpc_url,
+            interval,
+        }
+    }
+}
+
+impl AccountSource for PollingSource {
+    fn name(&self) -> String {
+        format!("{} (polling)", endpoint_label(&self.rpc_url))
+    }
+
+    fn subscribe(
+        &self,
+        accounts: Vec<Pubkey>,
+    ) -> BoxFuture<'_, Result<Subscription, anyhow::Error>> {
+        let rpc_client = self.rpc_client.clone();
+        let interval = self.interval;
+
+        Box::pin(async move {
+            let ticker = tokio::time::interval(interval);
+            let accounts = accounts.into_iter().collect::<HashSet<_>>();
+            let (changes_tx, changes) = mpsc::unbounded_channel::<SubscriptionChange>();
+
+            // A failed poll ends the stream so the supervisor backs off
+            let snapshots = stream::unfold(
+                (ticker, accounts, changes),
+                move |(mut ticker, mut accounts, mut changes)| {
+                    let rpc_client = rpc_client.clone();
+
+                    async move {
+                        ticker.tick().await;
+                        // Changes apply from the next poll on
+                        while let Ok(change) = changes.try_recv() {
+                            for account in change.removed {
+                                accounts.remove(&account);
+                            }
+                            accounts.extend(change.added);
+                        }
+
+                        let polled = accounts.iter().copied().collect::<Vec<_>>();
+                        match fetch_snapshot(&rpc_client, &polled).await {
+                            Ok(updates) => {
+                                Some((stream::iter(updates), (ticker, accounts, changes)))
+                            }
+                            Err(err) => {
+                                eprintln!("Error:{}", err);
+                                None
+                            }
+                        }
+                    }
+                },
+            );
+
+            Ok(Subscription {
+                updates: snapshots.flatten().boxed(),
+                changes: changes_tx,
+            })
+        })
+    }
+}
+
+/// How streamed state compared with a snapshot
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Consistency {
+    /// Same data, or the stream is already past the snapshot slot
+    pub consistent: u64,
+    /// Snapshot is newer and differs, the stream missed a write
+    pub missed: u64,
+    /// Accounts the stream never delivered
+    pub missing: u64,
+}
+
+/// Compare what the streams gave us with a fresh snapshot, publishing whatever they missed
+pub async fn check_consistency(
+    source: &PollingSource,
+    sink: &FeedSink,
+) -> Result<Consistency, anyhow::Error> {
+    let name = source.name();
+    let snapshot = fetch_snapshot(&source.rpc_client, &sink.pool_accounts()).await?;
+    let mut consistency = Consistency::default();
+
+    for update in snapshot {
+        match sink.markets.get(&update.pubkey) {
+            Some(entry) if entry.slot >= update.slot => consistency.consistent += 1,
+            Some(entry) if entry.data_hash == hash_data(&update.data) => {
+                consistency.consistent += 1;
+                sink.markets.confirm(&update.pubkey, update.slot);
+            }
+            Some(_) => {
+                consistency.missed += 1;
+                sink.publish(&name, update);
+            }
+            None => {
+                consistency.missing += 1;
+                sink.publish(&name, update);
+            }
+        }
+    }
+
+    Ok(consistency)
+}
+
+/// Next update of the polling subscription, waits forever while there is none
+async fn next_polled(
+    polling: &mut Option<(Subscription, HashSet<Pubkey>)>,
+) -> Option<AccountUpdate> {
+    match polling {
+        Some((subscription, _)) => subscription.updates.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Poll through `source`'s own subscription whenever no stream has delivered anything for
+/// `stale_after`, and check the streamed state against a snapshot every `check_interval`
+pub async fn fallback(
+    source: Arc<PollingSource>,
+    sink: Arc<FeedSink>,
+    stale_after: Duration,
+    check_interval: Duration,
+) {
+    let name = source.name();
+    let started = Instant::now();
+    let mut last_check = Instant::now();
+    let mut ticker = tokio::time::interval(source.interval);
+    let mut accounts_changed = sink.watch_accounts();
+    // Open only while the streams are down, with the accounts it covers
+    let mut polling: Option<(Subscription, HashSet<Pubkey>)> = None;
+
+    loop {
+        tokio::select! {
+            update = next_polled(&mut polling), if polling.is_some() => {
+                match update {
+                    Some(update) => sink.publish(&name, update),
+                    None => {
+                        eprintln!("Polling {} failed, retrying", name);
+                        polling = None;
+                    }
+                }
+            }
+            Ok(()) = accounts_changed.changed(), if polling.is_some() => {
+                if let Some((subscription, accounts)) = &mut polling {
+                    follow_accounts(subscription, accounts, &sink);
+                }
+            }
+            _ = ticker.tick() => {
+                let last_streamed = sink.last_update_except(&name).unwrap_or(started);
+                let streams_down = last_streamed.elapsed() > stale_after;
+
+                match (polling.is_some(), streams_down) {
+                    (false, true) => {
+                        eprintln!(
+                            "No stream update for {:?}, polling",
+                            last_streamed.elapsed()
+                        );
+                        accounts_changed.mark_unchanged();
+                        let accounts = sink.accounts();
+                        match source.subscribe(accounts.clone()).await {
+                            Ok(subscription) => {
+                                polling = Some((subscription, accounts.into_iter().collect()))
+                            }
+                            Err(err) => eprintln!("Error:{}", err),
+                        }
+                    }
+                    (true, false) => {
+                        eprintln!("Streams are back, polling stopped");
+                        polling = None;
+                    }
+                    (false, false) if last_check.elapsed() >= check_interval => {
+                        last_check = Instant::now();
+
+                        match check_consistency(&source, &sink).await {
+                            Ok(consistency) => println!(
+                                "Consistency check: {} consistent, {} missed, {} missing",
+                                consistency.consistent, consistency.missed, consistency.missing
+                            ),
+                            Err(err) => eprintln!("Error:{}", err),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::queue::UpdateQueue;
+    use crate::market::MarketState;
+    use crate::pools_struct::orca::WhirlpoolState;
+    use crate::pools_struct::structs::{DexType, Pool};
+    use base64::engine::general_purpose;
+    use base64::Engine;
+    use borsh::BorshDeserialize;
+    use solana_client::rpc_client::Mocks;
+    use solana_client::rpc_request::RpcRequest;
+
+    fn whirlpool_data(sqrt_price: u128) -> Vec<u8> {
+        let mut state = WhirlpoolState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        state.sqrt_price = sqrt_price;
+        borsh::to_vec(&state).unwrap()
+    }
+
+    fn sink(pool_id: Pubkey) -> FeedSink {
+        let pool = Pool {
+            name: "SOL-USDC".to_string(),
+            pool_id,
+            pool: DexType::Orca,
+            fee_rate: None,
+        };
+        FeedSink::new(
+            &[pool],
+            Arc::new(MarketState::new()),
+            Arc::new(UpdateQueue::new()),
+        )
+    }
+
+    /// Polling through a mock RPC answering one getMultipleAccounts of `polled` accounts,
+    /// all holding `data` at `slot`
+    fn polling(slot: u64, data: &[u8], polled: usize, interval: Duration) -> PollingSource {
+        let account = serde_json::json!({
+            "lamports": 1,
+            "data": [general_purpose::STANDARD.encode(data), "base64"],
+            "owner": DexType::Orca.program_id().to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": data.len(),
+        });
+        let mocks = Mocks::from([(
+            RpcRequest::GetMultipleAccounts,
+            serde_json::json!({ "context": { "slot": slot }, "value": vec![account; polled] }),
+        )]);
+
+        PollingSource {
+            rpc_url: "succeeds".to_string(),
+            rpc_client: Arc::new(RpcClient::new_mock_with_mocks(
+                "succeeds".to_string(),
+                mocks,
+            )),
+            interval,
+        }
+    }
+
+    fn streamed(pool_id: Pubkey, slot: u64, data: Vec<u8>) -> AccountUpdate {
+        AccountUpdate {
+            pubkey: pool_id,
+            slot,
+            write_version: Some(1),
+            data,
+        }
+    }
+
+    #[tokio::test]
+    async fn consistency_check_publishes_what_the_stream_missed() {
+        let pool_id = Pubkey::new_unique();
+        let sink = sink(pool_id);
+        sink.publish("grpc", streamed(pool_id, 10, whirlpool_data(1 << 64)));
+
+        // The snapshot moved on without the stream
+        let drifted = whirlpool_data(2 << 64);
+        let source = polling(12, &drifted, 1, Duration::from_secs(1));
+        let consistency = check_consistency(&source, &sink).await.unwrap();
+        assert_eq!(
+            (
+                consistency.consistent,
+                consistency.missed,
+                consistency.missing
+            ),
+            (0, 1, 0)
+        );
+        assert_eq!(sink.markets.get(&pool_id).unwrap().slot, 12);
+
+        // Nothing written since, a later snapshot only confirms the state
+        let source = polling(15, &drifted, 1, Duration::from_secs(1));
+        let consistency = check_consistency(&source, &sink).await.unwrap();
+        assert_eq!(
+            (
+                consistency.consistent,
+                consistency.missed,
+                consistency.missing
+            ),
+            (1, 0, 0)
+        );
+        assert_eq!(sink.markets.get(&pool_id).unwrap().slot, 12);
+        assert_eq!(sink.markets.confirmed_slot(&pool_id), Some(15));
+    }
+
+    #[tokio::test]
+    async fn consistency_check_counts_pools_never_streamed() {
+        let pool_id = Pubkey::new_unique();
+        let sink = sink(pool_id);
+
+        let source = polling(12, &whirlpool_data(1 << 64), 1, Duration::from_secs(1));
+        let consistency = check_consistency(&source, &sink).await.unwrap();
+        assert_eq!(
+            (
+                consistency.consistent,
+                consistency.missed,
+                consistency.missing
+            ),
+            (0, 0, 1)
+        );
+        assert!(sink.markets.get(&pool_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn fallback_polls_once_the_streams_go_stale() {
+        let pool_id = Pubkey::new_unique();
+        let sink = Arc::new(sink(pool_id));
+        sink.publish("grpc", streamed(pool_id, 10, whirlpool_data(1 << 64)));
+
+        // Polling covers the pool and the vaults it depends on, in no set order
+        let polled = sink.accounts().into_iter().collect::<HashSet<_>>().len();
+        let source = Arc::new(polling(
+            20,
+            &whirlpool_data(2 << 64),
+            polled,
+            Duration::from_millis(10),
+        ));
+        let name = source.name();
+        let stale_after = Duration::from_millis(300);
+        let fallback = tokio::spawn(fallback(
+            source,
+            sink.clone(),
+            stale_after,
+            Duration::from_secs(3600),
+        ));
+
+        // The stream is still fresh, nothing is polled yet
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(sink.markets.get(&pool_id).unwrap().slot, 10);
+
+        let polled = tokio::time::timeout(Duration::from_secs(5), async {
+            while sink.markets.get(&pool_id).unwrap().slot != 20 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        fallback.abort();
+
+        assert!(polled.is_ok(), "polling never took over");
+        let stats = sink.stats();
+        let (_, polling) = stats.iter().find(|(source, _)| *source == name).unwrap();
+        assert_eq!(polling.first, 1);
+    }
+}
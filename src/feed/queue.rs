@@ -0,0 +1,134 @@
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// How many pool updates went through the queue and how many were merged into a pending one
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QueueStats {
+    pub pushed: u64,
+    /// Updates of a pool already waiting, the strategy reads the latest state anyway
+    pub coalesced: u64,
+    pub pending: usize,
+}
+
+#[derive(Default)]
+struct QueueState {
+    order: VecDeque<Pubkey>,
+    pending: HashSet<Pubkey>,
+    stats: QueueStats,
+}
+
+/// Updated pools waiting for the strategy, each at most once, in the order they first
+/// changed. The queue holds pool ids only, the state is read from the market when popped,
+/// so it never grows past the number of pools and the strategy always sees the freshest state
+#[derive(Default)]
+pub struct UpdateQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+impl UpdateQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, pool_id: Pubkey) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.pushed += 1;
+
+        if !state.pending.insert(pool_id) {
+            state.stats.coalesced += 1;
+            return;
+        }
+
+        state.order.push_back(pool_id);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// Next updated pool, waiting for one if none is pending
+    pub async fn recv(&self) -> Pubkey {
+        loop {
+            if let Some(pool_id) = self.try_recv() {
+                return pool_id;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<Pubkey> {
+        let mut state = self.state.lock().unwrap();
+        let pool_id = state.order.pop_front()?;
+        state.pending.remove(&pool_id);
+        Some(pool_id)
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.state.lock().unwrap();
+        QueueStats {
+            pending: state.order.len(),
+            ..state.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn pending_pools_are_coalesced() {
+        let queue = UpdateQueue::new();
+        let [a, b] = [(); 2].map(|_| Pubkey::new_unique());
+
+        queue.push(a);
+        queue.push(b);
+        queue.push(a);
+        queue.push(a);
+
+        let stats = queue.stats();
+        assert_eq!((stats.pushed, stats.coalesced, stats.pending), (4, 2, 2));
+        assert_eq!(queue.try_recv(), Some(a));
+        assert_eq!(queue.try_recv(), Some(b));
+        assert_eq!(queue.try_recv(), None);
+    }
+
+    #[test]
+    fn pools_come_out_in_the_order_they_first_changed() {
+        let queue = UpdateQueue::new();
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+
+        for pool_id in [b, a, b, c, a] {
+            queue.push(pool_id);
+        }
+        assert_eq!([(); 3].map(|_| queue.try_recv().unwrap()), [b, a, c]);
+
+        // Popped pools queue again on their next change
+        queue.push(b);
+        assert_eq!(queue.try_recv(), Some(b));
+        assert_eq!(queue.stats().pending, 0);
+        assert_eq!(queue.stats().coalesced, 2);
+    }
+
+    #[tokio::test]
+    async fn recv_waits_for_a_push() {
+        let queue = Arc::new(UpdateQueue::new());
+        let pool_id = Pubkey::new_unique();
+
+        let receiver = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.recv().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        queue.push(pool_id);
+
+        let received = tokio::time::timeout(Duration::from_secs(1), receiver)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, pool_id);
+    }
+}
@@ -0,0 +1,189 @@
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream, SelectAll, StreamExt};
+use solana_account_decoder::{UiAccount, UiAccountData, UiAccountEncoding};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_client::rpc_response::Response as RpcResponse;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::feed::{endpoint_label, AccountSource, AccountUpdate, Subscription, SubscriptionChange};
+use crate::utils::decode_account_data;
+
+/// One `accountSubscribe` per account over a websocket, added and dropped one by one as
+/// the accounts change
+pub struct WebsocketSource {
+    pub url: String,
+}
+
+impl WebsocketSource {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+impl AccountSource for WebsocketSource {
+    fn name(&self) -> String {
+        endpoint_label(&self.url)
+    }
+
+    fn subscribe(
+        &self,
+        accounts: Vec<Pubkey>,
+    ) -> BoxFuture<'_, Result<Subscription, anyhow::Error>> {
+        Box::pin(async move {
+            let (ready_tx, ready_rx) = oneshot::channel();
+            let (tx, rx) = mpsc::unbounded_channel();
+            let (changes_tx, changes) = mpsc::unbounded_channel();
+
+            // Subscription streams borrow the client, so both live in the forwarding task
+            tokio::spawn(forward_updates(
+                self.url.clone(),
+                accounts,
+                changes,
+                ready_tx,
+                tx,
+            ));
+
+            ready_rx.await??;
+
+            Ok(Subscription {
+                updates: stream::unfold(rx, |mut rx| async move {
+                    let update = rx.recv().await?;
+                    Some((update, rx))
+                })
+                .boxed(),
+                changes: changes_tx,
+            })
+        })
+    }
+}
+
+type UnsubscribeFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+/// Account notification tagged with its subscription, None marks the subscription's end
+type Notification = (Pubkey, u64, Option<RpcResponse<UiAccount>>);
+
+/// Open the `accountSubscribe` of `pubkey`, its notifications tagged with `id`
+async fn subscribe_account<'a>(
+    client: &'a PubsubClient,
+    pubkey: Pubkey,
+    id: u64,
+) -> Result<(BoxStream<'a, Notification>, UnsubscribeFn), anyhow::Error> {
+    let config = RpcAccountInfoConfig {
+        commitment: Some(CommitmentConfig::confirmed()),
+        data_slice: None,
+        encoding: Some(UiAccountEncoding::Base64Zstd),
+        min_context_slot: None,
+    };
+
+    let (stream, unsubscribe) = client.account_subscribe(&pubkey, Some(config)).await?;
+    let stream = stream
+        .map(move |response| (pubkey, id, Some(response)))
+        .chain(stream::once(async move { (pubkey, id, None) }))
+        .boxed();
+
+    Ok((stream, unsubscribe))
+}
+
+async fn forward_updates(
+    url: String,
+    accounts: Vec<Pubkey>,
+    mut changes: mpsc::UnboundedReceiver<SubscriptionChange>,
+    ready: oneshot::Sender<Result<(), anyhow::Error>>,
+    tx: mpsc::UnboundedSender<AccountUpdate>,
+) {
+    let client = match PubsubClient::new(&url).await {
+        Ok(client) => client,
+        Err(err) => {
+            let _ = ready.send(Err(err.into()));
+            return;
+        }
+    };
+
+    // Live subscriptions by account, ids tell an unsubscribed stream from a dropped one
+    let mut active: HashMap<Pubkey, (u64, UnsubscribeFn)> = HashMap::new();
+    let mut next_id = 0;
+    let mut updates = SelectAll::new();
+
+    for pubkey in accounts {
+        match subscribe_account(&client, pubkey, next_id).await {
+            Ok((stream, unsubscribe)) => {
+                updates.push(stream);
+                active.insert(pubkey, (next_id, unsubscribe));
+                next_id += 1;
+            }
+            Err(err) => {
+                let _ = ready.send(Err(err));
+                return;
+            }
+        }
+    }
+
+    if ready.send(Ok(())).is_err() {
+        return;
+    }
+
+    loop {
+        let (pubkey, response) = tokio::select! {
+            // The supervisor resubscribed or gave up, drop our subscriptions with the client
+            _ = tx.closed() => return,
+            Some(change) = changes.recv() => {
+                for pubkey in change.removed {
+                    if let Some((_, unsubscribe)) = active.remove(&pubkey) {
+                        unsubscribe().await;
+                    }
+                }
+                for pubkey in change.added {
+                    if active.contains_key(&pubkey) {
+                        continue;
+                    }
+                    match subscribe_account(&client, pubkey, next_id).await {
+                        Ok((stream, unsubscribe)) => {
+                            updates.push(stream);
+                            active.insert(pubkey, (next_id, unsubscribe));
+                            next_id += 1;
+                        }
+                        Err(err) => eprintln!("Can't subscribe to {}: {}", pubkey, err),
+                    }
+                }
+                continue;
+            }
+            update = updates.next() => match update {
+                Some((pubkey, _, Some(response))) => (pubkey, response),
+                // Ending on its own rather than through an unsubscribe, the connection is gone
+                Some((pubkey, id, None)) => {
+                    if active.get(&pubkey).is_some_and(|(active_id, _)| *active_id == id) {
+                        return;
+                    }
+                    continue;
+                }
+                None => return,
+            },
+        };
+
+        match &response.value.data {
+            UiAccountData::Binary(encoded, _) | UiAccountData::LegacyBinary(encoded) => {
+                match decode_account_data(encoded) {
+                    Ok(data) => {
+                        let update = AccountUpdate {
+                            pubkey,
+                            slot: response.context.slot,
+                            write_version: None,
+                            data,
+                        };
+
+                        if tx.send(update).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => eprintln!("Error:{}", err),
+                }
+            }
+            _ => {
+                eprintln!("Error")
+            }
+        }
+    }
+}
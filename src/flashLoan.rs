@@ -2,43 +2,51 @@ use solana_sdk::message::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 
+use crate::kamino::{
+    kamino_program_id, lending_market_authority, referrer_token_state, KaminoReserve,
+};
+
 // Official Kamino discriminators from their GitHub
 const FLASH_BORROW_DISCRIMINATOR: [u8; 8] = [135, 231, 52, 167, 7, 52, 212, 193];
 const FLASH_REPAY_DISCRIMINATOR: [u8; 8] = [185, 117, 0, 203, 96, 245, 180, 186];
 
-pub fn flash_loan_instruction(payer: &Pubkey, user_ata: &Pubkey, data: Vec<u8>) -> Instruction {
-    let program_id = Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD").unwrap();
-    let lending_market = Pubkey::from_str("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF").unwrap();
-    let lending_market_authority =
-        Pubkey::from_str("9DrvZvyWh1HuAoZxvYWMvkf2XCzryCpGgHqrMjyDWpmo").unwrap();
-    let reserve = Pubkey::from_str("d4A2prbA2whesmvHaL88BH6Ewn5N4bTSU2Ze8P6Bc4Q").unwrap();
-    let reserve_liquidity_mint =
-        Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
-    let reserve_liquidity =
-        Pubkey::from_str("GafNuUXj9rxGLn4y79dPu6MHSuPWeJR6UtTWuexpGh3U").unwrap();
-    let reserve_liquidity_fee_receiver =
-        Pubkey::from_str("3JNof8s453bwG5UqiXBLJc77NRQXezYYEBbk3fqnoKph").unwrap();
-    let referrer_token_state =
-        Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD").unwrap();
-    let referrer_account = Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD").unwrap();
+/// Without a referrer both referrer accounts are set to the program id, Anchor's "none" sentinel
+pub fn flash_loan_instruction(
+    payer: &Pubkey,
+    user_ata: &Pubkey,
+    reserve: &KaminoReserve,
+    referrer: Option<&Pubkey>,
+    data: Vec<u8>,
+) -> Instruction {
+    let program_id = kamino_program_id();
+    let lending_market_authority = lending_market_authority(&reserve.lending_market);
+    let (referrer_token_state, referrer_account) = match referrer {
+        Some(referrer) => (
+            AccountMeta::new(referrer_token_state(referrer, &reserve.reserve), false),
+            AccountMeta::new_readonly(*referrer, false),
+        ),
+        None => (
+            AccountMeta::new_readonly(program_id, false),
+            AccountMeta::new_readonly(program_id, false),
+        ),
+    };
     let sysvar_info = Pubkey::from_str("Sysvar1nstructions1111111111111111111111111").unwrap();
-    let token_program = Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap();
 
     Instruction {
         program_id,
         accounts: vec![
             AccountMeta::new(*payer, true),
             AccountMeta::new_readonly(lending_market_authority, false),
-            AccountMeta::new_readonly(lending_market, false), //
-            AccountMeta::new(reserve, false),
-            AccountMeta::new_readonly(reserve_liquidity_mint, false),
-            AccountMeta::new(reserve_liquidity, false), // source for borrow, destination for repay
+            AccountMeta::new_readonly(reserve.lending_market, false), //
+            AccountMeta::new(reserve.reserve, false),
+            AccountMeta::new_readonly(reserve.mint, false),
+            AccountMeta::new(reserve.liquidity_supply, false), // source for borrow, destination for repay
             AccountMeta::new(*user_ata, false),
-            AccountMeta::new(reserve_liquidity_fee_receiver, false),
-            AccountMeta::new_readonly(referrer_token_state, false),
-            AccountMeta::new_readonly(referrer_account, false),
+            AccountMeta::new(reserve.fee_receiver, false),
+            referrer_token_state,
+            referrer_account,
             AccountMeta::new_readonly(sysvar_info, false),
-            AccountMeta::new_readonly(token_program, false),
+            AccountMeta::new_readonly(reserve.token_program, false),
         ],
         data,
     }
@@ -47,19 +55,23 @@ pub fn flash_loan_instruction(payer: &Pubkey, user_ata: &Pubkey, data: Vec<u8>)
 pub fn borrow_instruction_builder(
     payer: Pubkey,
     user_token_account: Pubkey,
+    reserve: &KaminoReserve,
+    referrer: Option<&Pubkey>,
     liquidity: u64,
 ) -> Instruction {
     let mut data = Vec::with_capacity(16);
     data.extend_from_slice(&FLASH_BORROW_DISCRIMINATOR);
     data.extend_from_slice(&liquidity.to_le_bytes());
 
-    flash_loan_instruction(&payer, &user_token_account, data)
+    flash_loan_instruction(&payer, &user_token_account, reserve, referrer, data)
 }
 
 pub fn repay_instruction_builder(
     payer: solana_sdk::pubkey::Pubkey,
     liquidity: u64,
     user_token_account: solana_sdk::pubkey::Pubkey,
+    reserve: &KaminoReserve,
+    referrer: Option<&Pubkey>,
     borrow_index: u8,
 ) -> Instruction {
     let mut data = Vec::with_capacity(17);
@@ -67,5 +79,97 @@ pub fn repay_instruction_builder(
     data.extend_from_slice(&liquidity.to_le_bytes());
     data.push(borrow_index);
 
-    flash_loan_instruction(&payer, &user_token_account, data)
+    flash_loan_instruction(&payer, &user_token_account, reserve, referrer, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::main_lending_market;
+
+    const REFERRER_TOKEN_STATE_INDEX: usize = 8;
+    const REFERRER_ACCOUNT_INDEX: usize = 9;
+
+    fn wsol_reserve() -> KaminoReserve {
+        KaminoReserve {
+            lending_market: main_lending_market(),
+            reserve: Pubkey::from_str("d4A2prbA2whesmvHaL88BH6Ewn5N4bTSU2Ze8P6Bc4Q").unwrap(),
+            mint: Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap(),
+            liquidity_supply: Pubkey::from_str("GafNuUXj9rxGLn4y79dPu6MHSuPWeJR6UtTWuexpGh3U")
+                .unwrap(),
+            fee_receiver: Pubkey::from_str("3JNof8s453bwG5UqiXBLJc77NRQXezYYEBbk3fqnoKph").unwrap(),
+            token_program: Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap(),
+        }
+    }
+
+    #[test]
+    fn borrow_without_referrer_uses_program_id_sentinel() {
+        let payer = Pubkey::new_unique();
+        let user_ata = Pubkey::new_unique();
+        let reserve = wsol_reserve();
+
+        let instruction = borrow_instruction_builder(payer, user_ata, &reserve, None, 1_000);
+
+        assert_eq!(instruction.accounts.len(), 12);
+        assert_eq!(
+            instruction.accounts[REFERRER_TOKEN_STATE_INDEX],
+            AccountMeta::new_readonly(kamino_program_id(), false)
+        );
+        assert_eq!(
+            instruction.accounts[REFERRER_ACCOUNT_INDEX],
+            AccountMeta::new_readonly(kamino_program_id(), false)
+        );
+        assert_eq!(&instruction.data[..8], &FLASH_BORROW_DISCRIMINATOR);
+        assert_eq!(&instruction.data[8..], &1_000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn repay_with_referrer_uses_referrer_token_state() {
+        let payer = Pubkey::new_unique();
+        let user_ata = Pubkey::new_unique();
+        let referrer = Pubkey::new_unique();
+        let reserve = wsol_reserve();
+
+        let instruction =
+            repay_instruction_builder(payer, 1_000, user_ata, &reserve, Some(&referrer), 2);
+
+        let (expected_token_state, _) = Pubkey::find_program_address(
+            &[b"referrer_acc", referrer.as_ref(), reserve.reserve.as_ref()],
+            &kamino_program_id(),
+        );
+        assert_eq!(
+            instruction.accounts[REFERRER_TOKEN_STATE_INDEX],
+            AccountMeta::new(expected_token_state, false)
+        );
+        assert_eq!(
+            instruction.accounts[REFERRER_ACCOUNT_INDEX],
+            AccountMeta::new_readonly(referrer, false)
+        );
+        assert_eq!(&instruction.data[..8], &FLASH_REPAY_DISCRIMINATOR);
+        assert_eq!(instruction.data[16], 2);
+    }
+
+    #[test]
+    fn referrer_does_not_change_other_accounts() {
+        let payer = Pubkey::new_unique();
+        let user_ata = Pubkey::new_unique();
+        let referrer = Pubkey::new_unique();
+        let reserve = wsol_reserve();
+
+        let without = flash_loan_instruction(&payer, &user_ata, &reserve, None, vec![]);
+        let with = flash_loan_instruction(&payer, &user_ata, &reserve, Some(&referrer), vec![]);
+
+        for (index, (a, b)) in without
+            .accounts
+            .iter()
+            .zip(with.accounts.iter())
+            .enumerate()
+        {
+            if index != REFERRER_TOKEN_STATE_INDEX && index != REFERRER_ACCOUNT_INDEX {
+                assert_eq!(a, b);
+            }
+        }
+        assert_eq!(with.accounts[0], AccountMeta::new(payer, true));
+        assert_eq!(with.accounts[6], AccountMeta::new(user_ata, false));
+    }
 }
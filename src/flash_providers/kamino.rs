@@ -0,0 +1,107 @@
+use solana_sdk::message::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use crate::client::BotRpcClient;
+use crate::flashLoan::{borrow_instruction_builder, repay_instruction_builder};
+use crate::flash_providers::{FlashLoan, FlashLoanProvider};
+use crate::kamino::{ReserveCatalog, ReserveState};
+
+pub struct KaminoProvider {
+    pub reserves: ReserveCatalog,
+    pub referrer: Option<Pubkey>,
+    // Decoded reserve accounts keyed by liquidity mint
+    states: HashMap<Pubkey, ReserveState>,
+}
+
+impl KaminoProvider {
+    pub fn new(reserves: ReserveCatalog, referrer: Option<Pubkey>) -> Self {
+        Self {
+            reserves,
+            referrer,
+            states: HashMap::new(),
+        }
+    }
+}
+
+impl FlashLoanProvider for KaminoProvider {
+    fn name(&self) -> &'static str {
+        "kamino"
+    }
+
+    fn supported_mints(&self) -> Vec<Pubkey> {
+        self.reserves
+            .reserves()
+            .map(|reserve| reserve.mint)
+            .collect()
+    }
+
+    fn fee(&self, mint: &Pubkey, amount: u64) -> Option<u64> {
+        self.states.get(mint)?.flash_loan_fee(amount)
+    }
+
+    fn max_available(&self, mint: &Pubkey) -> u64 {
+        self.states
+            .get(mint)
+            .map(|state| state.max_flash_loan())
+            .unwrap_or(0)
+    }
+
+    fn instruction_counts(&self) -> (usize, usize) {
+        (1, 1)
+    }
+
+    fn borrow_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some(reserve) = self.reserves.get(&loan.mint) else {
+            return vec![];
+        };
+
+        vec![borrow_instruction_builder(
+            loan.payer,
+            loan.user_token_account,
+            reserve,
+            self.referrer.as_ref(),
+            loan.amount,
+        )]
+    }
+
+    fn repay_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some(reserve) = self.reserves.get(&loan.mint) else {
+            return vec![];
+        };
+
+        vec![repay_instruction_builder(
+            loan.payer,
+            loan.amount,
+            loan.user_token_account,
+            reserve,
+            self.referrer.as_ref(),
+            loan.borrow_index,
+        )]
+    }
+
+    fn refresh(&mut self, rpc_client: &BotRpcClient) -> Result<(), anyhow::Error> {
+        let reserves = self.reserves.reserves().cloned().collect::<Vec<_>>();
+        let addresses = reserves
+            .iter()
+            .map(|reserve| reserve.reserve)
+            .collect::<Vec<_>>();
+        let accounts = rpc_client.connection.get_multiple_accounts(&addresses)?;
+
+        for (reserve, account) in reserves.iter().zip(accounts) {
+            let Some(account) = account else {
+                eprintln!("Kamino reserve {} not found", reserve.reserve);
+                continue;
+            };
+
+            match ReserveState::decode(&account.data) {
+                Ok(state) => {
+                    self.states.insert(reserve.mint, state);
+                }
+                Err(err) => eprintln!("Error decoding reserve {}: {}", reserve.reserve, err),
+            }
+        }
+
+        Ok(())
+    }
+}
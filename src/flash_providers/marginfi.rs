@@ -0,0 +1,256 @@
+use solana_sdk::message::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::client::BotRpcClient;
+use crate::flash_providers::{FlashLoan, FlashLoanProvider};
+use crate::utils::token_account_amount;
+
+// Anchor discriminators, sha256("global:<instruction>")[..8]
+const START_FLASHLOAN_DISCRIMINATOR: [u8; 8] = [14, 131, 33, 220, 81, 186, 180, 107];
+const END_FLASHLOAN_DISCRIMINATOR: [u8; 8] = [105, 124, 201, 106, 153, 2, 8, 156];
+const BORROW_DISCRIMINATOR: [u8; 8] = [4, 126, 116, 53, 48, 5, 212, 31];
+const REPAY_DISCRIMINATOR: [u8; 8] = [79, 209, 172, 177, 222, 51, 173, 151];
+
+// Offset of `mint` in the Bank account, right after the discriminator
+const BANK_MINT_OFFSET: usize = 8;
+
+/// Mint a Bank account lends
+pub fn bank_mint(data: &[u8]) -> Option<Pubkey> {
+    let bytes = data.get(BANK_MINT_OFFSET..BANK_MINT_OFFSET + 32)?;
+    Pubkey::try_from(bytes).ok()
+}
+
+pub fn marginfi_program_id() -> Pubkey {
+    Pubkey::from_str("MFv2hWf31Z9kbCa1snEPYctwafyhdvnV7FZnsebVacA").unwrap()
+}
+
+pub fn main_group() -> Pubkey {
+    Pubkey::from_str("4qp6Fx6tnZkY5Wropq9wUYgtFxXKwE6viZxFHg3rdAG8").unwrap()
+}
+
+#[derive(Debug, Clone)]
+pub struct MarginfiBank {
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub liquidity_vault: Pubkey,
+    pub liquidity_vault_authority: Pubkey,
+}
+
+impl MarginfiBank {
+    pub fn new(bank: Pubkey, mint: Pubkey) -> Self {
+        let program_id = marginfi_program_id();
+
+        Self {
+            bank,
+            mint,
+            liquidity_vault: Pubkey::find_program_address(
+                &[b"liquidity_vault", bank.as_ref()],
+                &program_id,
+            )
+            .0,
+            liquidity_vault_authority: Pubkey::find_program_address(
+                &[b"liquidity_vault_auth", bank.as_ref()],
+                &program_id,
+            )
+            .0,
+        }
+    }
+}
+
+/// MarginFi flash loans are free but go through our own marginfi account,
+/// which must not hold other balances so ending the loan needs no oracles
+pub struct MarginfiProvider {
+    pub group: Pubkey,
+    pub marginfi_account: Pubkey,
+    bank_addresses: Vec<Pubkey>,
+    // Banks and their vault balance keyed by mint
+    banks: HashMap<Pubkey, MarginfiBank>,
+    available: HashMap<Pubkey, u64>,
+}
+
+impl MarginfiProvider {
+    pub fn new(group: Pubkey, marginfi_account: Pubkey, bank_addresses: Vec<Pubkey>) -> Self {
+        Self {
+            group,
+            marginfi_account,
+            bank_addresses,
+            banks: HashMap::new(),
+            available: HashMap::new(),
+        }
+    }
+}
+
+impl FlashLoanProvider for MarginfiProvider {
+    fn name(&self) -> &'static str {
+        "marginfi"
+    }
+
+    fn supported_mints(&self) -> Vec<Pubkey> {
+        self.banks.keys().copied().collect()
+    }
+
+    fn fee(&self, mint: &Pubkey, _amount: u64) -> Option<u64> {
+        self.banks.get(mint).map(|_| 0)
+    }
+
+    fn max_available(&self, mint: &Pubkey) -> u64 {
+        self.available.get(mint).copied().unwrap_or(0)
+    }
+
+    fn instruction_counts(&self) -> (usize, usize) {
+        (2, 2)
+    }
+
+    fn borrow_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some(bank) = self.banks.get(&loan.mint) else {
+            return vec![];
+        };
+        let program_id = marginfi_program_id();
+        let sysvar_info = Pubkey::from_str("Sysvar1nstructions1111111111111111111111111").unwrap();
+        let token_program = Pubkey::new_from_array(spl_token::ID.to_bytes());
+
+        let mut start_data = Vec::with_capacity(16);
+        start_data.extend_from_slice(&START_FLASHLOAN_DISCRIMINATOR);
+        start_data.extend_from_slice(&(loan.end_index as u64).to_le_bytes());
+
+        let mut borrow_data = Vec::with_capacity(16);
+        borrow_data.extend_from_slice(&BORROW_DISCRIMINATOR);
+        borrow_data.extend_from_slice(&loan.amount.to_le_bytes());
+
+        vec![
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(self.marginfi_account, false),
+                    AccountMeta::new_readonly(loan.payer, true),
+                    AccountMeta::new_readonly(sysvar_info, false),
+                ],
+                data: start_data,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(self.group, false),
+                    AccountMeta::new(self.marginfi_account, false),
+                    AccountMeta::new_readonly(loan.payer, true),
+                    AccountMeta::new(bank.bank, false),
+                    AccountMeta::new(loan.user_token_account, false),
+                    AccountMeta::new(bank.liquidity_vault_authority, false),
+                    AccountMeta::new(bank.liquidity_vault, false),
+                    AccountMeta::new_readonly(token_program, false),
+                ],
+                data: borrow_data,
+            },
+        ]
+    }
+
+    fn repay_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some(bank) = self.banks.get(&loan.mint) else {
+            return vec![];
+        };
+        let program_id = marginfi_program_id();
+        let token_program = Pubkey::new_from_array(spl_token::ID.to_bytes());
+
+        let mut repay_data = Vec::with_capacity(18);
+        repay_data.extend_from_slice(&REPAY_DISCRIMINATOR);
+        repay_data.extend_from_slice(&loan.amount.to_le_bytes());
+        // repay_all: Some(true) closes the balance so nothing is left to health check
+        repay_data.extend_from_slice(&[1, 1]);
+
+        vec![
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(self.group, false),
+                    AccountMeta::new(self.marginfi_account, false),
+                    AccountMeta::new_readonly(loan.payer, true),
+                    AccountMeta::new(bank.bank, false),
+                    AccountMeta::new(loan.user_token_account, false),
+                    AccountMeta::new(bank.liquidity_vault, false),
+                    AccountMeta::new_readonly(token_program, false),
+                ],
+                data: repay_data,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(self.marginfi_account, false),
+                    AccountMeta::new_readonly(loan.payer, true),
+                ],
+                data: END_FLASHLOAN_DISCRIMINATOR.to_vec(),
+            },
+        ]
+    }
+
+    fn refresh(&mut self, rpc_client: &BotRpcClient) -> Result<(), anyhow::Error> {
+        let accounts = rpc_client
+            .connection
+            .get_multiple_accounts(&self.bank_addresses)?;
+
+        for (address, account) in self.bank_addresses.iter().zip(accounts) {
+            let mint = account
+                .as_ref()
+                .and_then(|account| bank_mint(&account.data));
+
+            match mint {
+                Some(mint) => {
+                    self.banks.insert(mint, MarginfiBank::new(*address, mint));
+                }
+                None => eprintln!("Error decoding marginfi bank {}", address),
+            }
+        }
+
+        let banks = self.banks.values().cloned().collect::<Vec<_>>();
+        let vaults = banks
+            .iter()
+            .map(|bank| bank.liquidity_vault)
+            .collect::<Vec<_>>();
+        let accounts = rpc_client.connection.get_multiple_accounts(&vaults)?;
+
+        for (bank, account) in banks.iter().zip(accounts) {
+            let amount = account
+                .and_then(|account| token_account_amount(&account.data))
+                .unwrap_or(0);
+
+            self.available.insert(bank.mint, amount);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bank_mint_is_read_after_the_discriminator() {
+        let mint = Pubkey::new_unique();
+        let mut data = vec![0u8; 1_856];
+        // sha256("account:Bank")[..8]
+        data[..8].copy_from_slice(&[142, 49, 166, 242, 50, 66, 97, 188]);
+        data[BANK_MINT_OFFSET..BANK_MINT_OFFSET + 32].copy_from_slice(mint.as_ref());
+
+        assert_eq!(bank_mint(&data), Some(mint));
+        assert_eq!(bank_mint(&data[..39]), None);
+    }
+
+    #[test]
+    fn flash_loans_are_free_for_known_banks() {
+        let mint = Pubkey::new_unique();
+        let mut provider = MarginfiProvider::new(main_group(), Pubkey::new_unique(), vec![]);
+        let bank = MarginfiBank::new(Pubkey::new_unique(), mint);
+        assert_ne!(bank.liquidity_vault, bank.liquidity_vault_authority);
+        provider.banks.insert(mint, bank);
+        provider.available.insert(mint, 9_000);
+
+        assert_eq!(provider.fee(&mint, 1_000_000), Some(0));
+        assert_eq!(provider.max_available(&mint), 9_000);
+
+        let unknown = Pubkey::new_unique();
+        assert_eq!(provider.fee(&unknown, 1_000_000), None);
+        assert_eq!(provider.max_available(&unknown), 0);
+    }
+}
@@ -0,0 +1,152 @@
+pub mod kamino;
+pub mod marginfi;
+pub mod save;
+
+use solana_sdk::message::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::client::BotRpcClient;
+
+/// Where a flash loan sits in the transaction being assembled
+#[derive(Debug, Clone)]
+pub struct FlashLoan {
+    pub payer: Pubkey,
+    pub user_token_account: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Index of the first borrow instruction in the transaction
+    pub borrow_index: u8,
+    /// Index of the last repay instruction in the transaction
+    pub end_index: u8,
+}
+
+pub trait FlashLoanProvider: Send + Sync {
+    /// Get the provider name
+    fn name(&self) -> &'static str;
+    /// Get the mints we can flash borrow
+    fn supported_mints(&self) -> Vec<Pubkey>;
+    /// Get the fee for borrowing `amount`, None when the mint can't be borrowed
+    fn fee(&self, mint: &Pubkey, amount: u64) -> Option<u64>;
+    /// Get the largest amount that can be borrowed right now
+    fn max_available(&self, mint: &Pubkey) -> u64;
+    /// Get the number of (borrow, repay) instructions the loan adds to a transaction
+    fn instruction_counts(&self) -> (usize, usize);
+    /// Build the instructions placed before the swaps
+    fn borrow_instructions(&self, loan: &FlashLoan) -> Vec<Instruction>;
+    /// Build the instructions placed after the swaps
+    fn repay_instructions(&self, loan: &FlashLoan) -> Vec<Instruction>;
+    /// Reload liquidity and fees from chain
+    fn refresh(&mut self, rpc_client: &BotRpcClient) -> Result<(), anyhow::Error>;
+}
+
+/// Cheapest provider that can lend `amount` of `mint` and its fee, providers that can't
+/// tell the fee are skipped rather than assumed free
+pub fn select_provider<'a>(
+    providers: &'a [Box<dyn FlashLoanProvider>],
+    mint: &Pubkey,
+    amount: u64,
+) -> Option<(&'a dyn FlashLoanProvider, u64)> {
+    providers
+        .iter()
+        .filter(|provider| provider.supported_mints().contains(mint))
+        .filter(|provider| provider.max_available(mint) >= amount)
+        .filter_map(|provider| Some((provider.as_ref(), provider.fee(mint, amount)?)))
+        .min_by_key(|(_, fee)| *fee)
+}
+
+pub fn refresh_providers(providers: &mut [Box<dyn FlashLoanProvider>], rpc_client: &BotRpcClient) {
+    for provider in providers.iter_mut() {
+        if let Err(err) = provider.refresh(rpc_client) {
+            eprintln!("Error refreshing {} flash loans: {}", provider.name(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    struct FixedProvider {
+        name: &'static str,
+        mint: Pubkey,
+        fee: Option<u64>,
+        available: u64,
+    }
+
+    impl FlashLoanProvider for FixedProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn supported_mints(&self) -> Vec<Pubkey> {
+            vec![self.mint]
+        }
+
+        fn fee(&self, _mint: &Pubkey, _amount: u64) -> Option<u64> {
+            self.fee
+        }
+
+        fn max_available(&self, _mint: &Pubkey) -> u64 {
+            self.available
+        }
+
+        fn instruction_counts(&self) -> (usize, usize) {
+            (1, 1)
+        }
+
+        fn borrow_instructions(&self, _loan: &FlashLoan) -> Vec<Instruction> {
+            vec![]
+        }
+
+        fn repay_instructions(&self, _loan: &FlashLoan) -> Vec<Instruction> {
+            vec![]
+        }
+
+        fn refresh(&mut self, _rpc_client: &BotRpcClient) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    /// Lends up to `available` of `mint` for a fixed fee
+    pub(crate) fn provider(
+        name: &'static str,
+        mint: Pubkey,
+        fee: Option<u64>,
+        available: u64,
+    ) -> Box<dyn FlashLoanProvider> {
+        Box::new(FixedProvider {
+            name,
+            mint,
+            fee,
+            available,
+        })
+    }
+
+    #[test]
+    fn picks_the_cheapest_provider_that_can_lend() {
+        let mint = Pubkey::new_unique();
+        let providers = vec![
+            provider("expensive", mint, Some(90), 1_000),
+            provider("cheap", mint, Some(10), 1_000),
+            provider("shallow", mint, Some(1), 100),
+            provider("other", Pubkey::new_unique(), Some(0), 1_000),
+        ];
+
+        let (selected, fee) = select_provider(&providers, &mint, 500).unwrap();
+        assert_eq!((selected.name(), fee), ("cheap", 10));
+        assert!(select_provider(&providers, &mint, 5_000).is_none());
+    }
+
+    #[test]
+    fn unknown_fees_are_not_free() {
+        let mint = Pubkey::new_unique();
+        let providers = vec![
+            provider("unknown", mint, None, 1_000),
+            provider("known", mint, Some(50), 1_000),
+        ];
+
+        let (selected, fee) = select_provider(&providers, &mint, 500).unwrap();
+        assert_eq!((selected.name(), fee), ("known", 50));
+        assert!(select_provider(&providers[..1], &mint, 500).is_none());
+    }
+}
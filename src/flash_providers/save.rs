@@ -0,0 +1,322 @@
+use anyhow::bail;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::account::Account;
+use solana_sdk::message::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::client::BotRpcClient;
+use crate::flash_providers::{FlashLoan, FlashLoanProvider};
+
+// Instruction tags of the token-lending program
+const FLASH_BORROW_TAG: u8 = 19;
+const FLASH_REPAY_TAG: u8 = 20;
+
+const WAD: u128 = 1_000_000_000_000_000_000;
+
+// Packed size of a Reserve account and the version the program stamps it with
+pub const RESERVE_LEN: usize = 619;
+const RESERVE_VERSION: u8 = 1;
+
+pub fn save_program_id() -> Pubkey {
+    Pubkey::from_str("So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo").unwrap()
+}
+
+pub fn lending_market_authority(lending_market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[lending_market.as_ref()], &save_program_id()).0
+}
+
+/// Leading part of the packed Save `Reserve` account, up to the fee receiver
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct SaveReserveState {
+    pub version: u8,
+    pub last_update_slot: u64,
+    pub last_update_stale: u8,
+    pub lending_market: Pubkey,
+    pub liquidity_mint: Pubkey,
+    pub liquidity_mint_decimals: u8,
+    pub liquidity_supply: Pubkey,
+    pub liquidity_pyth_oracle: Pubkey,
+    pub liquidity_switchboard_oracle: Pubkey,
+    pub liquidity_available_amount: u64,
+    pub liquidity_borrowed_amount_wads: u128,
+    pub liquidity_cumulative_borrow_rate_wads: u128,
+    pub liquidity_market_price: u128,
+    pub collateral_mint: Pubkey,
+    pub collateral_mint_total_supply: u64,
+    pub collateral_supply: Pubkey,
+    pub optimal_utilization_rate: u8,
+    pub loan_to_value_ratio: u8,
+    pub liquidation_bonus: u8,
+    pub liquidation_threshold: u8,
+    pub min_borrow_rate: u8,
+    pub optimal_borrow_rate: u8,
+    pub max_borrow_rate: u8,
+    pub borrow_fee_wad: u64,
+    pub flash_loan_fee_wad: u64,
+    pub host_fee_percentage: u8,
+    pub deposit_limit: u64,
+    pub borrow_limit: u64,
+    pub fee_receiver: Pubkey,
+}
+
+impl SaveReserveState {
+    /// Decode a Reserve account, anything else of the program or another owner is refused
+    pub fn decode(account: &Account) -> Result<Self, anyhow::Error> {
+        if account.owner != save_program_id() {
+            bail!("Owned by {}, not the Save program", account.owner);
+        }
+        if account.data.len() != RESERVE_LEN {
+            bail!(
+                "{} bytes, a Save reserve is {}",
+                account.data.len(),
+                RESERVE_LEN
+            );
+        }
+        if account.data[0] != RESERVE_VERSION {
+            bail!("Not a Save reserve, version {}", account.data[0]);
+        }
+
+        Ok(SaveReserveState::deserialize(&mut &account.data[..])?)
+    }
+
+    pub fn borrowed_amount(&self) -> u64 {
+        u64::try_from(self.liquidity_borrowed_amount_wads / WAD).unwrap_or(u64::MAX)
+    }
+
+    /// Largest amount we can flash borrow right now, within the reserve's borrow limit
+    pub fn max_flash_loan(&self) -> u64 {
+        let borrow_capacity = self.borrow_limit.saturating_sub(self.borrowed_amount());
+
+        self.liquidity_available_amount.min(borrow_capacity)
+    }
+
+    /// Fee charged on repay, rounded up. u64::MAX disables flash loans
+    pub fn flash_loan_fee(&self, amount: u64) -> Option<u64> {
+        if self.flash_loan_fee_wad == u64::MAX {
+            return None;
+        }
+
+        let fee = (amount as u128 * self.flash_loan_fee_wad as u128).div_ceil(WAD);
+
+        u64::try_from(fee).ok()
+    }
+}
+
+pub struct SaveProvider {
+    reserve_addresses: Vec<Pubkey>,
+    // Reserve address and decoded state keyed by liquidity mint
+    reserves: HashMap<Pubkey, (Pubkey, SaveReserveState)>,
+}
+
+impl SaveProvider {
+    pub fn new(reserve_addresses: Vec<Pubkey>) -> Self {
+        Self {
+            reserve_addresses,
+            reserves: HashMap::new(),
+        }
+    }
+}
+
+impl FlashLoanProvider for SaveProvider {
+    fn name(&self) -> &'static str {
+        "save"
+    }
+
+    fn supported_mints(&self) -> Vec<Pubkey> {
+        self.reserves.keys().copied().collect()
+    }
+
+    fn fee(&self, mint: &Pubkey, amount: u64) -> Option<u64> {
+        self.reserves.get(mint)?.1.flash_loan_fee(amount)
+    }
+
+    fn max_available(&self, mint: &Pubkey) -> u64 {
+        self.reserves
+            .get(mint)
+            .map(|(_, state)| state.max_flash_loan())
+            .unwrap_or(0)
+    }
+
+    fn instruction_counts(&self) -> (usize, usize) {
+        (1, 1)
+    }
+
+    fn borrow_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some((reserve, state)) = self.reserves.get(&loan.mint) else {
+            return vec![];
+        };
+        let sysvar_info = Pubkey::from_str("Sysvar1nstructions1111111111111111111111111").unwrap();
+        let token_program = Pubkey::new_from_array(spl_token::ID.to_bytes());
+
+        let mut data = Vec::with_capacity(9);
+        data.push(FLASH_BORROW_TAG);
+        data.extend_from_slice(&loan.amount.to_le_bytes());
+
+        vec![Instruction {
+            program_id: save_program_id(),
+            accounts: vec![
+                AccountMeta::new(state.liquidity_supply, false),
+                AccountMeta::new(loan.user_token_account, false),
+                AccountMeta::new(*reserve, false),
+                AccountMeta::new_readonly(state.lending_market, false),
+                AccountMeta::new_readonly(lending_market_authority(&state.lending_market), false),
+                AccountMeta::new_readonly(sysvar_info, false),
+                AccountMeta::new_readonly(token_program, false),
+            ],
+            data,
+        }]
+    }
+
+    fn repay_instructions(&self, loan: &FlashLoan) -> Vec<Instruction> {
+        let Some((reserve, state)) = self.reserves.get(&loan.mint) else {
+            return vec![];
+        };
+        let sysvar_info = Pubkey::from_str("Sysvar1nstructions1111111111111111111111111").unwrap();
+        let token_program = Pubkey::new_from_array(spl_token::ID.to_bytes());
+
+        let mut data = Vec::with_capacity(10);
+        data.push(FLASH_REPAY_TAG);
+        data.extend_from_slice(&loan.amount.to_le_bytes());
+        data.push(loan.borrow_index);
+
+        vec![Instruction {
+            program_id: save_program_id(),
+            accounts: vec![
+                AccountMeta::new(loan.user_token_account, false),
+                AccountMeta::new(state.liquidity_supply, false),
+                AccountMeta::new(state.fee_receiver, false),
+                // Host fee goes back to us
+                AccountMeta::new(loan.user_token_account, false),
+                AccountMeta::new(*reserve, false),
+                AccountMeta::new_readonly(state.lending_market, false),
+                AccountMeta::new_readonly(loan.payer, true),
+                AccountMeta::new_readonly(sysvar_info, false),
+                AccountMeta::new_readonly(token_program, false),
+            ],
+            data,
+        }]
+    }
+
+    fn refresh(&mut self, rpc_client: &BotRpcClient) -> Result<(), anyhow::Error> {
+        let accounts = rpc_client
+            .connection
+            .get_multiple_accounts(&self.reserve_addresses)?;
+
+        for (address, account) in self.reserve_addresses.iter().zip(accounts) {
+            let Some(account) = account else {
+                eprintln!("Save reserve {} not found", address);
+                continue;
+            };
+
+            match SaveReserveState::decode(&account) {
+                Ok(state) => {
+                    self.reserves
+                        .insert(state.liquidity_mint, (*address, state));
+                }
+                Err(err) => eprintln!("Error decoding Save reserve {}: {}", address, err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reserve_state(mint: Pubkey, available: u64, borrowed: u64) -> SaveReserveState {
+        let mut state = SaveReserveState::deserialize(&mut &[0u8; RESERVE_LEN][..]).unwrap();
+        state.version = RESERVE_VERSION;
+        state.liquidity_mint = mint;
+        state.liquidity_available_amount = available;
+        state.liquidity_borrowed_amount_wads = borrowed as u128 * WAD;
+        state.borrow_limit = u64::MAX;
+        state
+    }
+
+    /// Packed account of the Save program, the tail past the fee receiver left zeroed
+    fn reserve_account(state: &SaveReserveState) -> Account {
+        let mut data = borsh::to_vec(state).unwrap();
+        data.resize(RESERVE_LEN, 0);
+
+        Account {
+            lamports: 1,
+            data,
+            owner: save_program_id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn reserves_decode_only_from_save_reserve_accounts() {
+        let mint = Pubkey::new_unique();
+        let mut state = reserve_state(mint, 7_000, 0);
+        state.fee_receiver = Pubkey::new_unique();
+        let account = reserve_account(&state);
+
+        let decoded = SaveReserveState::decode(&account).unwrap();
+        assert_eq!(decoded.liquidity_mint, mint);
+        assert_eq!(decoded.liquidity_available_amount, 7_000);
+        assert_eq!(decoded.fee_receiver, state.fee_receiver);
+
+        let mut foreign = account.clone();
+        foreign.owner = Pubkey::new_unique();
+        let mut market = account.clone();
+        market.data.truncate(290);
+        let mut obligation = account.clone();
+        obligation.data[0] = 0;
+        for (invalid, expected) in [
+            (foreign, "Owned by"),
+            (market, "290 bytes, a Save reserve is 619"),
+            (obligation, "Not a Save reserve, version 0"),
+        ] {
+            let err = SaveReserveState::decode(&invalid)
+                .err()
+                .unwrap()
+                .to_string();
+            assert!(err.starts_with(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn flash_loan_fee_rounds_up() {
+        let mut state = reserve_state(Pubkey::new_unique(), 0, 0);
+
+        // 0.3%
+        state.flash_loan_fee_wad = 3_000_000_000_000_000;
+        assert_eq!(state.flash_loan_fee(1_000_000), Some(3_000));
+        assert_eq!(state.flash_loan_fee(1_001), Some(4));
+        assert_eq!(state.flash_loan_fee(0), Some(0));
+
+        state.flash_loan_fee_wad = 0;
+        assert_eq!(state.flash_loan_fee(1_000_000), Some(0));
+
+        state.flash_loan_fee_wad = u64::MAX;
+        assert_eq!(state.flash_loan_fee(1_000_000), None);
+    }
+
+    #[test]
+    fn max_available_respects_the_borrow_limit() {
+        let mint = Pubkey::new_unique();
+        let mut state = reserve_state(mint, 5_000, 1_000);
+        assert_eq!(state.max_flash_loan(), 5_000);
+
+        state.borrow_limit = 3_500;
+        assert_eq!(state.max_flash_loan(), 2_500);
+        state.borrow_limit = 500;
+        assert_eq!(state.max_flash_loan(), 0);
+
+        state.borrow_limit = 3_500;
+        let mut provider = SaveProvider::new(vec![]);
+        provider
+            .reserves
+            .insert(mint, (Pubkey::new_unique(), state));
+        assert_eq!(provider.max_available(&mint), 2_500);
+        assert_eq!(provider.max_available(&Pubkey::new_unique()), 0);
+        assert_eq!(provider.supported_mints(), vec![mint]);
+    }
+}
@@ -0,0 +1,362 @@
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use crate::opportunity::{Leg, PoolQuoter};
+use crate::utils::wsol_mint;
+
+// Longest cycle we look for, SOL -> USDC -> X -> Y -> SOL
+pub const MAX_CYCLE_LENGTH: usize = 4;
+
+/// Mints as nodes and pools as edges, one per swap direction
+pub struct TokenGraph {
+    pub base_mint: Pubkey,
+    pools: HashMap<Pubkey, PoolQuoter>,
+    /// Legs leaving each mint
+    edges: HashMap<Pubkey, Vec<Leg>>,
+    /// Every cycle found so far, only grows as pools are added
+    cycles: Vec<Vec<Leg>>,
+    /// Indices into `cycles` of the cycles going through each pool
+    cycles_by_pool: HashMap<Pubkey, Vec<usize>>,
+}
+
+impl TokenGraph {
+    pub fn new(base_mint: Pubkey) -> Self {
+        Self {
+            base_mint,
+            pools: HashMap::new(),
+            edges: HashMap::new(),
+            cycles: Vec::new(),
+            cycles_by_pool: HashMap::new(),
+        }
+    }
+
+    pub fn pool(&self, pool_id: &Pubkey) -> Option<&PoolQuoter> {
+        self.pools.get(pool_id)
+    }
+
+    pub fn cycle_count(&self) -> usize {
+        self.cycles.len()
+    }
+
+    /// Store the latest state of a pool, adding its edges and cycles the first time we see it
+    pub fn insert(&mut self, quoter: PoolQuoter) {
+        let pool_id = quoter.pool.pool_id;
+        let is_new = !self.pools.contains_key(&pool_id);
+
+        if is_new {
+            let (mint_a, mint_b) = quoter.mints;
+            for mint in [mint_a, mint_b] {
+                if let Some(leg) = quoter.leg(&mint) {
+                    self.edges.entry(mint).or_default().push(leg);
+                }
+            }
+        }
+
+        self.pools.insert(pool_id, quoter);
+
+        if is_new {
+            self.index_cycles(&pool_id);
+        }
+    }
+
+    /// Find the cycles the new pool closes, every other cycle is already indexed
+    fn index_cycles(&mut self, pool_id: &Pubkey) {
+        let mut cycles = vec![];
+        self.extend(&mut vec![], pool_id, &mut cycles);
+
+        for cycle in cycles {
+            let index = self.cycles.len();
+            for leg in &cycle {
+                self.cycles_by_pool.entry(leg.pool).or_default().push(index);
+            }
+            self.cycles.push(cycle);
+        }
+    }
+
+    /// Simple cycles from the base mint back to itself going through `required`
+    fn extend(&self, path: &mut Vec<Leg>, required: &Pubkey, cycles: &mut Vec<Vec<Leg>>) {
+        let current = path.last().map_or(self.base_mint, |leg| leg.output_mint);
+
+        for leg in self.edges.get(&current).into_iter().flatten() {
+            if path.iter().any(|visited| visited.pool == leg.pool) {
+                continue;
+            }
+
+            if leg.output_mint == self.base_mint {
+                let mut cycle = path.clone();
+                cycle.push(*leg);
+                if cycle.iter().any(|visited| visited.pool == *required) {
+                    cycles.push(cycle);
+                }
+                continue;
+            }
+
+            // Keep room for the leg back to the base mint and don't pass a mint twice
+            if path.len() + 2 > MAX_CYCLE_LENGTH
+                || path
+                    .iter()
+                    .any(|visited| visited.output_mint == leg.output_mint)
+            {
+                continue;
+            }
+
+            path.push(*leg);
+            self.extend(path, required, cycles);
+            path.pop();
+        }
+    }
+
+    /// Sum of -ln(rate) along the legs at spot prices, negative when the cycle gains
+    pub fn log_weight(&self, legs: &[Leg]) -> Option<f64> {
+        legs.iter().try_fold(0.0, |weight, leg| {
+            let rate = self.pools.get(&leg.pool)?.spot_rate(leg.a_to_b)?;
+            (rate > 0.0).then(|| weight - rate.ln())
+        })
+    }
+
+    /// What a lamport is worth in the base mint, at the best spot rate of the pools trading
+    /// WSOL for it. Valuing lamports high keeps fees and tips from being underestimated
+    pub fn lamport_rate(&self) -> Option<f64> {
+        let wsol = wsol_mint();
+        if self.base_mint == wsol {
+            return Some(1.0);
+        }
+
+        self.edges
+            .get(&wsol)?
+            .iter()
+            .filter(|leg| leg.output_mint == self.base_mint)
+            .filter_map(|leg| self.pools.get(&leg.pool)?.spot_rate(leg.a_to_b))
+            .filter(|rate| *rate > 0.0)
+            .max_by(f64::total_cmp)
+    }
+
+    /// Slots between the pools along the legs were last known current. A pool is current
+    /// at the state it was quoted from, or at `confirmed_slot` if something confirmed that
+    /// state later
+    pub fn slot_spread(
+        &self,
+        legs: &[Leg],
+        confirmed_slot: impl Fn(&Pubkey) -> Option<u64>,
+    ) -> Option<u64> {
+        let slots = legs
+            .iter()
+            .map(|leg| {
+                let slot = self.pools.get(&leg.pool)?.slot;
+                Some(confirmed_slot(&leg.pool).map_or(slot, |confirmed| confirmed.max(slot)))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(slots.iter().max()? - slots.iter().min()?)
+    }
+
+    /// Indexed cycles through `pool_id`
+    pub fn cycles_through(&self, pool_id: &Pubkey) -> impl Iterator<Item = &[Leg]> {
+        self.cycles_by_pool
+            .get(pool_id)
+            .into_iter()
+            .flatten()
+            .map(|index| self.cycles[*index].as_slice())
+    }
+
+    /// Cycles through `pool_id` that gain at spot prices, worth an exact quote
+    pub fn candidates(&self, pool_id: &Pubkey) -> Vec<&[Leg]> {
+        self.cycles_through(pool_id)
+            .filter(|legs| self.log_weight(legs).is_some_and(|weight| weight < 0.0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pools_struct::error::PoolError;
+    use crate::pools_struct::structs::{DexType, Pool, PriceFetcher};
+    use std::sync::Arc;
+
+    /// Swaps at a fixed rate of second mint per first mint
+    struct FixedRate {
+        mints: (Pubkey, Pubkey),
+        rate: f64,
+    }
+
+    impl PriceFetcher for FixedRate {
+        fn get_price(&self) -> Result<f64, PoolError> {
+            Ok(self.rate)
+        }
+
+        fn get_dex_name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn get_mints(&self) -> Option<(Pubkey, Pubkey)> {
+            Some(self.mints)
+        }
+
+        fn get_fee_rate(&self) -> Option<u32> {
+            Some(0)
+        }
+
+        fn spot_rate(&self, a_to_b: bool, _fee_rate: u32) -> Option<f64> {
+            Some(if a_to_b { self.rate } else { 1.0 / self.rate })
+        }
+    }
+
+    fn quoter(mint_a: Pubkey, mint_b: Pubkey, rate: f64) -> PoolQuoter {
+        let pool = Pool {
+            name: "fixed".to_string(),
+            pool_id: Pubkey::new_unique(),
+            pool: DexType::Orca,
+            fee_rate: None,
+        };
+        let state = FixedRate {
+            mints: (mint_a, mint_b),
+            rate,
+        };
+
+        PoolQuoter::new(pool, Arc::new(state), 0).unwrap()
+    }
+
+    /// Insert a pool per pair of consecutive mints, returning their ids
+    fn chain(graph: &mut TokenGraph, mints: &[Pubkey]) -> Vec<Pubkey> {
+        mints
+            .windows(2)
+            .map(|pair| {
+                let quoter = quoter(pair[0], pair[1], 1.0);
+                let pool_id = quoter.pool.pool_id;
+                graph.insert(quoter);
+                pool_id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_cycles_of_two_to_four_legs() {
+        let base = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(base);
+        let [a, b, c, d, e] = [(); 5].map(|_| Pubkey::new_unique());
+
+        // Two pools between the same mints close a cycle each way
+        chain(&mut graph, &[base, a]);
+        let second = chain(&mut graph, &[base, a]);
+        assert_eq!(graph.cycles_through(&second[0]).count(), 2);
+
+        let triangle = chain(&mut graph, &[a, b, base]);
+        assert_eq!(graph.cycles_through(&triangle[1]).count(), 4);
+
+        let square = chain(&mut graph, &[base, c, d, e, base]);
+        assert_eq!(graph.cycles_through(&square[3]).count(), 2);
+        assert!(graph
+            .cycles_through(&square[3])
+            .all(|legs| legs.len() == 4 && legs[0].input_mint == base));
+        assert_eq!(graph.cycle_count(), 2 + 4 + 2);
+    }
+
+    #[test]
+    fn skips_cycles_longer_than_four_legs() {
+        let base = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(base);
+        let [a, b, c, d] = [(); 4].map(|_| Pubkey::new_unique());
+
+        let pentagon = chain(&mut graph, &[base, a, b, c, d, base]);
+
+        assert_eq!(graph.cycle_count(), 0);
+        assert!(pentagon
+            .iter()
+            .all(|pool_id| graph.cycles_through(pool_id).next().is_none()));
+    }
+
+    #[test]
+    fn cycles_are_indexed_under_every_pool_they_use() {
+        let base = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(base);
+        let [a, b] = [(); 2].map(|_| Pubkey::new_unique());
+
+        let pools = chain(&mut graph, &[base, a, b, base]);
+        for pool_id in &pools {
+            let cycles = graph.cycles_through(pool_id).collect::<Vec<_>>();
+            assert_eq!(cycles.len(), 2);
+            assert!(cycles
+                .iter()
+                .all(|legs| legs.iter().any(|leg| leg.pool == *pool_id)));
+        }
+
+        // A new state for a known pool adds nothing
+        let mut updated = graph.pool(&pools[0]).unwrap().clone();
+        updated.slot = 10;
+        graph.insert(updated);
+        assert_eq!(graph.cycle_count(), 2);
+        assert_eq!(graph.pool(&pools[0]).unwrap().slot, 10);
+    }
+
+    #[test]
+    fn candidates_gain_at_spot_prices() {
+        let base = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(base);
+
+        let cheap = quoter(base, mint, 1.0);
+        let dear = quoter(base, mint, 0.9);
+        let (cheap_id, dear_id) = (cheap.pool.pool_id, dear.pool.pool_id);
+        graph.insert(cheap);
+        graph.insert(dear);
+
+        // Buy where a base unit gets more of the mint, sell where the mint is worth more
+        let candidates = graph.candidates(&cheap_id);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(
+            candidates[0].iter().map(|leg| leg.pool).collect::<Vec<_>>(),
+            vec![cheap_id, dear_id]
+        );
+        assert!(graph.log_weight(candidates[0]).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn slot_spread_measures_each_pools_own_confirmation() {
+        let base = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(base);
+
+        let mut fresh = quoter(base, mint, 1.0);
+        let mut stale = quoter(base, mint, 0.9);
+        fresh.slot = 100;
+        stale.slot = 90;
+        let (fresh_id, stale_id) = (fresh.pool.pool_id, stale.pool.pool_id);
+        graph.insert(fresh);
+        graph.insert(stale);
+        let legs = graph.cycles_through(&fresh_id).next().unwrap().to_vec();
+
+        // A write to the fresh pool says nothing about the stale one
+        assert_eq!(graph.slot_spread(&legs, |_| None), Some(10));
+        let confirmed = |pool_id: &Pubkey| (*pool_id == fresh_id).then_some(105);
+        assert_eq!(graph.slot_spread(&legs, confirmed), Some(15));
+
+        // Until a snapshot confirms it still holds the state it was quoted from
+        let confirmed = |pool_id: &Pubkey| Some(if *pool_id == stale_id { 104 } else { 105 });
+        assert_eq!(graph.slot_spread(&legs, confirmed), Some(1));
+
+        // Confirmations older than the quoted state don't move it back
+        assert_eq!(graph.slot_spread(&legs, |_| Some(0)), Some(10));
+
+        let unknown = [Leg {
+            pool: Pubkey::new_unique(),
+            ..legs[0]
+        }];
+        assert_eq!(graph.slot_spread(&unknown, |_| None), None);
+    }
+
+    #[test]
+    fn lamports_are_priced_through_wsol_pools() {
+        let wsol = wsol_mint();
+        assert_eq!(TokenGraph::new(wsol).lamport_rate(), Some(1.0));
+
+        let usdc = Pubkey::new_unique();
+        let mut graph = TokenGraph::new(usdc);
+        assert_eq!(graph.lamport_rate(), None);
+
+        // Either mint order, the best rate wins
+        graph.insert(quoter(wsol, usdc, 0.00015));
+        graph.insert(quoter(usdc, wsol, 1.0 / 0.00016));
+        assert!((graph.lamport_rate().unwrap() - 0.00016).abs() < 1e-12);
+    }
+}
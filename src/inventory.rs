@@ -0,0 +1,55 @@
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use crate::client::BotRpcClient;
+use crate::utils::{get_associated_token_address, token_account_amount};
+
+/// Token balances the wallet can trade with directly, keyed by mint
+pub struct Inventory {
+    pub owner: Pubkey,
+    balances: HashMap<Pubkey, u64>,
+}
+
+impl Inventory {
+    pub fn new(owner: Pubkey) -> Self {
+        Self {
+            owner,
+            balances: HashMap::new(),
+        }
+    }
+
+    /// Reload the balance of our associated token account for each mint
+    pub fn refresh(
+        &mut self,
+        rpc_client: &BotRpcClient,
+        mints: &[Pubkey],
+    ) -> Result<(), anyhow::Error> {
+        let atas = mints
+            .iter()
+            .map(|mint| get_associated_token_address(&self.owner, mint))
+            .collect::<Vec<_>>();
+        let accounts = rpc_client.connection.get_multiple_accounts(&atas)?;
+
+        for (mint, account) in mints.iter().zip(accounts) {
+            let amount = account
+                .and_then(|account| token_account_amount(&account.data))
+                .unwrap_or(0);
+
+            self.set_balance(*mint, amount);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_balance(&mut self, mint: Pubkey, amount: u64) {
+        self.balances.insert(mint, amount);
+    }
+
+    pub fn balance(&self, mint: &Pubkey) -> u64 {
+        self.balances.get(mint).copied().unwrap_or(0)
+    }
+
+    pub fn covers(&self, mint: &Pubkey, amount: u64) -> bool {
+        self.balance(mint) >= amount
+    }
+}
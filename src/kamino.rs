@@ -0,0 +1,381 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use sha2::{Digest, Sha256};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::client::BotRpcClient;
+
+pub const RESERVE_ACCOUNT_SIZE: u64 = 8624;
+// Kamino fractions are fixed point with 60 fractional bits
+const FRACTION_BITS: u32 = 60;
+// Offset of `lending_market` in the Reserve account, discriminator included
+const RESERVE_LENDING_MARKET_OFFSET: usize = 32;
+
+pub fn kamino_program_id() -> Pubkey {
+    Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD").unwrap()
+}
+
+pub fn main_lending_market() -> Pubkey {
+    Pubkey::from_str("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF").unwrap()
+}
+
+pub fn lending_market_authority(lending_market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"lma", lending_market.as_ref()], &kamino_program_id()).0
+}
+
+pub fn referrer_token_state(referrer: &Pubkey, reserve: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"referrer_acc", referrer.as_ref(), reserve.as_ref()],
+        &kamino_program_id(),
+    )
+    .0
+}
+
+fn reserve_discriminator() -> [u8; 8] {
+    let hash = Sha256::digest(b"account:Reserve");
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+
+    discriminator
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct LastUpdate {
+    pub slot: u64,
+    pub stale: u8,
+    pub price_status: u8,
+    pub placeholder: [u8; 6],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ReserveLiquidity {
+    pub mint_pubkey: Pubkey,
+    pub supply_vault: Pubkey,
+    pub fee_vault: Pubkey,
+    pub available_amount: u64,
+    pub borrowed_amount_sf: u128,
+    pub market_price_sf: u128,
+    pub market_price_last_updated_ts: u64,
+    pub mint_decimals: u64,
+    pub deposit_limit_crossed_timestamp: u64,
+    pub borrow_limit_crossed_timestamp: u64,
+    pub cumulative_borrow_rate_bsf: [u64; 6],
+    pub accumulated_protocol_fees_sf: u128,
+    pub accumulated_referrer_fees_sf: u128,
+    pub pending_referrer_fees_sf: u128,
+    pub absolute_referral_rate_sf: u128,
+    pub token_program: Pubkey,
+    pub padding2: [u64; 51],
+    pub padding3: [u128; 32],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ReserveCollateral {
+    pub mint_pubkey: Pubkey,
+    pub mint_total_supply: u64,
+    pub supply_vault: Pubkey,
+    pub padding1: [u128; 32],
+    pub padding2: [u128; 32],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ReserveFees {
+    /// Fraction of the borrowed amount charged on borrow, scaled by 2^60
+    pub borrow_fee_sf: u64,
+    /// Fraction of the flash loaned amount charged on repay, scaled by 2^60. u64::MAX disables flash loans
+    pub flash_loan_fee_sf: u64,
+    pub padding: [u8; 8],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct CurvePoint {
+    pub utilization_rate_bps: u32,
+    pub borrow_rate_bps: u32,
+}
+
+/// Leading part of the reserve config, up to the borrow limit
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ReserveConfig {
+    /// 0 = active, 1 = obsolete, 2 = hidden
+    pub status: u8,
+    pub asset_tier: u8,
+    pub host_fixed_interest_rate_bps: u16,
+    pub reserved_2: [u8; 2],
+    pub reserved_3: [u8; 8],
+    pub protocol_take_rate_pct: u8,
+    pub protocol_liquidation_fee_pct: u8,
+    pub loan_to_value_pct: u8,
+    pub liquidation_threshold_pct: u8,
+    pub min_liquidation_bonus_bps: u16,
+    pub max_liquidation_bonus_bps: u16,
+    pub bad_debt_liquidation_bonus_bps: u16,
+    pub deleveraging_margin_call_period_secs: u64,
+    pub deleveraging_threshold_decrease_bps_per_day: u64,
+    pub fees: ReserveFees,
+    pub borrow_rate_curve: [CurvePoint; 11],
+    pub borrow_factor_pct: u64,
+    /// Maximum amount of liquidity that can be deposited
+    pub deposit_limit: u64,
+    /// Maximum amount of liquidity that can be borrowed
+    pub borrow_limit: u64,
+}
+
+/// Leading part of the Kamino `Reserve` account, the rest is ignored
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ReserveState {
+    pub version: u64,
+    pub last_update: LastUpdate,
+    pub lending_market: Pubkey,
+    pub farm_collateral: Pubkey,
+    pub farm_debt: Pubkey,
+    pub liquidity: ReserveLiquidity,
+    pub reserve_liquidity_padding: [u64; 150],
+    pub collateral: ReserveCollateral,
+    pub reserve_collateral_padding: [u64; 150],
+    pub config: ReserveConfig,
+}
+
+impl ReserveState {
+    pub fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        if data.len() < 8 || data[..8] != reserve_discriminator() {
+            return Err(anyhow::anyhow!("Not a Kamino reserve account"));
+        }
+
+        Ok(ReserveState::deserialize(&mut &data[8..])?)
+    }
+
+    pub fn available_amount(&self) -> u64 {
+        self.liquidity.available_amount
+    }
+
+    pub fn borrowed_amount(&self) -> u64 {
+        (self.liquidity.borrowed_amount_sf >> FRACTION_BITS) as u64
+    }
+
+    pub fn flash_loans_enabled(&self) -> bool {
+        self.config.status == 0 && self.config.fees.flash_loan_fee_sf != u64::MAX
+    }
+
+    /// Largest amount we can flash borrow right now
+    pub fn max_flash_loan(&self) -> u64 {
+        if !self.flash_loans_enabled() {
+            return 0;
+        }
+
+        let borrow_capacity = self
+            .config
+            .borrow_limit
+            .saturating_sub(self.borrowed_amount());
+
+        self.available_amount().min(borrow_capacity)
+    }
+
+    /// Fee charged on repay, rounded up like the program does
+    pub fn flash_loan_fee(&self, amount: u64) -> Option<u64> {
+        if !self.flash_loans_enabled() {
+            return None;
+        }
+
+        let fee_sf = self.config.fees.flash_loan_fee_sf as u128;
+        if fee_sf == 0 {
+            return Some(0);
+        }
+
+        let scaled = (amount as u128).checked_mul(fee_sf)?;
+        let fee = scaled.div_ceil(1u128 << FRACTION_BITS).max(1);
+
+        u64::try_from(fee).ok()
+    }
+}
+
+/// Accounts needed to flash borrow a mint from a Kamino reserve
+#[derive(Debug, Clone)]
+pub struct KaminoReserve {
+    pub lending_market: Pubkey,
+    pub reserve: Pubkey,
+    pub mint: Pubkey,
+    pub liquidity_supply: Pubkey,
+    pub fee_receiver: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl KaminoReserve {
+    pub fn from_state(reserve: Pubkey, state: &ReserveState) -> Self {
+        Self {
+            lending_market: state.lending_market,
+            reserve,
+            mint: state.liquidity.mint_pubkey,
+            liquidity_supply: state.liquidity.supply_vault,
+            fee_receiver: state.liquidity.fee_vault,
+            token_program: state.liquidity.token_program,
+        }
+    }
+}
+
+/// Reserves we can flash borrow from, keyed by liquidity mint
+#[derive(Debug, Default)]
+pub struct ReserveCatalog {
+    reserves: HashMap<Pubkey, KaminoReserve>,
+}
+
+impl ReserveCatalog {
+    pub fn new(reserves: Vec<KaminoReserve>) -> Self {
+        let mut catalog = Self::default();
+        for reserve in reserves {
+            catalog.insert(reserve);
+        }
+
+        catalog
+    }
+
+    pub fn insert(&mut self, reserve: KaminoReserve) {
+        self.reserves.insert(reserve.mint, reserve);
+    }
+
+    pub fn get(&self, mint: &Pubkey) -> Option<&KaminoReserve> {
+        self.reserves.get(mint)
+    }
+
+    pub fn reserves(&self) -> impl Iterator<Item = &KaminoReserve> {
+        self.reserves.values()
+    }
+
+    /// Add every reserve of the lending market, keeping entries that are already configured
+    pub fn discover(
+        &mut self,
+        rpc_client: &BotRpcClient,
+        lending_market: &Pubkey,
+    ) -> Result<usize, anyhow::Error> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(RESERVE_ACCOUNT_SIZE),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, reserve_discriminator().to_vec())),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    RESERVE_LENDING_MARKET_OFFSET,
+                    lending_market.to_bytes().to_vec(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let accounts = rpc_client
+            .connection
+            .get_program_accounts_with_config(&kamino_program_id(), config)?;
+
+        let mut discovered = 0;
+        for (address, account) in accounts {
+            match ReserveState::decode(&account.data) {
+                Ok(state) => {
+                    let reserve = KaminoReserve::from_state(address, &state);
+                    if self.reserves.contains_key(&reserve.mint) {
+                        continue;
+                    }
+
+                    self.insert(reserve);
+                    discovered += 1;
+                }
+                Err(err) => eprintln!("Error decoding reserve {}: {}", address, err),
+            }
+        }
+
+        Ok(discovered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reserve of `mint` with `available` liquidity and `borrowed` lent out
+    fn reserve_state(mint: Pubkey, available: u64, borrowed: u64) -> ReserveState {
+        let mut state = ReserveState::deserialize(&mut &[0u8; 8192][..]).unwrap();
+        state.lending_market = main_lending_market();
+        state.liquidity.mint_pubkey = mint;
+        state.liquidity.available_amount = available;
+        state.liquidity.borrowed_amount_sf = (borrowed as u128) << FRACTION_BITS;
+        state.config.borrow_limit = u64::MAX;
+        state
+    }
+
+    /// Account data as the program stores it, discriminator first and padded to size
+    fn reserve_account(state: &ReserveState) -> Vec<u8> {
+        let mut data = reserve_discriminator().to_vec();
+        data.extend(borsh::to_vec(state).unwrap());
+        data.resize(RESERVE_ACCOUNT_SIZE as usize, 0);
+        data
+    }
+
+    #[test]
+    fn reserves_decode_from_account_data() {
+        let mint = Pubkey::new_unique();
+        let mut state = reserve_state(mint, 5_000, 1_000);
+        state.liquidity.supply_vault = Pubkey::new_unique();
+        state.liquidity.fee_vault = Pubkey::new_unique();
+        state.config.fees.flash_loan_fee_sf = 1 << 50;
+        let data = reserve_account(&state);
+
+        // Discovery filters on the lending market at this offset
+        assert_eq!(
+            &data[RESERVE_LENDING_MARKET_OFFSET..RESERVE_LENDING_MARKET_OFFSET + 32],
+            main_lending_market().as_ref()
+        );
+
+        let decoded = ReserveState::decode(&data).unwrap();
+        assert_eq!(decoded.available_amount(), 5_000);
+        assert_eq!(decoded.borrowed_amount(), 1_000);
+        assert_eq!(decoded.config.fees.flash_loan_fee_sf, 1 << 50);
+
+        let reserve = KaminoReserve::from_state(Pubkey::new_unique(), &decoded);
+        assert_eq!(reserve.mint, mint);
+        assert_eq!(reserve.liquidity_supply, state.liquidity.supply_vault);
+        assert_eq!(reserve.fee_receiver, state.liquidity.fee_vault);
+
+        let mut other = data.clone();
+        other[0] ^= 1;
+        assert!(ReserveState::decode(&other).is_err());
+        assert!(ReserveState::decode(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn flash_loan_fee_rounds_up_to_at_least_one() {
+        let mut state = reserve_state(Pubkey::new_unique(), 0, 0);
+
+        // 0.09% as a 2^60 fraction
+        state.config.fees.flash_loan_fee_sf = ((9u128 << FRACTION_BITS) / 10_000) as u64;
+        assert_eq!(state.flash_loan_fee(1_000_000_000), Some(900_000));
+        assert_eq!(state.flash_loan_fee(1_001), Some(1));
+        assert_eq!(state.flash_loan_fee(1_112), Some(2));
+        assert_eq!(state.flash_loan_fee(1), Some(1));
+
+        state.config.fees.flash_loan_fee_sf = 0;
+        assert_eq!(state.flash_loan_fee(1_000_000), Some(0));
+
+        state.config.fees.flash_loan_fee_sf = u64::MAX;
+        assert_eq!(state.flash_loan_fee(1_000_000), None);
+    }
+
+    #[test]
+    fn max_flash_loan_is_capped_by_liquidity_and_borrow_limit() {
+        let mut state = reserve_state(Pubkey::new_unique(), 5_000, 1_000);
+        assert_eq!(state.max_flash_loan(), 5_000);
+
+        // Only 2_500 more may be borrowed
+        state.config.borrow_limit = 3_500;
+        assert_eq!(state.max_flash_loan(), 2_500);
+
+        state.config.borrow_limit = 500;
+        assert_eq!(state.max_flash_loan(), 0);
+
+        state.config.borrow_limit = u64::MAX;
+        state.config.status = 1;
+        assert_eq!(state.max_flash_loan(), 0);
+    }
+}
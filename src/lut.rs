@@ -0,0 +1,408 @@
+use solana_address_lookup_table_interface::instruction::{
+    create_lookup_table, extend_lookup_table,
+};
+use solana_address_lookup_table_interface::state::AddressLookupTable;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::message::AddressLookupTableAccount;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::client::BotRpcClient;
+use crate::config::{self, Config};
+use crate::flashLoan::flash_loan_instruction;
+use crate::kamino::ReserveCatalog;
+use crate::pools_struct::structs::{Pool, PriceFetcher};
+use crate::signer::TxSigner;
+use crate::utils::{get_associated_token_address, parse_account_data, COMPUTE_BUDGET_PROGRAM_ID};
+
+// Each address costs 32 bytes, 30 keeps an extend transaction well under the packet limit
+pub const EXTEND_CHUNK_SIZE: usize = 30;
+// Activation takes a slot, a minute means the RPC node stopped following the chain
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Accounts of the Kamino flash loans, without the payer and user token account
+pub fn kamino_static_accounts(reserves: &ReserveCatalog, referrer: Option<&Pubkey>) -> Vec<Pubkey> {
+    let placeholder = Pubkey::default();
+    let mut accounts = vec![];
+
+    for reserve in reserves.reserves() {
+        let instruction =
+            flash_loan_instruction(&placeholder, &placeholder, reserve, referrer, vec![]);
+
+        accounts.push(instruction.program_id);
+        accounts.extend(
+            instruction
+                .accounts
+                .into_iter()
+                .map(|meta| meta.pubkey)
+                .filter(|pubkey| *pubkey != placeholder),
+        );
+    }
+
+    accounts
+}
+
+/// Every account our arbitrage transactions touch that doesn't change between transactions
+pub fn collect_static_accounts(
+    rpc_client: &BotRpcClient,
+    pools: &[Pool],
+    reserves: &ReserveCatalog,
+    referrer: Option<&Pubkey>,
+    payer: &Pubkey,
+) -> Result<Vec<Pubkey>, anyhow::Error> {
+    let pool_ids = pools.iter().map(|pool| pool.pool_id).collect::<Vec<_>>();
+    let pool_accounts = rpc_client.connection.get_multiple_accounts(&pool_ids)?;
+
+    let states = pools
+        .iter()
+        .zip(pool_accounts)
+        .map(|(pool, account)| {
+            let Some(account) = account else {
+                eprintln!("Pool {} ({}) not found", pool.name, pool.pool_id);
+                return None;
+            };
+
+            parse_account_data(&account.data, pool.pool)
+                .map_err(|err| eprintln!("Error decoding pool {}: {}", pool.name, err))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(static_accounts(pools, &states, reserves, referrer, payer))
+}
+
+/// Static accounts given the decoded state of each pool, `None` for pools that couldn't be
+/// read still contribute their program and address
+pub fn static_accounts(
+    pools: &[Pool],
+    states: &[Option<Box<dyn PriceFetcher>>],
+    reserves: &ReserveCatalog,
+    referrer: Option<&Pubkey>,
+    payer: &Pubkey,
+) -> Vec<Pubkey> {
+    let mut accounts = vec![
+        COMPUTE_BUDGET_PROGRAM_ID,
+        Pubkey::new_from_array(spl_associated_token_account::ID.to_bytes()),
+    ];
+    accounts.extend(kamino_static_accounts(reserves, referrer));
+    for reserve in reserves.reserves() {
+        accounts.push(get_associated_token_address(payer, &reserve.mint));
+    }
+
+    for (pool, state) in pools.iter().zip(states) {
+        accounts.push(pool.pool.program_id());
+        accounts.push(pool.pool_id);
+
+        if let Some(state) = state {
+            accounts.extend(state.get_static_accounts());
+
+            if let Some((mint_a, mint_b)) = state.get_mints() {
+                accounts.push(get_associated_token_address(payer, &mint_a));
+                accounts.push(get_associated_token_address(payer, &mint_b));
+            }
+        }
+    }
+
+    dedup(accounts)
+}
+
+fn dedup(accounts: Vec<Pubkey>) -> Vec<Pubkey> {
+    let mut seen = HashSet::new();
+
+    accounts
+        .into_iter()
+        .filter(|address| seen.insert(*address))
+        .collect()
+}
+
+/// Addresses in `wanted` that the table doesn't hold yet, in order
+pub fn missing_addresses(existing: &[Pubkey], wanted: &[Pubkey]) -> Vec<Pubkey> {
+    let existing = existing.iter().collect::<HashSet<_>>();
+
+    dedup(
+        wanted
+            .iter()
+            .filter(|address| !existing.contains(address))
+            .copied()
+            .collect(),
+    )
+}
+
+pub fn fetch_lookup_table_addresses(
+    rpc_client: &BotRpcClient,
+    lookup_table: &Pubkey,
+) -> Result<Vec<Pubkey>, anyhow::Error> {
+    Ok(fetch_lookup_table(rpc_client, lookup_table)?.addresses)
+}
+
+/// The table as transactions compile against it
+pub fn fetch_lookup_table(
+    rpc_client: &BotRpcClient,
+    lookup_table: &Pubkey,
+) -> Result<AddressLookupTableAccount, anyhow::Error> {
+    let account = rpc_client.connection.get_account(lookup_table)?;
+    let table = AddressLookupTable::deserialize(&account.data)
+        .map_err(|err| anyhow::anyhow!("Invalid lookup table {}: {}", lookup_table, err))?;
+
+    Ok(AddressLookupTableAccount {
+        key: *lookup_table,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+pub fn create_owned_lookup_table(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+) -> Result<Pubkey, anyhow::Error> {
+    let recent_slot = rpc_client
+        .connection
+        .get_slot_with_commitment(CommitmentConfig::finalized())?;
+    let (instruction, lookup_table) =
+        create_lookup_table(payer.pubkey(), payer.pubkey(), recent_slot);
+
+    rpc_client.send_instructions(payer, &[instruction])?;
+    println!("Created lookup table {}", lookup_table);
+
+    Ok(lookup_table)
+}
+
+pub fn extend_owned_lookup_table(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    lookup_table: &Pubkey,
+    addresses: &[Pubkey],
+) -> Result<(), anyhow::Error> {
+    for chunk in addresses.chunks(EXTEND_CHUNK_SIZE) {
+        let instruction = extend_lookup_table(
+            *lookup_table,
+            payer.pubkey(),
+            Some(payer.pubkey()),
+            chunk.to_vec(),
+        );
+
+        rpc_client.send_instructions(payer, &[instruction])?;
+        println!(
+            "Extended lookup table {} with {} addresses",
+            lookup_table,
+            chunk.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Addresses are only usable once the slot they were added in has passed
+pub fn wait_for_activation(
+    rpc_client: &BotRpcClient,
+    lookup_table: &Pubkey,
+) -> Result<(), anyhow::Error> {
+    let account = rpc_client.connection.get_account(lookup_table)?;
+    let table = AddressLookupTable::deserialize(&account.data)
+        .map_err(|err| anyhow::anyhow!("Invalid lookup table {}: {}", lookup_table, err))?;
+    let last_extended_slot = table.meta.last_extended_slot;
+    let started = Instant::now();
+
+    loop {
+        let slot = rpc_client
+            .connection
+            .get_slot_with_commitment(CommitmentConfig::confirmed())?;
+
+        if slot > last_extended_slot {
+            println!("Lookup table {} active at slot {}", lookup_table, slot);
+            return Ok(());
+        }
+        if started.elapsed() > ACTIVATION_TIMEOUT {
+            anyhow::bail!(
+                "Lookup table {} still not active after {:?}, at slot {} of {}",
+                lookup_table,
+                ACTIVATION_TIMEOUT,
+                slot,
+                last_extended_slot + 1
+            );
+        }
+
+        std::thread::sleep(Duration::from_millis(400));
+    }
+}
+
+/// Create a new lookup table, save it as `lookup_table.address` in the config file at
+/// `config_path` and fill it with our static accounts
+pub fn init_lookup_table(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    config: &Config,
+    config_path: &Path,
+    reserves: &ReserveCatalog,
+) -> Result<Pubkey, anyhow::Error> {
+    let lookup_table = create_owned_lookup_table(rpc_client, payer)?;
+    // Saved first, so filling it can be resumed with `lut extend`
+    config::save_lookup_table(config_path, &lookup_table)?;
+    println!(
+        "Saved lookup table {} in {}",
+        lookup_table,
+        config_path.display()
+    );
+
+    fill_lookup_table(rpc_client, payer, config, reserves, &lookup_table, vec![])?;
+
+    Ok(lookup_table)
+}
+
+/// Extend the configured lookup table with whatever it's missing
+pub fn sync_lookup_table(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    config: &Config,
+    reserves: &ReserveCatalog,
+) -> Result<Pubkey, anyhow::Error> {
+    let lookup_table = config
+        .lookup_table
+        .ok_or_else(|| anyhow::anyhow!("lookup_table.address isn't set, create one first"))?;
+    let existing = fetch_lookup_table_addresses(rpc_client, &lookup_table)?;
+
+    fill_lookup_table(rpc_client, payer, config, reserves, &lookup_table, existing)?;
+
+    Ok(lookup_table)
+}
+
+fn fill_lookup_table(
+    rpc_client: &BotRpcClient,
+    payer: &dyn TxSigner,
+    config: &Config,
+    reserves: &ReserveCatalog,
+    lookup_table: &Pubkey,
+    existing: Vec<Pubkey>,
+) -> Result<(), anyhow::Error> {
+    let wanted = collect_static_accounts(
+        rpc_client,
+        &config.pools,
+        reserves,
+        config.kamino_referrer.as_ref(),
+        &payer.pubkey(),
+    )?;
+
+    let missing = missing_addresses(&existing, &wanted);
+    println!(
+        "Lookup table {} holds {} addresses, {} missing",
+        lookup_table,
+        existing.len(),
+        missing.len()
+    );
+
+    if !missing.is_empty() {
+        extend_owned_lookup_table(rpc_client, payer, lookup_table, &missing)?;
+        wait_for_activation(rpc_client, lookup_table)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::{main_lending_market, KaminoReserve};
+    use crate::pools_struct::orca::WhirlpoolState;
+    use crate::pools_struct::structs::DexType;
+    use crate::utils::wsol_mint;
+    use borsh::BorshDeserialize;
+
+    fn reserves() -> ReserveCatalog {
+        ReserveCatalog::new(vec![KaminoReserve {
+            lending_market: main_lending_market(),
+            reserve: Pubkey::new_unique(),
+            mint: wsol_mint(),
+            liquidity_supply: Pubkey::new_unique(),
+            fee_receiver: Pubkey::new_unique(),
+            token_program: Pubkey::new_from_array(spl_token::ID.to_bytes()),
+        }])
+    }
+
+    fn pool(name: &str, dex: DexType) -> Pool {
+        Pool {
+            name: name.to_string(),
+            pool_id: Pubkey::new_unique(),
+            pool: dex,
+            fee_rate: None,
+        }
+    }
+
+    /// A WSOL pool, its other side a fresh mint
+    fn whirlpool() -> Box<dyn PriceFetcher> {
+        let mut state = WhirlpoolState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        state.whirlpools_config = Pubkey::new_unique();
+        state.token_mint_a = wsol_mint();
+        state.token_vault_a = Pubkey::new_unique();
+        state.token_mint_b = Pubkey::new_unique();
+        state.token_vault_b = Pubkey::new_unique();
+        Box::new(state)
+    }
+
+    #[test]
+    fn missing_addresses_keep_order_without_duplicates() {
+        let [a, b, c, d] = [(); 4].map(|_| Pubkey::new_unique());
+
+        assert_eq!(missing_addresses(&[b], &[a, b, c, a, d, c]), vec![a, c, d]);
+        assert!(missing_addresses(&[a, b], &[b, a]).is_empty());
+    }
+
+    #[test]
+    fn static_accounts_cover_pools_reserves_and_token_accounts() {
+        let payer = Pubkey::new_unique();
+        let reserves = reserves();
+        let pools = [
+            pool("orca", DexType::Orca),
+            pool("raydium", DexType::Raydium),
+        ];
+        let orca = whirlpool();
+        let orca_accounts = orca.get_static_accounts();
+
+        let accounts = static_accounts(&pools, &[Some(orca), None], &reserves, None, &payer);
+
+        assert_eq!(accounts, dedup(accounts.clone()));
+        for account in kamino_static_accounts(&reserves, None)
+            .into_iter()
+            .chain(orca_accounts.iter().copied())
+            .chain([
+                COMPUTE_BUDGET_PROGRAM_ID,
+                DexType::Orca.program_id(),
+                DexType::Raydium.program_id(),
+                pools[0].pool_id,
+                pools[1].pool_id,
+                get_associated_token_address(&payer, &orca_accounts[1]),
+                get_associated_token_address(&payer, &orca_accounts[3]),
+            ])
+        {
+            assert!(accounts.contains(&account), "{} missing", account);
+        }
+    }
+
+    #[test]
+    fn adding_a_pool_only_extends_by_its_accounts() {
+        let payer = Pubkey::new_unique();
+        let reserves = reserves();
+        let orca = pool("orca", DexType::Orca);
+        let raydium = pool("raydium", DexType::Raydium);
+
+        let existing = static_accounts(
+            std::slice::from_ref(&orca),
+            &[None],
+            &reserves,
+            None,
+            &payer,
+        );
+        let wanted = static_accounts(
+            &[orca, raydium.clone()],
+            &[None, None],
+            &reserves,
+            None,
+            &payer,
+        );
+
+        assert_eq!(
+            missing_addresses(&existing, &wanted),
+            vec![DexType::Raydium.program_id(), raydium.pool_id]
+        );
+    }
+}
@@ -1,323 +1,73 @@
+mod assembler;
+mod cli;
 mod client;
+mod commands;
+mod config;
+mod debug;
+mod discovery;
+mod feed;
 mod flashLoan;
+mod flash_providers;
+mod graph;
+mod inventory;
+mod kamino;
+mod lut;
+mod market;
+mod opportunity;
 mod payer;
 mod pools_struct;
+mod signer;
+mod sizing;
 mod utils;
 
-use dashmap::DashMap;
-use futures_util::StreamExt;
-use solana_account_decoder::UiAccountEncoding;
-use solana_client::nonblocking::pubsub_client::PubsubClient;
-use solana_client::rpc_config::{RpcAccountInfoConfig, RpcSendTransactionConfig};
-use solana_client::rpc_request::RpcRequest;
-use solana_commitment_config::CommitmentConfig;
-use solana_sdk::message::{AccountMeta, Instruction, Message};
-use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signer::Signer;
-use solana_sdk::transaction::{Transaction, VersionedTransaction};
-use spl_token::instruction::transfer;
-use std::str::FromStr;
-use std::sync::Arc;
+use clap::Parser;
 
+use crate::cli::{Cli, Command, LutCommand};
 use crate::client::BotRpcClient;
-use crate::flashLoan::{borrow_instruction_builder, repay_instruction_builder};
-use crate::payer::get_payer;
-use crate::pools_struct::structs::DexType;
-use crate::utils::{parse_encoded_data, set_compute_unit_limit, set_compute_unit_price};
-
-#[derive(Debug)]
-struct Price {
-    price: f64,
-}
-
-#[derive(Clone)]
-struct Pool {
-    name: String,
-    pool_id: Pubkey,
-    pool: DexType,
-}
+use crate::commands::{discover, doctor, quote, reserve_catalog, run, simulate, wallet};
+use crate::config::Config;
+use crate::signer::load_signer;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let ws_client_url =
-        "wss://mainnet.helius-rpc.com/?api-key=5ddfdb35-09e4-48fb-8916-d57174620515";
-
-    let ws_client = Arc::new(PubsubClient::new(ws_client_url).await?);
-    let rpc_client = BotRpcClient::new("https://api.mainnet-beta.solana.com");
-
-    let config = RpcAccountInfoConfig {
-        commitment: Some(CommitmentConfig::confirmed()),
-        data_slice: None,
-        encoding: Some(UiAccountEncoding::Base64Zstd),
-        min_context_slot: None,
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => Config::from_file(path)?,
+        None => Config::load()?,
     };
 
-    let wallet = get_payer();
-
-    let account_data = spl_associated_token_account::get_associated_token_address(
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::new_from_array(
-            wallet.pubkey().to_bytes(),
-        ),
-        &spl_associated_token_account::solana_program::pubkey::Pubkey::from_str(
-            "So11111111111111111111111111111111111111112",
-        )
-        .unwrap(),
-    );
-
-    let liquidity = 3_700_574_265;
-
-    let compute_budget_ix = set_compute_unit_limit(800_000); // 800,000 CU to match or exceed successful tx
-    let compute_price_ix = set_compute_unit_price(37198); // 0.1 lamports/CU priority fee
-
-    let borrow_instruction = borrow_instruction_builder(
-        wallet.pubkey(),
-        Pubkey::new_from_array(account_data.to_bytes()),
-        liquidity,
-    );
-    let repay_instruction = repay_instruction_builder(
-        wallet.pubkey(),
-        liquidity,
-        Pubkey::new_from_array(account_data.to_bytes()),
-        2,
-    );
-
-    let instructions = vec![
-        compute_budget_ix,
-        compute_price_ix,
-        borrow_instruction,
-        // ins,
-        repay_instruction,
-    ];
-
-    let transfer_instruction = transfer(
-        &spl_token::ID,
-        &spl_token::solana_program::pubkey::Pubkey::new_from_array(account_data.to_bytes()), // from (your ATA)
-        &spl_token::solana_program::pubkey::Pubkey::new_from_array(account_data.to_bytes()), // to (same account - dummy transfer)
-        &spl_token::solana_program::pubkey::Pubkey::new_from_array(wallet.pubkey().to_bytes()), // authority
-        &[],
-        1, // transfer 1 lamport to self (dummy operation)
-    )
-    .unwrap();
-
-    // let ins = Instruction {
-    //     data: transfer_instruction.data,
-    //     program_id: Pubkey::new_from_array(transfer_instruction.program_id.to_bytes()),
-    //     accounts: transfer_instruction
-    //         .accounts
-    //         .into_iter()
-    //         .map(|p| AccountMeta {
-    //             pubkey: Pubkey::new_from_array(p.pubkey.to_bytes()),
-    //             is_signer: p.is_signer,
-    //             is_writable: p.is_writable,
-    //         })
-    //         .collect(),
-    // };
-
-    // let (recent_blockhash, _) = rpc_client
-    //     .get_latest_block_hash(Some(CommitmentConfig::confirmed()))
-    //     .unwrap();
-
-    // let mut tx = Transaction::new_signed_with_payer(
-    //     &instructions,
-    //     Some(&wallet.pubkey()),
-    //     &[&wallet],
-    //     recent_blockhash,
-    // );
-    // tx.try_sign(&[wallet], recent_blockhash).unwrap();
-
-    // // let msg =
-    // //     v0::Message::try_compile(&wallet.pubkey(), &instructions, &[], recent_blockhash).unwrap();
-
-    // // let tx = VersionedTransaction::try_new(VersionedMessage::V0(msg), &[&wallet]).unwrap();
-
-    // let sig = rpc_client
-    //     .connection
-    //     .send_transaction_with_config(
-    //         &tx,
-    //         RpcSendTransactionConfig {
-    //             skip_preflight: true,
-    //             ..Default::default()
-    //         },
-    //     )
-    //     .unwrap();
-    // println!("Tx signature: {}", sig);
-
-    // loop {
-    //     match rpc_client.connection.get_transaction(
-    //         &sig,
-    //         solana_transaction_status_client_types::UiTransactionEncoding::Base64,
-    //     ) {
-    //         Ok(trans) => {
-    //             if let Some(meta) = trans.transaction.meta {
-    //                 if let logs = meta.log_messages {
-    //                     for log in logs.as_ref().unwrap() {
-    //                         println!("{}", log);
-    //                     }
-    //                     break;
-    //                 }
-    //             }
-    //         }
-    //         Err(err) => {
-    //             eprintln!("ERROR: {}", err)
-    //         }
-    //     }
-    // }
-
-    let pool_account_pubkey = Pubkey::from_str("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE")?;
-    let raydium_usdc_pool_account =
-        Pubkey::from_str("3ucNos4NbumPLZNWztqGHNFFgkHeRMBQAVemeeomsUxv")?;
-    let meteora_usdc_sol_pool_1 = Pubkey::from_str("8Pm2kZpnxD3hoMmt4bjStX2Pw2Z9abpbHzZxMPqxPmie")?;
-    let meteora_usdc_sol_pool_2 = Pubkey::from_str("HTvjzsfX3yU6BUodCjZ5vZkUrAxMDTrBs3CJaq43ashR")?;
-    let humidifi_usdc_sol_pool = Pubkey::from_str("FksffEqnBRixYGR791Qw2MgdU7zNCpHVFYBL4Fa4qVuH")?;
-    let solfiv2_usdc_sol_pool = Pubkey::from_str("65ZHSArs5XxPseKQbB1B4r16vDxMWnCxHMzogDAqiDUc")?;
-    let pancake_usdc_sol_pool = Pubkey::from_str("4QU2NpRaqmKMvPSwVKQDeW4V6JFEKJdkzbzdauumD9qN")?;
-    let lifinity_usdc_sol_pool = Pubkey::from_str("DrRd8gYMJu9XGxLhwTCPdHNLXCKHsxJtMpbn62YqmwQe")?;
-
-    let addresses = [
-        Pool {
-            name: "orca".to_string(),
-            pool_id: pool_account_pubkey,
-            pool: DexType::Orca,
-        },
-        Pool {
-            name: "raydium".to_string(),
-            pool_id: raydium_usdc_pool_account,
-            pool: DexType::Raydium,
-        },
-        Pool {
-            name: "meteora_1".to_string(),
-            pool_id: meteora_usdc_sol_pool_1,
-            pool: DexType::Meteora,
-        },
-        // Pool {
-        //     name: "meteora_2".to_string(),
-        //     pool_id: meteora_usdc_sol_pool_2,
-        //     pool: DexType::Meteora,
-        // },
-        // Pool {
-        //     name: "humidifi".to_string(),
-        //     pool_id: humidifi_usdc_sol_pool,
-        //     pool: DexType::HumidiFi,
-        // },
-        // Pool {
-        //     name: "solfiv2".to_string(),
-        //     pool_id: solfiv2_usdc_sol_pool,
-        //     pool: DexType::SolFiV2,
-        // },
-        // Pool {
-        //     name: "pancake".to_string(),
-        //     pool_id: pancake_usdc_sol_pool,
-        //     pool: DexType::PancakeSwap,
-        // },
-        // Pool {
-        //     name: "lifinity".to_string(),
-        //     pool_id: lifinity_usdc_sol_pool,
-        //     pool: DexType::SolFiV2,
-        // },
-    ];
-
-    // loop {
-    //     let multiple_accounts = rpc_client
-    //         .get_multiple_accounts_with_commitment(&addresses, config.commitment.unwrap());
-
-    //     match multiple_accounts {
-    //         Ok(data) => {
-    //             let v = &data.value;
-
-    //             let wirpool_data = v[0].as_ref().unwrap().data.clone();
-    //             let raydium_data = v[1].as_ref().unwrap().data.clone();
-
-    //             let w = Whirlpool::try_from_slice(&wirpool_data);
-
-    //             let w_price = calculate_price_raw_B_per_a(&Data {
-    //                 sqrt_price: w.unwrap().sqrt_price,
-    //             });
-
-    //             let r = RaydiumPoolState::try_from_slice(&raydium_data[8..]);
-
-    //             let r_price = calculate_price_raw_B_per_a(&Data {
-    //                 sqrt_price: r.unwrap().sqrt_price_x64,
-    //             });
-
-    //             println!("ORCA: {:#?}", w_price);
-    //             println!("Raydium: {:#?}", r_price);
-
-    //             // for (i, elem) in data.value.iter().enumerate() {
-    //             //     let w = ele
-    //             //     // println!("{:#?}", elem.as_ref().unwrap().data);
-    //             //     println!("{:#?}", elem.as_ref().unwrap());
-    //             // }
-    //         }
-    //         Err(err) => {
-    //             eprintln!("ERROR: {}", err)
-    //         }
-    //     };
-    // }
-
-    let markets = Arc::new(DashMap::<String, Price>::new());
-
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-
-    for pool in addresses.iter() {
-        let markets = markets.clone();
-        let ws_client_clone = Arc::clone(&ws_client);
-        let config_clone = config.clone();
-        let pool = pool.clone();
-
-        let tx = tx.clone();
-
-        tokio::spawn(async move {
-            let (mut stream, _) = ws_client_clone
-                .account_subscribe(&pool.pool_id, Some(config_clone))
-                .await
-                .expect("Failed to stream");
-
-            while let Some(account) = stream.next().await {
-                match &account.value.data {
-                    solana_account_decoder::UiAccountData::Binary(encoded, _)
-                    | solana_account_decoder::UiAccountData::LegacyBinary(encoded) => {
-                        match parse_encoded_data(encoded, pool.pool) {
-                            Ok(data) => {
-                                let price = data.get_price().expect("e");
-                                let name = data.get_dex_name();
-
-                                markets.insert(name.to_string(), Price { price: price });
-
-                                tx.send((name, Price { price: price })).unwrap();
-                            }
-                            Err(err) => {
-                                eprintln!("Error:{}", err)
-                            }
-                        }
-                    }
-                    _ => {
-                        eprintln!("Error")
-                    }
+    match cli.command {
+        Command::Run => run::run(&config).await,
+        Command::Watch => run::watch(&config).await,
+        Command::Quote {
+            pool,
+            amount,
+            b_to_a,
+        } => quote::quote(&config, &pool, amount, !b_to_a),
+        Command::Simulate { opportunity_file } => simulate::simulate(&config, &opportunity_file),
+        Command::Doctor { fix, dry_run } => doctor::doctor(&config, fix, dry_run).await,
+        Command::Wrap { lamports } => wallet::wrap(&config, lamports),
+        Command::Unwrap => wallet::unwrap(&config),
+        Command::Lut { command } => {
+            let rpc_client = BotRpcClient::new(&config.rpc_url);
+            let wallet = load_signer(&config.signer)?;
+            let reserves = reserve_catalog(&config, &rpc_client)?;
+
+            let lookup_table = match command {
+                LutCommand::Create => lut::init_lookup_table(
+                    &rpc_client,
+                    wallet.as_ref(),
+                    &config,
+                    &config::config_path(),
+                    &reserves,
+                )?,
+                LutCommand::Extend => {
+                    lut::sync_lookup_table(&rpc_client, wallet.as_ref(), &config, &reserves)?
                 }
-            }
-        });
-    }
-
-    // tokio::spawn(async move {
-    //     loop {
-    //         for entry in markets.iter() {
-    //             println!("{:#?}: {:#?}", entry.key(), entry.price);
-    //         }
-    //         sleep(Duration::from_millis(200)).await
-    //     }
-    // });
-
-    tokio::spawn(async move {
-        while let Some((pool_name, price)) = rx.recv().await {
-            // println!("Updated {}: {:#?}", pool_name, price);
-
-            let v = markets.get(pool_name).unwrap();
-
-            println!("{} -> {}", v.key(), v.price);
+            };
+            println!("Lookup table ready: {}", lookup_table);
+            Ok(())
         }
-    });
-
-    tokio::signal::ctrl_c().await?;
-
-    Ok(())
+        Command::Discover => discover::discover(&config).await,
+    }
 }
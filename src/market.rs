@@ -0,0 +1,304 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use solana_sdk::pubkey::Pubkey;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::pools_struct::structs::{DexType, Pool, PriceFetcher};
+
+/// Latest decoded state of one pool and when we got it
+#[derive(Clone)]
+pub struct MarketEntry {
+    pub pool: Pool,
+    pub state: Arc<dyn PriceFetcher>,
+    /// Slot of the update's context
+    pub slot: u64,
+    /// Only sources that report it (gRPC) set this
+    pub write_version: Option<u64>,
+    /// Hash of the raw account data, to compare with snapshots
+    pub data_hash: u64,
+    /// Newest slot the account was known to still hold this state: its write, or a later
+    /// snapshot of the same data
+    pub confirmed_slot: u64,
+    pub received_at: Instant,
+}
+
+impl MarketEntry {
+    fn arrival_of(&self, slot: u64, write_version: Option<u64>) -> Arrival {
+        // Write versions only order writes within a slot when both sides have one, a
+        // source without them delivers the slot's state once
+        let ordering = match (write_version, self.write_version) {
+            (Some(version), Some(current)) => (slot, version).cmp(&(self.slot, current)),
+            _ => slot.cmp(&self.slot),
+        };
+
+        match ordering {
+            std::cmp::Ordering::Greater => Arrival::First,
+            std::cmp::Ordering::Equal => Arrival::Duplicate {
+                behind: self.received_at.elapsed(),
+            },
+            std::cmp::Ordering::Less => Arrival::Stale,
+        }
+    }
+}
+
+pub fn hash_data(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How an update compares with the state we already hold for its pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrival {
+    /// First time we see this slot, or this write version of it
+    First,
+    /// Another source delivered it `behind` earlier
+    Duplicate { behind: Duration },
+    /// Older than what we hold
+    Stale,
+}
+
+/// How far behind the newest update a pool was last confirmed
+#[derive(Debug, Clone, Copy)]
+pub struct PoolAge {
+    pub slots: u64,
+    pub elapsed: Duration,
+}
+
+/// Pool states keyed by pool address
+#[derive(Default)]
+pub struct MarketState {
+    entries: DashMap<Pubkey, MarketEntry>,
+    /// Highest slot seen across every pool
+    latest_slot: AtomicU64,
+}
+
+impl MarketState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare an update at (`slot`, `write_version`) with what we hold for `pool_id`
+    pub fn classify(&self, pool_id: &Pubkey, slot: u64, write_version: Option<u64>) -> Arrival {
+        self.entries.get(pool_id).map_or(Arrival::First, |current| {
+            current.arrival_of(slot, write_version)
+        })
+    }
+
+    /// Store the update only when it's the first arrival of a newer state
+    pub fn insert(&self, entry: MarketEntry) -> Arrival {
+        let slot = entry.slot;
+
+        let arrival = match self.entries.entry(entry.pool.pool_id) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+                Arrival::First
+            }
+            Entry::Occupied(mut occupied) => {
+                let arrival = occupied.get().arrival_of(entry.slot, entry.write_version);
+                if arrival == Arrival::First {
+                    occupied.insert(entry);
+                }
+                arrival
+            }
+        };
+
+        if arrival == Arrival::First {
+            self.latest_slot.fetch_max(slot, Ordering::Relaxed);
+        }
+
+        arrival
+    }
+
+    /// Swap in the state decoded again from the write at (`slot`, `write_version`) once the
+    /// accounts it depends on changed, returns false if a newer write replaced it meanwhile
+    pub fn replace_state(
+        &self,
+        pool_id: &Pubkey,
+        slot: u64,
+        write_version: Option<u64>,
+        state: Arc<dyn PriceFetcher>,
+    ) -> bool {
+        match self.entries.get_mut(pool_id) {
+            Some(mut entry) if entry.slot == slot && entry.write_version == write_version => {
+                entry.state = state;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn latest_slot(&self) -> u64 {
+        self.latest_slot.load(Ordering::Relaxed)
+    }
+
+    /// A snapshot at `slot` showed the pool still holds the state we have
+    pub fn confirm(&self, pool_id: &Pubkey, slot: u64) {
+        if let Some(mut entry) = self.entries.get_mut(pool_id) {
+            entry.confirmed_slot = entry.confirmed_slot.max(slot);
+        }
+    }
+
+    /// Newest slot the pool's state is known to be current at
+    pub fn confirmed_slot(&self, pool_id: &Pubkey) -> Option<u64> {
+        self.entries.get(pool_id).map(|entry| entry.confirmed_slot)
+    }
+
+    /// Slots between the newest update seen on any pool and the last time this pool was
+    /// confirmed, and time since we received its state
+    pub fn age(&self, pool_id: &Pubkey) -> Option<PoolAge> {
+        let entry = self.entries.get(pool_id)?;
+
+        Some(PoolAge {
+            slots: self.latest_slot().saturating_sub(entry.confirmed_slot),
+            elapsed: entry.received_at.elapsed(),
+        })
+    }
+
+    pub fn get(&self, pool_id: &Pubkey) -> Option<MarketEntry> {
+        self.entries.get(pool_id).map(|entry| entry.clone())
+    }
+
+    /// Pools trading `mint_a` against `mint_b`, in either order
+    pub fn by_mints(&self, mint_a: &Pubkey, mint_b: &Pubkey) -> Vec<MarketEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .state
+                    .get_mints()
+                    .is_some_and(|mints| mints == (*mint_a, *mint_b) || mints == (*mint_b, *mint_a))
+            })
+            .map(|entry| entry.clone())
+            .collect()
+    }
+
+    pub fn by_dex(&self, dex: DexType) -> Vec<MarketEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.pool.pool == dex)
+            .map(|entry| entry.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pools_struct::error::PoolError;
+
+    struct Flat;
+
+    impl PriceFetcher for Flat {
+        fn get_price(&self) -> Result<f64, PoolError> {
+            Ok(1.0)
+        }
+
+        fn get_dex_name(&self) -> &'static str {
+            "flat"
+        }
+    }
+
+    fn entry(pool_id: Pubkey, slot: u64, write_version: Option<u64>) -> MarketEntry {
+        MarketEntry {
+            pool: Pool {
+                name: "flat".to_string(),
+                pool_id,
+                pool: DexType::Orca,
+                fee_rate: None,
+            },
+            state: Arc::new(Flat),
+            slot,
+            write_version,
+            data_hash: 0,
+            confirmed_slot: slot,
+            received_at: Instant::now(),
+        }
+    }
+
+    fn is_duplicate(arrival: Arrival) -> bool {
+        matches!(arrival, Arrival::Duplicate { .. })
+    }
+
+    #[test]
+    fn write_versions_order_grpc_writes_within_a_slot() {
+        let markets = MarketState::new();
+        let pool = Pubkey::new_unique();
+
+        assert_eq!(markets.insert(entry(pool, 10, Some(5))), Arrival::First);
+        assert_eq!(markets.insert(entry(pool, 10, Some(7))), Arrival::First);
+        assert!(is_duplicate(markets.insert(entry(pool, 10, Some(7)))));
+        assert_eq!(markets.insert(entry(pool, 10, Some(6))), Arrival::Stale);
+        assert_eq!(markets.insert(entry(pool, 9, Some(9))), Arrival::Stale);
+        assert_eq!(markets.get(&pool).unwrap().write_version, Some(7));
+    }
+
+    #[test]
+    fn websocket_copy_after_grpc_is_a_duplicate() {
+        let markets = MarketState::new();
+        let pool = Pubkey::new_unique();
+
+        assert_eq!(markets.insert(entry(pool, 10, Some(5))), Arrival::First);
+        assert!(is_duplicate(markets.insert(entry(pool, 10, None))));
+        assert_eq!(markets.insert(entry(pool, 9, None)), Arrival::Stale);
+        assert_eq!(markets.insert(entry(pool, 11, None)), Arrival::First);
+    }
+
+    #[test]
+    fn grpc_writes_after_the_websocket_copy_are_duplicates() {
+        let markets = MarketState::new();
+        let pool = Pubkey::new_unique();
+
+        assert_eq!(markets.insert(entry(pool, 10, None)), Arrival::First);
+        for write_version in [3, 4] {
+            assert!(is_duplicate(markets.classify(
+                &pool,
+                10,
+                Some(write_version)
+            )));
+            assert!(is_duplicate(markets.insert(entry(
+                pool,
+                10,
+                Some(write_version)
+            ))));
+        }
+        // The websocket copy is the slot's state and stays
+        assert_eq!(markets.get(&pool).unwrap().write_version, None);
+        assert_eq!(markets.insert(entry(pool, 11, Some(1))), Arrival::First);
+    }
+
+    #[test]
+    fn snapshots_without_versions_dedup_on_slot() {
+        let markets = MarketState::new();
+        let pool = Pubkey::new_unique();
+
+        assert_eq!(markets.classify(&pool, 10, None), Arrival::First);
+        assert_eq!(markets.insert(entry(pool, 10, None)), Arrival::First);
+        assert!(is_duplicate(markets.insert(entry(pool, 10, None))));
+        assert_eq!(markets.latest_slot(), 10);
+    }
+
+    #[test]
+    fn pools_age_from_their_last_confirmation() {
+        let markets = MarketState::new();
+        let (quiet, busy) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        markets.insert(entry(quiet, 10, None));
+        markets.insert(entry(busy, 20, None));
+        assert_eq!(markets.age(&quiet).unwrap().slots, 10);
+        assert_eq!(markets.age(&busy).unwrap().slots, 0);
+
+        // Writes to other pools don't vouch for this one, a snapshot of it does
+        markets.confirm(&quiet, 18);
+        markets.confirm(&quiet, 15);
+        assert_eq!(markets.confirmed_slot(&quiet), Some(18));
+        assert_eq!(markets.age(&quiet).unwrap().slots, 2);
+        assert_eq!(markets.get(&quiet).unwrap().slot, 10);
+
+        markets.confirm(&Pubkey::new_unique(), 30);
+        assert_eq!(markets.latest_slot(), 20);
+    }
+}
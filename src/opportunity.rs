@@ -0,0 +1,233 @@
+use solana_sdk::message::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::assembler::FundingContext;
+use crate::graph::TokenGraph;
+use crate::pools_struct::structs::{Pool, PriceFetcher};
+use crate::sizing::{size_route, SizedTrade};
+
+/// One swap of a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leg {
+    pub pool: Pubkey,
+    /// Swap the pool's first mint for its second
+    pub a_to_b: bool,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+}
+
+/// Route that starts and ends in the base mint
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub legs: Vec<Leg>,
+    pub amount_in: u64,
+    /// Output of each leg, the last one is `expected_out`
+    pub amounts: Vec<u64>,
+    pub expected_out: u64,
+    /// In base mint units, lamports for SOL
+    pub expected_profit: i128,
+}
+
+/// Latest decoded state of a pool, with the fee used to quote it
+#[derive(Clone)]
+pub struct PoolQuoter {
+    pub pool: Pool,
+    pub state: Arc<dyn PriceFetcher>,
+    pub mints: (Pubkey, Pubkey),
+    pub fee_rate: u32,
+    /// Slot the state was read at
+    pub slot: u64,
+}
+
+impl PoolQuoter {
+    /// None when the pool can't be quoted (unknown mints or fee)
+    pub fn new(pool: Pool, state: Arc<dyn PriceFetcher>, slot: u64) -> Option<Self> {
+        let mints = state.get_mints()?;
+        let fee_rate = pool.fee_rate.or_else(|| state.get_fee_rate())?;
+
+        Some(Self {
+            pool,
+            state,
+            mints,
+            fee_rate,
+            slot,
+        })
+    }
+
+    /// Leg swapping `input_mint` through this pool
+    pub fn leg(&self, input_mint: &Pubkey) -> Option<Leg> {
+        let (mint_a, mint_b) = self.mints;
+
+        if *input_mint == mint_a {
+            Some(Leg {
+                pool: self.pool.pool_id,
+                a_to_b: true,
+                input_mint: mint_a,
+                output_mint: mint_b,
+            })
+        } else if *input_mint == mint_b {
+            Some(Leg {
+                pool: self.pool.pool_id,
+                a_to_b: false,
+                input_mint: mint_b,
+                output_mint: mint_a,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn quote(&self, amount_in: u64, a_to_b: bool) -> Option<u64> {
+        self.state.quote(amount_in, a_to_b, self.fee_rate)
+    }
+
+    pub fn spot_rate(&self, a_to_b: bool) -> Option<f64> {
+        self.state.spot_rate(a_to_b, self.fee_rate)
+    }
+}
+
+/// Quote `amount_in` through each leg in turn
+pub fn quote_route(graph: &TokenGraph, legs: &[Leg], amount_in: u64) -> Option<Opportunity> {
+    let mut amounts = Vec::with_capacity(legs.len());
+    let mut amount = amount_in;
+    for leg in legs {
+        amount = graph.pool(&leg.pool)?.quote(amount, leg.a_to_b)?;
+        amounts.push(amount);
+    }
+
+    Some(Opportunity {
+        legs: legs.to_vec(),
+        amount_in,
+        amounts,
+        expected_out: amount,
+        expected_profit: amount as i128 - amount_in as i128,
+    })
+}
+
+/// Swap instruction of every leg of `opportunity` for `owner`, each spending what the leg
+/// before it is quoted to return and failing unless it returns its own quote
+pub fn swap_instructions(
+    graph: &TokenGraph,
+    opportunity: &Opportunity,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>, anyhow::Error> {
+    let inputs = std::iter::once(opportunity.amount_in).chain(opportunity.amounts.iter().copied());
+
+    opportunity
+        .legs
+        .iter()
+        .zip(inputs)
+        .zip(&opportunity.amounts)
+        .map(|((leg, amount_in), &minimum_out)| {
+            let quoter = graph
+                .pool(&leg.pool)
+                .ok_or_else(|| anyhow::anyhow!("Pool {} isn't in the graph", leg.pool))?;
+            quoter
+                .state
+                .swap_instruction(&leg.pool, owner, amount_in, minimum_out, leg.a_to_b)
+                .ok_or_else(|| anyhow::anyhow!("Can't build a swap through {}", quoter.pool.name))
+        })
+        .collect()
+}
+
+/// How long evaluating the cycles touched by an update takes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EvaluationStats {
+    pub updates: u64,
+    /// Cycles through the last updated pool
+    pub last_cycles: usize,
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+    /// Candidates dropped because their pools were read too many slots apart
+    pub stale: u64,
+}
+
+impl EvaluationStats {
+    fn record(&mut self, cycles: usize, elapsed: Duration) {
+        self.updates += 1;
+        self.last_cycles = cycles;
+        self.last = elapsed;
+        self.max = self.max.max(elapsed);
+        self.total += elapsed;
+    }
+
+    pub fn average(&self) -> Duration {
+        self.total / u32::try_from(self.updates).unwrap_or(u32::MAX).max(1)
+    }
+}
+
+/// Looks for profitable cycles through a pool whenever it updates
+pub struct OpportunityEngine {
+    pub max_trade_size: u64,
+    /// Largest slot gap allowed between the pools of a route
+    pub max_slot_spread: u64,
+    pub stats: EvaluationStats,
+    funding: Arc<FundingContext>,
+    graph: TokenGraph,
+}
+
+impl OpportunityEngine {
+    pub fn new(
+        base_mint: Pubkey,
+        max_trade_size: u64,
+        max_slot_spread: u64,
+        funding: Arc<FundingContext>,
+    ) -> Self {
+        Self {
+            max_trade_size,
+            max_slot_spread,
+            stats: EvaluationStats::default(),
+            funding,
+            graph: TokenGraph::new(base_mint),
+        }
+    }
+
+    /// Cycles indexed across every pool seen so far
+    pub fn cycle_count(&self) -> usize {
+        self.graph.cycle_count()
+    }
+
+    /// Store the new pool state and return the profitable cycles through it, sized for net
+    /// profit. `confirmed_slot` tells up to when each pool's state is known to be current
+    pub fn update(
+        &mut self,
+        pool: &Pool,
+        state: Arc<dyn PriceFetcher>,
+        slot: u64,
+        confirmed_slot: impl Fn(&Pubkey) -> Option<u64>,
+    ) -> Vec<SizedTrade> {
+        let Some(updated) = PoolQuoter::new(pool.clone(), state, slot) else {
+            return vec![];
+        };
+        self.graph.insert(updated);
+
+        // Only cycles through the updated pool can have changed
+        let started = Instant::now();
+        let cycles = self.graph.cycles_through(&pool.pool_id).count();
+
+        // Spot prices only shortlist cycles, exact quotes decide
+        let (fresh, stale): (Vec<_>, Vec<_>) = self
+            .graph
+            .candidates(&pool.pool_id)
+            .into_iter()
+            .partition(|legs| {
+                self.graph
+                    .slot_spread(legs, &confirmed_slot)
+                    .is_some_and(|spread| spread <= self.max_slot_spread)
+            });
+
+        let trades = fresh
+            .into_iter()
+            .filter_map(|legs| size_route(&self.graph, legs, &self.funding, self.max_trade_size))
+            .filter(|trade| trade.net_profit > 0)
+            .collect();
+
+        self.stats.stale += stale.len() as u64;
+        self.stats.record(cycles, started.elapsed());
+
+        trades
+    }
+}
@@ -1,14 +1,120 @@
+use anyhow::{anyhow, Context};
 use base64::Engine;
 use solana_sdk::{bs58, signature::Keypair};
-use std::fs;
 
-pub fn get_payer() -> Keypair {
-    let wallet_base64 = fs::read_to_string("wallet.txt").expect("Failed to read");
+use crate::config::Secret;
 
-    let decoded_bytes = base64::engine::general_purpose::STANDARD
-        .decode(wallet_base64)
-        .expect("Failed to decode base 64");
-    let base58_wallet = bs58::encode(decoded_bytes).into_string();
+// Secret key followed by the public key
+const KEYPAIR_LENGTH: usize = 64;
 
-    Keypair::from_base58_string(&base58_wallet)
+/// Decode a keypair in any of the supported encodings. Errors never include the input,
+/// not even the part that failed to parse
+pub fn parse_keypair(contents: &str) -> Result<Keypair, anyhow::Error> {
+    let contents = contents.trim();
+
+    let bytes = if contents.starts_with('[') {
+        serde_json::from_str::<Vec<u8>>(contents)
+            .map_err(|_| anyhow!("not a JSON array of bytes"))?
+    } else if let Ok(bytes) = bs58::decode(contents).into_vec() {
+        bytes
+    } else {
+        base64::engine::general_purpose::STANDARD
+            .decode(contents)
+            .map_err(|_| anyhow!("neither a JSON array, base58 nor base64"))?
+    };
+
+    if bytes.len() != KEYPAIR_LENGTH {
+        return Err(anyhow!(
+            "decoded to {} bytes, a keypair is {}",
+            bytes.len(),
+            KEYPAIR_LENGTH
+        ));
+    }
+    Keypair::try_from(bytes.as_slice())
+        .map_err(|_| anyhow!("public key doesn't match the secret key"))
+}
+
+/// Load the payer from a JSON keypair file, or a base58 or base64 key inline, in an env
+/// var or in a file
+pub fn get_payer(source: &Secret) -> Result<Keypair, anyhow::Error> {
+    let contents = source.resolve().context("Can't load keypair")?;
+
+    parse_keypair(&contents).with_context(|| format!("Invalid keypair in {}", source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn keypairs_decode_from_json_base58_and_base64() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+        let encodings = [
+            serde_json::to_string(&bytes.to_vec()).unwrap(),
+            keypair.to_base58_string(),
+            base64::engine::general_purpose::STANDARD.encode(bytes),
+        ];
+
+        for encoded in encodings {
+            // Files usually end with a newline
+            let decoded = parse_keypair(&format!("{}\n", encoded)).unwrap();
+            assert_eq!(decoded.pubkey(), keypair.pubkey());
+        }
+    }
+
+    #[test]
+    fn invalid_keypairs_are_rejected_without_echoing_them() {
+        let bytes = Keypair::new().to_bytes();
+        let mut mismatched = bytes;
+        mismatched[32..].copy_from_slice(&Keypair::new().pubkey().to_bytes());
+
+        let cases = [
+            (
+                serde_json::to_string(&bytes[..32]).unwrap(),
+                "decoded to 32 bytes, a keypair is 64",
+            ),
+            (
+                bs58::encode(&bytes[..63]).into_string(),
+                "decoded to 63 bytes, a keypair is 64",
+            ),
+            (
+                bs58::encode(mismatched).into_string(),
+                "public key doesn't match the secret key",
+            ),
+            ("[1, 2, 300]".to_string(), "not a JSON array of bytes"),
+            (
+                "not a key!".to_string(),
+                "neither a JSON array, base58 nor base64",
+            ),
+        ];
+
+        for (contents, expected) in cases {
+            let error = parse_keypair(&contents).err().unwrap().to_string();
+            assert_eq!(error, expected);
+            assert!(!error.contains(&contents));
+        }
+    }
+
+    #[test]
+    fn payer_errors_name_the_source() {
+        let error = get_payer(&Secret::Inline("not a key!".to_string()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{:#}", error),
+            "Invalid keypair in inline value: neither a JSON array, base58 nor base64"
+        );
+
+        let error = get_payer(&Secret::Env {
+            env: "ARBITRAGE_TEST_UNSET_KEYPAIR".to_string(),
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            format!("{:#}", error),
+            "Can't load keypair: env var ARBITRAGE_TEST_UNSET_KEYPAIR is not set"
+        );
+    }
 }
@@ -0,0 +1,489 @@
+// Fee rates of Orca and Raydium CLMMs are in hundredths of a basis point
+pub const FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+// Tick range of Orca and Raydium CLMMs, where the sqrt price fits in Q64.64
+pub const MIN_TICK: i32 = -443636;
+pub const MAX_TICK: i32 = 443636;
+const Q64: u128 = 1 << 64;
+// 1/√1.0001^(2^i) in Q128.128 for i = 1..19, bit 0 is handled on its own
+const TICK_FACTORS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+/// Initialized tick of a tick array and the liquidity that starts (or ends, if negative)
+/// there when the price moves up across it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    pub index: i32,
+    pub liquidity_net: i128,
+}
+
+/// Initialized ticks of the loaded tick arrays around the current tick and the range those
+/// arrays cover, quotes can't step past it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickWindow {
+    pub lower: i32,
+    pub upper: i32,
+    /// Sorted by index
+    pub ticks: Vec<Tick>,
+}
+
+impl TickWindow {
+    /// Window over the arrays below, holding and above the current tick, in that order.
+    /// None until the current one is loaded, a missing neighbour narrows the window
+    pub fn new(
+        current_start: i32,
+        ticks_in_array: i32,
+        arrays: [Option<Vec<Tick>>; 3],
+    ) -> Option<Self> {
+        let [below, current, above] = arrays;
+        let current = current?;
+
+        let lower = if below.is_some() {
+            current_start - ticks_in_array
+        } else {
+            current_start
+        };
+        let upper = if above.is_some() {
+            current_start + 2 * ticks_in_array
+        } else {
+            current_start + ticks_in_array
+        };
+
+        let mut ticks = below
+            .into_iter()
+            .chain(Some(current))
+            .chain(above)
+            .flatten()
+            .collect::<Vec<_>>();
+        ticks.sort_by_key(|tick| tick.index);
+
+        Some(Self {
+            lower,
+            upper,
+            ticks,
+        })
+    }
+}
+
+/// `a * b` as its high and low 128 bits
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a1, a0) = (a >> 64, a & MASK);
+    let (b1, b0) = (b >> 64, b & MASK);
+    let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let low = (p00 & MASK) | (mid << 64);
+    let high = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (high, low)
+}
+
+/// Quotient and remainder of the 256-bit `high:low` by `divisor`, None when the quotient
+/// doesn't fit in 128 bits
+fn div_rem(high: u128, low: u128, divisor: u128) -> Option<(u128, u128)> {
+    if divisor == 0 || high >= divisor {
+        return None;
+    }
+
+    let (mut quotient, mut remainder) = (0u128, high);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+
+    Some((quotient, remainder))
+}
+
+/// `a * b / divisor` rounded down, without overflowing in between
+pub fn mul_div(a: u128, b: u128, divisor: u128) -> Option<u128> {
+    match a.checked_mul(b) {
+        Some(product) => product.checked_div(divisor),
+        None => {
+            let (high, low) = full_mul(a, b);
+            div_rem(high, low, divisor).map(|(quotient, _)| quotient)
+        }
+    }
+}
+
+/// `a * b / divisor` rounded up
+pub fn mul_div_ceil(a: u128, b: u128, divisor: u128) -> Option<u128> {
+    let (high, low) = full_mul(a, b);
+    let (quotient, remainder) = div_rem(high, low, divisor)?;
+    quotient.checked_add(u128::from(remainder != 0))
+}
+
+/// √1.0001^tick in Q64.64
+pub fn sqrt_price_at_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    // 1/√1.0001^|tick| in Q128.128, one factor per set bit; MAX stands for 1.0
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        0xfffcb933bd6fad37aa2d162d1a594001
+    } else {
+        u128::MAX
+    };
+    for (bit, factor) in TICK_FACTORS.iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            ratio = full_mul(ratio, *factor).0;
+        }
+    }
+
+    if tick > 0 {
+        // 2^192 / ratio is the inverse in Q64.64
+        div_rem(Q64, 0, ratio).map(|(quotient, _)| quotient)
+    } else {
+        Some((ratio >> 64) + u128::from(ratio as u64 != 0))
+    }
+}
+
+/// Input that moves the price from `sqrt_price` to `target` at constant `liquidity`, rounded up
+fn input_to_target(sqrt_price: u128, target: u128, liquidity: u128, a_to_b: bool) -> Option<u128> {
+    if a_to_b {
+        // Δx = L·(√P - √T) / (√P·√T)
+        mul_div_ceil(
+            mul_div_ceil(liquidity, sqrt_price - target, target)?,
+            Q64,
+            sqrt_price,
+        )
+    } else {
+        // Δy = L·(√T - √P)
+        mul_div_ceil(liquidity, target - sqrt_price, Q64)
+    }
+}
+
+/// Output of moving the price from `sqrt_price` to `next` at constant `liquidity`, rounded down
+fn output_to_price(sqrt_price: u128, next: u128, liquidity: u128, a_to_b: bool) -> Option<u128> {
+    if a_to_b {
+        mul_div(liquidity, sqrt_price - next, Q64)
+    } else {
+        mul_div(
+            mul_div(liquidity, next - sqrt_price, next)?,
+            Q64,
+            sqrt_price,
+        )
+    }
+}
+
+/// Price after swapping `amount` in at constant `liquidity`, rounded against the trader
+fn price_after_input(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u128,
+    a_to_b: bool,
+) -> Option<u128> {
+    if a_to_b {
+        // √P' = L·√P / (L + Δx·√P)
+        let denominator = liquidity.checked_add(mul_div(amount, sqrt_price, Q64)?)?;
+        mul_div_ceil(liquidity, sqrt_price, denominator)
+    } else {
+        // √P' = √P + Δy / L
+        sqrt_price.checked_add(mul_div(amount, Q64, liquidity)?)
+    }
+}
+
+/// Output of an exact-in swap stepping across the initialized ticks of `window`, the fee
+/// taken from the input of every step like the programs do. None when the swap would
+/// leave the window or the math overflows
+pub fn quote_across_ticks(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    tick_current: i32,
+    window: &TickWindow,
+    amount_in: u64,
+    a_to_b: bool,
+    fee_rate: u32,
+) -> Option<u64> {
+    let fee_rate = fee_rate as u128;
+    let denominator = FEE_RATE_DENOMINATOR as u128;
+    if sqrt_price_x64 == 0 || fee_rate >= denominator {
+        return None;
+    }
+
+    let (mut sqrt_price, mut liquidity, mut tick) = (sqrt_price_x64, liquidity, tick_current);
+    let mut remaining = amount_in as u128;
+    let mut amount_out = 0u128;
+
+    while remaining > 0 {
+        // Selling A moves down onto the tick at or below the current one, B moves up
+        let next = if a_to_b {
+            window.ticks.iter().rev().find(|next| next.index <= tick)
+        } else {
+            window.ticks.iter().find(|next| next.index > tick)
+        };
+        let edge = if a_to_b { window.lower } else { window.upper };
+        let target = sqrt_price_at_tick(next.map_or(edge, |next| next.index))?;
+
+        let after_fee = mul_div(remaining, denominator - fee_rate, denominator)?;
+        let needed = input_to_target(sqrt_price, target, liquidity, a_to_b)?;
+
+        if after_fee < needed {
+            let next_price = price_after_input(sqrt_price, liquidity, after_fee, a_to_b)?;
+            amount_out += output_to_price(sqrt_price, next_price, liquidity, a_to_b)?;
+            break;
+        }
+
+        amount_out += output_to_price(sqrt_price, target, liquidity, a_to_b)?;
+        let fee = mul_div_ceil(needed, fee_rate, denominator - fee_rate)?;
+        remaining = remaining.saturating_sub(needed + fee);
+        sqrt_price = target;
+
+        let Some(next) = next else {
+            // Input left at the edge of the arrays we loaded
+            if remaining > 0 {
+                return None;
+            }
+            break;
+        };
+        if a_to_b {
+            liquidity = liquidity.checked_add_signed(next.liquidity_net.checked_neg()?)?;
+            tick = next.index - 1;
+        } else {
+            liquidity = liquidity.checked_add_signed(next.liquidity_net)?;
+            tick = next.index;
+        }
+    }
+
+    u64::try_from(amount_out).ok()
+}
+
+/// Marginal output per unit of input at the current price, after the fee
+pub fn spot_rate(sqrt_price_x64: u128, a_to_b: bool, fee_rate: u32) -> Option<f64> {
+    if sqrt_price_x64 == 0 || fee_rate as u64 >= FEE_RATE_DENOMINATOR {
+        return None;
+    }
+
+    let sqrt_price = sqrt_price_x64 as f64 / 2_f64.powi(64);
+    let price = sqrt_price * sqrt_price;
+    let after_fee = 1.0 - fee_rate as f64 / FEE_RATE_DENOMINATOR as f64;
+
+    if a_to_b {
+        Some(price * after_fee)
+    } else {
+        Some(after_fee / price)
+    }
+}
+
+/// Start indices of the tick array holding the current tick and of its neighbours on each
+/// side, the arrays a swap from the current price can cross into
+pub fn tick_array_start_indices(
+    tick_current: i32,
+    tick_spacing: u16,
+    ticks_per_array: i32,
+) -> Vec<i32> {
+    let ticks_in_array = tick_spacing as i32 * ticks_per_array;
+    if ticks_in_array == 0 {
+        return Vec::new();
+    }
+
+    let start = tick_current.div_euclid(ticks_in_array) * ticks_in_array;
+    vec![start - ticks_in_array, start, start + ticks_in_array]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Whirlpool's MIN_SQRT_PRICE_X64 and MAX_SQRT_PRICE_X64
+    const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+    const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
+    fn window(lower: i32, upper: i32, ticks: &[(i32, i128)]) -> TickWindow {
+        TickWindow {
+            lower,
+            upper,
+            ticks: ticks
+                .iter()
+                .map(|&(index, liquidity_net)| Tick {
+                    index,
+                    liquidity_net,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sqrt_prices_at_the_tick_range_bounds() {
+        assert_eq!(sqrt_price_at_tick(0), Some(Q64));
+        assert!(
+            sqrt_price_at_tick(MIN_TICK)
+                .unwrap()
+                .abs_diff(MIN_SQRT_PRICE_X64)
+                <= 1
+        );
+        // Whirlpool rounds its own constants, a few units out of 2^96 apart
+        let max = sqrt_price_at_tick(MAX_TICK).unwrap();
+        assert!(max.abs_diff(MAX_SQRT_PRICE_X64) <= 16);
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+    }
+
+    #[test]
+    fn sqrt_prices_follow_one_basis_point_per_tick() {
+        for tick in (0..19).flat_map(|bit| [1 << bit, -(1 << bit), 3 << bit, -(3 << bit)]) {
+            if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+                continue;
+            }
+            // In Q64.64 units, low prices only keep the integer part
+            let expected = (tick as f64 / 2.0 * 0.0001_f64.ln_1p()).exp() * Q64 as f64;
+            let actual = sqrt_price_at_tick(tick).unwrap() as f64;
+            assert!(
+                (actual - expected).abs() <= (expected * 1e-12).max(1.0),
+                "tick {}: {} vs {}",
+                tick,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn mul_div_keeps_256_bit_products() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 4, 8), Some(u128::MAX / 2));
+        assert_eq!(mul_div_ceil(u128::MAX, 3, 6), Some(u128::MAX / 2 + 1));
+        assert_eq!(mul_div(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div(1, 1, 0), None);
+    }
+
+    #[test]
+    fn small_swap_matches_constant_liquidity() {
+        let liquidity = 1_000_000_000_000u128;
+        let window = window(-5632, 11264, &[(-5632, 0), (5632, 0)]);
+
+        // At price 1 with no fee, Δy = L·Δx / (L + Δx)
+        let out = quote_across_ticks(Q64, liquidity, 0, &window, 1_000_000, true, 0).unwrap();
+        let expected = liquidity * 1_000_000 / (liquidity + 1_000_000);
+        assert!(
+            out.abs_diff(expected as u64) <= 1,
+            "{} vs {}",
+            out,
+            expected
+        );
+
+        let out = quote_across_ticks(Q64, liquidity, 0, &window, 1_000_000, false, 0).unwrap();
+        assert!(
+            out.abs_diff(expected as u64) <= 1,
+            "{} vs {}",
+            out,
+            expected
+        );
+    }
+
+    #[test]
+    fn fee_is_taken_from_the_input() {
+        let liquidity = 1_000_000_000_000u128;
+        let window = window(-5632, 5632, &[]);
+
+        let without_fee = quote_across_ticks(Q64, liquidity, 0, &window, 1_000_000, true, 0);
+        let with_fee = quote_across_ticks(Q64, liquidity, 0, &window, 1_000_000, true, 3000);
+        let after_fee = quote_across_ticks(Q64, liquidity, 0, &window, 997_000, true, 0);
+
+        assert!(with_fee < without_fee);
+        assert!(with_fee.unwrap().abs_diff(after_fee.unwrap()) <= 1);
+    }
+
+    #[test]
+    fn crossing_a_tick_uses_the_liquidity_past_it() {
+        let liquidity = 1_000_000_000u128;
+        let amount = 10_000_000;
+
+        // Most liquidity ends 10 ticks below, past there the price moves much faster
+        let thin = window(-5632, 5632, &[(-10, 900_000_000)]);
+        let deep = window(-5632, 5632, &[]);
+
+        let through_thin = quote_across_ticks(Q64, liquidity, 0, &thin, amount, true, 0).unwrap();
+        let through_deep = quote_across_ticks(Q64, liquidity, 0, &deep, amount, true, 0).unwrap();
+        assert!(through_thin < through_deep);
+
+        // The first 10 ticks still fill at full liquidity
+        let to_tick = input_to_target(Q64, sqrt_price_at_tick(-10).unwrap(), liquidity, true);
+        let small = to_tick.unwrap() as u64 / 2;
+        assert_eq!(
+            quote_across_ticks(Q64, liquidity, 0, &thin, small, true, 0),
+            quote_across_ticks(Q64, liquidity, 0, &deep, small, true, 0)
+        );
+    }
+
+    #[test]
+    fn swaps_past_the_loaded_arrays_cannot_be_quoted() {
+        let window = window(-100, 100, &[]);
+
+        assert_eq!(
+            quote_across_ticks(Q64, 1_000_000, 0, &window, u64::MAX, true, 0),
+            None
+        );
+        assert!(quote_across_ticks(Q64, 1_000_000, 0, &window, 100, true, 0).is_some());
+    }
+
+    #[test]
+    fn window_narrows_to_the_loaded_arrays() {
+        let tick = |index| Tick {
+            index,
+            liquidity_net: 1,
+        };
+
+        let window = TickWindow::new(0, 600, [None, Some(vec![tick(10)]), Some(vec![tick(700)])]);
+        assert_eq!(
+            window,
+            Some(TickWindow {
+                lower: 0,
+                upper: 1200,
+                ticks: vec![tick(10), tick(700)],
+            })
+        );
+        assert_eq!(
+            TickWindow::new(0, 600, [Some(vec![]), None, Some(vec![])]),
+            None
+        );
+    }
+
+    #[test]
+    fn tick_arrays_around_the_current_tick() {
+        // Orca: 88 ticks of spacing 64 per array
+        assert_eq!(tick_array_start_indices(0, 64, 88), vec![-5632, 0, 5632]);
+        assert_eq!(tick_array_start_indices(5631, 64, 88), vec![-5632, 0, 5632]);
+        assert_eq!(tick_array_start_indices(5632, 64, 88), vec![0, 5632, 11264]);
+    }
+
+    #[test]
+    fn negative_ticks_round_down() {
+        // Raydium: 60 ticks of spacing 10 per array
+        assert_eq!(tick_array_start_indices(-1, 10, 60), vec![-1200, -600, 0]);
+        assert_eq!(tick_array_start_indices(-600, 10, 60), vec![-1200, -600, 0]);
+        assert_eq!(
+            tick_array_start_indices(-601, 10, 60),
+            vec![-1800, -1200, -600]
+        );
+    }
+
+    #[test]
+    fn zero_spacing_has_no_arrays() {
+        assert!(tick_array_start_indices(100, 0, 88).is_empty());
+    }
+}
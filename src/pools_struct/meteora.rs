@@ -0,0 +1,616 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::message::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use crate::pools_struct::{
+    clmm::{mul_div, mul_div_ceil, FEE_RATE_DENOMINATOR},
+    error::PoolError,
+    structs::{DexType, PriceFetcher, SWAP_DISCRIMINATOR},
+};
+use crate::utils::{get_associated_token_address, TOKEN_PROGRAM_ID};
+
+// Bins held by one DLMM bin array
+pub const BINS_PER_ARRAY: i32 = 70;
+// BinArray: discriminator, index, version, padding, lb_pair, bins
+const BIN_ARRAY_LEN: usize = 10136;
+const BIN_ARRAY_BINS_OFFSET: usize = 56;
+// Bin: amounts of X and Y, Q64.64 price, liquidity supply, reward and fee trackers
+const BIN_LEN: usize = 144;
+// DLMM fee rates are in billionths, capped at 10%
+const FEE_PRECISION: u128 = 1_000_000_000;
+const MAX_FEE_RATE: u128 = 100_000_000;
+const Q64: u128 = 1 << 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct StaticParameters {
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    pub min_bin_id: i32,
+    pub max_bin_id: i32,
+    pub protocol_share: u16,
+    pub base_fee_power_factor: u8,
+    pub padding: [u8; 5],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct VariableParameters {
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub index_reference: i32,
+    pub padding: [u8; 4],
+    pub last_update_timestamp: i64,
+    pub padding1: [u8; 8],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct ProtocolFee {
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct RewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub funder: Pubkey,
+    pub reward_duration: u64,
+    pub reward_duration_end: u64,
+    pub reward_rate: u128,
+    pub last_update_time: u64,
+    pub cumulative_seconds_with_empty_liquidity_reward: u64,
+}
+
+/// DLMM LbPair: liquidity sits in bins of constant price, X sells into lower bins and Y
+/// into higher ones
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct MeteoraPoolState {
+    pub parameters: StaticParameters,
+    pub v_parameters: VariableParameters,
+    pub bump_seed: [u8; 1],
+    pub bin_step_seed: [u8; 2],
+    pub pair_type: u8,
+    pub active_id: i32,
+    /// Price step between two bins, in basis points
+    pub bin_step: u16,
+    pub status: u8,
+    pub require_base_factor_seed: u8,
+    pub base_factor_seed: [u8; 2],
+    pub activation_type: u8,
+    pub creator_pool_on_off_control: u8,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub protocol_fee: ProtocolFee,
+    pub padding1: [u8; 32],
+    pub reward_infos: [RewardInfo; 2],
+    pub oracle: Pubkey,
+    pub bin_array_bitmap: [u64; 16],
+    pub last_updated_at: i64,
+    pub padding2: [u8; 32],
+    pub pre_activation_swap_address: Pubkey,
+    pub base_key: Pubkey,
+    pub activation_point: u64,
+    pub pre_activation_duration: u64,
+    pub padding3: [u8; 8],
+    pub padding4: u64,
+    pub creator: Pubkey,
+    pub token_mint_x_program_flag: u8,
+    pub token_mint_y_program_flag: u8,
+    pub reserved: [u8; 22],
+    /// Bins with liquidity around the active one, once the bin arrays are loaded
+    #[borsh(skip)]
+    pub bins: Option<BinWindow>,
+}
+
+/// Bin holding liquidity, at a constant price of Y per X in Q64.64
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bin {
+    pub id: i32,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub price: u128,
+}
+
+/// Bins with liquidity of the loaded bin arrays around the active bin and the ids those
+/// arrays cover, quotes can't step past them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinWindow {
+    pub lower: i32,
+    pub upper: i32,
+    /// Sorted by id
+    pub bins: Vec<Bin>,
+}
+
+impl BinWindow {
+    /// Window over the arrays below, holding and above the active bin, in that order.
+    /// None until the active one is loaded, a missing neighbour narrows the window
+    pub fn new(active_index: i64, arrays: [Option<Vec<Bin>>; 3]) -> Option<Self> {
+        let [below, current, above] = arrays;
+        let current = current?;
+
+        let first_id = active_index * BINS_PER_ARRAY as i64;
+        let lower = if below.is_some() {
+            first_id - BINS_PER_ARRAY as i64
+        } else {
+            first_id
+        };
+        let upper = if above.is_some() {
+            first_id + 2 * BINS_PER_ARRAY as i64 - 1
+        } else {
+            first_id + BINS_PER_ARRAY as i64 - 1
+        };
+
+        let mut bins = below
+            .into_iter()
+            .chain(Some(current))
+            .chain(above)
+            .flatten()
+            .collect::<Vec<_>>();
+        bins.sort_by_key(|bin| bin.id);
+
+        Some(Self {
+            lower: i32::try_from(lower).ok()?,
+            upper: i32::try_from(upper).ok()?,
+            bins,
+        })
+    }
+
+    fn get(&self, id: i32) -> Option<&Bin> {
+        let position = self.bins.binary_search_by_key(&id, |bin| bin.id).ok()?;
+        self.bins.get(position)
+    }
+}
+
+/// Index of the bin array holding `bin_id`, arrays of negative ids round down
+pub fn bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(BINS_PER_ARRAY) as i64
+}
+
+/// Address of the bin array of `pool_id` at `index`
+pub fn bin_array_address(pool_id: &Pubkey, index: i64) -> Pubkey {
+    let (bin_array, _) = Pubkey::find_program_address(
+        &[b"bin_array", pool_id.as_ref(), &index.to_le_bytes()],
+        &DexType::Meteora.program_id(),
+    );
+    bin_array
+}
+
+/// Bins with liquidity of a bin array, None unless it's the array of `pool_id` at `index`
+pub fn parse_bin_array(data: &[u8], pool_id: &Pubkey, index: i64) -> Option<Vec<Bin>> {
+    if data.len() != BIN_ARRAY_LEN
+        || data[24..56] != pool_id.to_bytes()
+        || i64::from_le_bytes(data[8..16].try_into().ok()?) != index
+    {
+        return None;
+    }
+
+    let first_id = i32::try_from(index * BINS_PER_ARRAY as i64).ok()?;
+    let bins = data[BIN_ARRAY_BINS_OFFSET..]
+        .as_chunks::<BIN_LEN>()
+        .0
+        .iter()
+        .enumerate()
+        .map(|(offset, bin)| Bin {
+            id: first_id + offset as i32,
+            amount_x: u64::from_le_bytes(bin[0..8].try_into().unwrap()),
+            amount_y: u64::from_le_bytes(bin[8..16].try_into().unwrap()),
+            price: u128::from_le_bytes(bin[16..32].try_into().unwrap()),
+        })
+        .filter(|bin| bin.price != 0 && (bin.amount_x != 0 || bin.amount_y != 0))
+        .collect();
+
+    Some(bins)
+}
+
+/// Output of an exact-in swap stepping across the bins of `window` from `active_id`, the
+/// fee taken from the input of every bin like the program does. None when the swap would
+/// leave the window or the math overflows
+pub fn quote_across_bins(
+    active_id: i32,
+    window: &BinWindow,
+    amount_in: u64,
+    swap_for_y: bool,
+    fee_rate: u32,
+) -> Option<u64> {
+    let fee_rate = fee_rate as u128;
+    let denominator = FEE_RATE_DENOMINATOR as u128;
+    if fee_rate >= denominator {
+        return None;
+    }
+
+    let mut id = active_id;
+    let mut remaining = amount_in as u128;
+    let mut amount_out = 0u128;
+
+    while remaining > 0 {
+        if !(window.lower..=window.upper).contains(&id) {
+            // Input left past the arrays we loaded
+            return None;
+        }
+
+        if let Some(bin) = window.get(id) {
+            // Selling X takes the bin's Y at its price, selling Y takes its X
+            let (available, needed) = if swap_for_y {
+                let y = bin.amount_y as u128;
+                (y, mul_div_ceil(y, Q64, bin.price)?)
+            } else {
+                let x = bin.amount_x as u128;
+                (x, mul_div_ceil(x, bin.price, Q64)?)
+            };
+            let fee = mul_div_ceil(needed, fee_rate, denominator - fee_rate)?;
+
+            if available > 0 && remaining < needed + fee {
+                let fee = mul_div_ceil(remaining, fee_rate, denominator)?;
+                let after_fee = remaining - fee;
+                let out = if swap_for_y {
+                    mul_div(after_fee, bin.price, Q64)?
+                } else {
+                    mul_div(after_fee, Q64, bin.price)?
+                };
+                amount_out += out.min(available);
+                break;
+            }
+
+            amount_out += available;
+            remaining -= needed + fee;
+        }
+
+        id = if swap_for_y { id - 1 } else { id + 1 };
+    }
+
+    u64::try_from(amount_out).ok()
+}
+
+impl MeteoraPoolState {
+    /// Y per X at the active bin, in raw token units
+    pub fn calculate_price(&self) -> Result<f64, PoolError> {
+        if self.bin_step == 0 {
+            return Err(PoolError::PriceCalculationFailed);
+        }
+
+        Ok((1.0 + self.bin_step as f64 / 10_000.0).powi(self.active_id))
+    }
+
+    /// Base fee plus the variable fee at the current volatility, in billionths
+    fn total_fee_rate(&self) -> Option<u128> {
+        let parameters = &self.parameters;
+        let bin_step = self.bin_step as u128;
+
+        let base = (parameters.base_factor as u128)
+            .checked_mul(bin_step * 10)?
+            .checked_mul(10u128.checked_pow(parameters.base_fee_power_factor as u32)?)?;
+        let volatility = self.v_parameters.volatility_accumulator as u128 * bin_step;
+        let variable = volatility
+            .checked_mul(volatility)?
+            .checked_mul(parameters.variable_fee_control as u128)?
+            .div_ceil(100_000_000_000);
+
+        Some(base.checked_add(variable)?.min(MAX_FEE_RATE))
+    }
+
+    /// Index and address of the bin arrays below, holding and above the active bin
+    fn bin_arrays(&self, pool_id: &Pubkey) -> Vec<(i64, Pubkey)> {
+        let active = bin_array_index(self.active_id);
+
+        [active - 1, active, active + 1]
+            .into_iter()
+            .map(|index| (index, bin_array_address(pool_id, index)))
+            .collect()
+    }
+}
+
+impl PriceFetcher for MeteoraPoolState {
+    fn get_dex_name(&self) -> &'static str {
+        "meteora"
+    }
+
+    fn get_price(&self) -> Result<f64, PoolError> {
+        self.calculate_price()
+    }
+
+    fn get_mints(&self) -> Option<(Pubkey, Pubkey)> {
+        Some((self.token_x_mint, self.token_y_mint))
+    }
+
+    fn get_fee_rate(&self) -> Option<u32> {
+        // Billionths to hundredths of a basis point, rounding against us
+        let rate = self
+            .total_fee_rate()?
+            .div_ceil(FEE_PRECISION / FEE_RATE_DENOMINATOR as u128);
+        u32::try_from(rate).ok()
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool, fee_rate: u32) -> Option<u64> {
+        quote_across_bins(
+            self.active_id,
+            self.bins.as_ref()?,
+            amount_in,
+            a_to_b,
+            fee_rate,
+        )
+    }
+
+    fn spot_rate(&self, a_to_b: bool, fee_rate: u32) -> Option<f64> {
+        if fee_rate as u64 >= FEE_RATE_DENOMINATOR {
+            return None;
+        }
+
+        let price = self.calculate_price().ok()?;
+        let after_fee = 1.0 - fee_rate as f64 / FEE_RATE_DENOMINATOR as f64;
+
+        if a_to_b {
+            Some(price * after_fee)
+        } else {
+            Some(after_fee / price)
+        }
+    }
+
+    fn get_static_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.token_x_mint,
+            self.reserve_x,
+            self.token_y_mint,
+            self.reserve_y,
+            self.oracle,
+        ]
+    }
+
+    fn get_dependent_accounts(&self, pool_id: &Pubkey) -> Vec<Pubkey> {
+        let mut accounts = vec![self.reserve_x, self.reserve_y];
+        accounts.extend(
+            self.bin_arrays(pool_id)
+                .into_iter()
+                .map(|(_, bin_array)| bin_array),
+        );
+        accounts
+    }
+
+    fn load_dependent_accounts(&mut self, pool_id: &Pubkey, accounts: &HashMap<Pubkey, Vec<u8>>) {
+        let arrays = self
+            .bin_arrays(pool_id)
+            .into_iter()
+            .map(|(index, bin_array)| parse_bin_array(accounts.get(&bin_array)?, pool_id, index))
+            .collect::<Vec<_>>();
+
+        if let Ok(arrays) = <[_; 3]>::try_from(arrays) {
+            self.bins = BinWindow::new(bin_array_index(self.active_id), arrays);
+        }
+    }
+
+    fn swap_instruction(
+        &self,
+        pool_id: &Pubkey,
+        owner: &Pubkey,
+        amount_in: u64,
+        minimum_out: u64,
+        a_to_b: bool,
+    ) -> Option<Instruction> {
+        // Token-2022 mints have token accounts of their own program
+        if self.token_mint_x_program_flag != 0 || self.token_mint_y_program_flag != 0 {
+            return None;
+        }
+
+        let program_id = DexType::Meteora.program_id();
+        let (event_authority, _) =
+            Pubkey::find_program_address(&[b"__event_authority"], &program_id);
+        let active = bin_array_index(self.active_id);
+        let next = if a_to_b { active - 1 } else { active + 1 };
+        let (input_mint, output_mint) = if a_to_b {
+            (self.token_x_mint, self.token_y_mint)
+        } else {
+            (self.token_y_mint, self.token_x_mint)
+        };
+
+        let mut data = Vec::with_capacity(24);
+        data.extend_from_slice(&SWAP_DISCRIMINATOR);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_out.to_le_bytes());
+
+        Some(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(*pool_id, false),
+                // Optional accounts left out are passed as the program itself
+                AccountMeta::new_readonly(program_id, false),
+                AccountMeta::new(self.reserve_x, false),
+                AccountMeta::new(self.reserve_y, false),
+                AccountMeta::new(get_associated_token_address(owner, &input_mint), false),
+                AccountMeta::new(get_associated_token_address(owner, &output_mint), false),
+                AccountMeta::new_readonly(self.token_x_mint, false),
+                AccountMeta::new_readonly(self.token_y_mint, false),
+                AccountMeta::new(self.oracle, false),
+                AccountMeta::new_readonly(program_id, false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(event_authority, false),
+                AccountMeta::new_readonly(program_id, false),
+                // Bin arrays the swap crosses, in order
+                AccountMeta::new(bin_array_address(pool_id, active), false),
+                AccountMeta::new(bin_array_address(pool_id, next), false),
+            ],
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::parse_account_data;
+
+    // LbPair account size, discriminator included
+    const LB_PAIR_LEN: usize = 904;
+
+    fn bin_array(pool_id: &Pubkey, index: i64, bins: &[(usize, u64, u64, u128)]) -> Vec<u8> {
+        let mut data = vec![0u8; BIN_ARRAY_LEN];
+        data[8..16].copy_from_slice(&index.to_le_bytes());
+        data[24..56].copy_from_slice(pool_id.as_ref());
+        for (offset, amount_x, amount_y, price) in bins {
+            let bin = BIN_ARRAY_BINS_OFFSET + offset * BIN_LEN;
+            data[bin..bin + 8].copy_from_slice(&amount_x.to_le_bytes());
+            data[bin + 8..bin + 16].copy_from_slice(&amount_y.to_le_bytes());
+            data[bin + 16..bin + 32].copy_from_slice(&price.to_le_bytes());
+        }
+        data
+    }
+
+    fn pair(active_id: i32, bin_step: u16) -> MeteoraPoolState {
+        let mut state = MeteoraPoolState::try_from_slice(&[0u8; LB_PAIR_LEN - 8]).unwrap();
+        state.active_id = active_id;
+        state.bin_step = bin_step;
+        state
+    }
+
+    #[test]
+    fn lb_pair_decodes_from_account_data() {
+        let (mint_x, mint_y) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; LB_PAIR_LEN];
+        data[76..80].copy_from_slice(&(-12i32).to_le_bytes());
+        data[80..82].copy_from_slice(&25u16.to_le_bytes());
+        data[88..120].copy_from_slice(mint_x.as_ref());
+        data[120..152].copy_from_slice(mint_y.as_ref());
+
+        let state = parse_account_data(&data, DexType::Meteora).unwrap();
+        assert_eq!(state.get_mints(), Some((mint_x, mint_y)));
+        let expected = 1.0025_f64.powi(-12);
+        assert!((state.get_price().unwrap() - expected).abs() < 1e-12);
+
+        assert!(parse_account_data(&data[..LB_PAIR_LEN - 1], DexType::Meteora).is_err());
+    }
+
+    #[test]
+    fn reads_bins_with_liquidity() {
+        let pool_id = Pubkey::new_unique();
+        let data = bin_array(&pool_id, -1, &[(0, 5, 0, Q64), (69, 0, 7, Q64)]);
+
+        assert_eq!(
+            parse_bin_array(&data, &pool_id, -1),
+            Some(vec![
+                Bin {
+                    id: -70,
+                    amount_x: 5,
+                    amount_y: 0,
+                    price: Q64
+                },
+                Bin {
+                    id: -1,
+                    amount_x: 0,
+                    amount_y: 7,
+                    price: Q64
+                },
+            ])
+        );
+        assert_eq!(parse_bin_array(&data, &Pubkey::new_unique(), -1), None);
+        assert_eq!(parse_bin_array(&data, &pool_id, 0), None);
+    }
+
+    #[test]
+    fn bin_arrays_round_negative_ids_down() {
+        assert_eq!(bin_array_index(0), 0);
+        assert_eq!(bin_array_index(69), 0);
+        assert_eq!(bin_array_index(70), 1);
+        assert_eq!(bin_array_index(-1), -1);
+        assert_eq!(bin_array_index(-70), -1);
+        assert_eq!(bin_array_index(-71), -2);
+    }
+
+    #[test]
+    fn fee_rate_adds_the_variable_fee() {
+        let mut state = pair(0, 10);
+        state.parameters.base_factor = 10_000;
+        // 10000 · 10 · 10 billionths = 0.1%
+        assert_eq!(state.get_fee_rate(), Some(1000));
+
+        state.parameters.variable_fee_control = 40_000;
+        state.v_parameters.volatility_accumulator = 50_000;
+        // (50000 · 10)² · 40000 / 1e11 = 100000 billionths more
+        assert_eq!(state.get_fee_rate(), Some(1100));
+
+        // Capped at 10%
+        state.parameters.base_fee_power_factor = 3;
+        assert_eq!(state.get_fee_rate(), Some(100_000));
+    }
+
+    #[test]
+    fn quotes_step_across_loaded_bins() {
+        let pool_id = Pubkey::new_unique();
+        let mut state = pair(0, 100);
+        // Y per X is 1 in the active bin, 1/2 a bin lower and 2 a bin higher
+        let accounts = HashMap::from([
+            (
+                bin_array_address(&pool_id, -1),
+                bin_array(&pool_id, -1, &[(69, 0, 1_000, Q64 / 2)]),
+            ),
+            (
+                bin_array_address(&pool_id, 0),
+                bin_array(
+                    &pool_id,
+                    0,
+                    &[(0, 1_000, 1_000, Q64), (1, 1_000, 0, 2 * Q64)],
+                ),
+            ),
+            (bin_array_address(&pool_id, 1), bin_array(&pool_id, 1, &[])),
+        ]);
+        assert_eq!(state.quote(1, true, 0), None);
+        state.load_dependent_accounts(&pool_id, &accounts);
+
+        // All the Y of the active bin, then 500 X buy 250 Y a bin lower
+        assert_eq!(state.quote(1_500, true, 0), Some(1_250));
+        assert_eq!(state.quote(1_500, false, 0), Some(1_250));
+        // The fee comes off the input of every bin
+        assert_eq!(state.quote(1_000, true, 10_000), Some(990));
+
+        // Past the loaded arrays there is nothing to quote from
+        assert_eq!(state.quote(5_000, true, 0), None);
+    }
+
+    #[test]
+    fn dependent_accounts_follow_the_active_bin() {
+        let pool_id = Pubkey::new_unique();
+        let state = pair(-1, 10);
+
+        assert_eq!(
+            state.get_dependent_accounts(&pool_id)[2..],
+            [-2, -1, 0].map(|index| bin_array_address(&pool_id, index))
+        );
+    }
+
+    #[test]
+    fn swaps_cross_bin_arrays_in_their_direction() {
+        let (pool_id, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut state = pair(70, 10);
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, true)
+            .unwrap();
+        let accounts = swap
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts[15..],
+            [1, 0].map(|index| bin_array_address(&pool_id, index))
+        );
+        assert!(swap.accounts[10].is_signer);
+        assert_eq!(swap.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(swap.data[16..], 990u64.to_le_bytes());
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, false)
+            .unwrap();
+        assert_eq!(swap.accounts[16].pubkey, bin_array_address(&pool_id, 2));
+
+        state.token_mint_y_program_flag = 1;
+        assert!(state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, true)
+            .is_none());
+    }
+}
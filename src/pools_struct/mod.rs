@@ -1,12 +1,23 @@
+pub mod clmm;
 pub mod error;
 pub mod meteora;
 pub mod orca;
 pub mod raydium;
 
 pub mod structs {
+    use serde::Deserialize;
+    use solana_sdk::message::Instruction;
+    use solana_sdk::pubkey::Pubkey;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
     use crate::pools_struct::error::PoolError;
 
-    #[derive(Debug, Clone, Copy)]
+    // Orca, Raydium and Meteora all name it `swap`, sha256("global:swap")[..8]
+    pub const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
     pub enum DexType {
         Meteora,
         HumidiFi,
@@ -17,10 +28,100 @@ pub mod structs {
         Raydium,
     }
 
-    pub trait PriceFetcher {
+    impl DexType {
+        pub const ALL: [DexType; 7] = [
+            DexType::Meteora,
+            DexType::HumidiFi,
+            DexType::SolFiV2,
+            DexType::PancakeSwap,
+            DexType::Lifinity,
+            DexType::Orca,
+            DexType::Raydium,
+        ];
+
+        /// DEX whose program owns `owner`'s pools
+        pub fn from_program_id(owner: &Pubkey) -> Option<DexType> {
+            Self::ALL.into_iter().find(|dex| dex.program_id() == *owner)
+        }
+
+        /// Get the on-chain program owning pools of this DEX
+        pub fn program_id(&self) -> Pubkey {
+            let address = match self {
+                DexType::Meteora => "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo",
+                DexType::HumidiFi => "9H6tua7jkLhdm3w8BvgpTn5LZNU7g4ZynDmCiNN3q6Rp",
+                DexType::SolFiV2 => "SV2EYYJyRz2YhfXwXnhNAevDEui5Q6yrfyo13WtupPF",
+                DexType::PancakeSwap => "HpNfyc2Saw7RKkQd8nEL4khUcuPhQ7WwY1B2qjx8jxFq",
+                DexType::Lifinity => "2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXYEGPWSUgAPjV7Z",
+                DexType::Orca => "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc",
+                DexType::Raydium => "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+            };
+
+            Pubkey::from_str(address).unwrap()
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct Pool {
+        pub name: String,
+        pub pool_id: Pubkey,
+        pub pool: DexType,
+        /// Fee in hundredths of a basis point, for pools whose state doesn't hold it
+        pub fee_rate: Option<u32>,
+    }
+
+    pub trait PriceFetcher: Send + Sync {
         /// Get the current SOL/USDC price
         fn get_price(&self) -> Result<f64, PoolError>;
         /// Get the DEX name
         fn get_dex_name(&self) -> &'static str;
+        /// Get the (base, quote) mints of the pool
+        fn get_mints(&self) -> Option<(Pubkey, Pubkey)> {
+            None
+        }
+        /// Get the swap fee in hundredths of a basis point
+        fn get_fee_rate(&self) -> Option<u32> {
+            None
+        }
+        /// Quote an exact-in swap, None when the pool can't be quoted
+        fn quote(&self, _amount_in: u64, _a_to_b: bool, _fee_rate: u32) -> Option<u64> {
+            None
+        }
+        /// Get the active liquidity, None when the model doesn't track it
+        fn get_liquidity(&self) -> Option<u128> {
+            None
+        }
+        /// Get the marginal output per unit of input after fees, in raw token units
+        fn spot_rate(&self, _a_to_b: bool, _fee_rate: u32) -> Option<f64> {
+            None
+        }
+        /// Get the accounts a swap through this pool always touches (config, mints, vaults)
+        fn get_static_accounts(&self) -> Vec<Pubkey> {
+            Vec::new()
+        }
+        /// Get the accounts an exact quote needs at the current price (tick arrays, vaults),
+        /// they change as the price moves
+        fn get_dependent_accounts(&self, _pool_id: &Pubkey) -> Vec<Pubkey> {
+            Vec::new()
+        }
+        /// Load the data of the dependent accounts, keyed by address; quotes read what
+        /// was loaded and missing accounts are left out
+        fn load_dependent_accounts(
+            &mut self,
+            _pool_id: &Pubkey,
+            _accounts: &HashMap<Pubkey, Vec<u8>>,
+        ) {
+        }
+        /// Build an exact-in swap of `amount_in` for at least `minimum_out` between the
+        /// associated token accounts of `owner`, None when the pool can't be swapped through
+        fn swap_instruction(
+            &self,
+            _pool_id: &Pubkey,
+            _owner: &Pubkey,
+            _amount_in: u64,
+            _minimum_out: u64,
+            _a_to_b: bool,
+        ) -> Option<Instruction> {
+            None
+        }
     }
 }
@@ -1,7 +1,25 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::message::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
 
-use crate::pools_struct::{error::PoolError, structs::PriceFetcher};
+use crate::pools_struct::{
+    clmm::{quote_across_ticks, spot_rate, tick_array_start_indices, Tick, TickWindow},
+    error::PoolError,
+    structs::{DexType, PriceFetcher, SWAP_DISCRIMINATOR},
+};
+use crate::utils::{get_associated_token_address, TOKEN_PROGRAM_ID};
+
+// Ticks held by one Whirlpool tick array
+pub const TICK_ARRAY_SIZE: i32 = 88;
+// TickArray: discriminator, start tick index, ticks, whirlpool
+const TICK_ARRAY_LEN: usize = 9988;
+const TICK_ARRAY_TICKS_OFFSET: usize = 12;
+// Tick: initialized flag, liquidity_net, liquidity_gross, fee and reward growths
+const TICK_LEN: usize = 113;
+// Whirlpool's price bounds, swaps run until the amount is spent
+const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
 
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
 pub struct RewardInfo {
@@ -34,6 +52,9 @@ pub struct WhirlpoolState {
     pub fee_growth_global_b: u128,
     pub reward_last_updated_timestamp: u64,
     pub reward_infos: [RewardInfo; 3], // adjust NUM_REWARDS
+    /// Initialized ticks around the current one, once the tick arrays are loaded
+    #[borsh(skip)]
+    pub ticks: Option<TickWindow>,
 }
 
 impl WhirlpoolState {
@@ -46,6 +67,56 @@ impl WhirlpoolState {
 
         Ok(price)
     }
+
+    /// Start index and address of the tick arrays below, holding and above the current tick
+    fn tick_arrays(&self, pool_id: &Pubkey) -> Vec<(i32, Pubkey)> {
+        let program_id = DexType::Orca.program_id();
+
+        tick_array_start_indices(self.tick_current_index, self.tick_spacing, TICK_ARRAY_SIZE)
+            .into_iter()
+            .map(|start| {
+                let (tick_array, _) = Pubkey::find_program_address(
+                    &[
+                        b"tick_array",
+                        pool_id.as_ref(),
+                        start.to_string().as_bytes(),
+                    ],
+                    &program_id,
+                );
+                (start, tick_array)
+            })
+            .collect()
+    }
+}
+
+/// Initialized ticks of a Whirlpool tick array, None unless it's the array of `pool_id`
+/// starting at `start`
+pub fn parse_tick_array(
+    data: &[u8],
+    pool_id: &Pubkey,
+    start: i32,
+    tick_spacing: u16,
+) -> Option<Vec<Tick>> {
+    if data.len() != TICK_ARRAY_LEN
+        || data[TICK_ARRAY_LEN - 32..] != pool_id.to_bytes()
+        || i32::from_le_bytes(data[8..12].try_into().ok()?) != start
+    {
+        return None;
+    }
+
+    let ticks = data[TICK_ARRAY_TICKS_OFFSET..TICK_ARRAY_LEN - 32]
+        .as_chunks::<TICK_LEN>()
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, tick)| tick[0] != 0)
+        .map(|(offset, tick)| Tick {
+            index: start + offset as i32 * tick_spacing as i32,
+            liquidity_net: i128::from_le_bytes(tick[1..17].try_into().unwrap()),
+        })
+        .collect();
+
+    Some(ticks)
 }
 
 impl PriceFetcher for WhirlpoolState {
@@ -56,4 +127,208 @@ impl PriceFetcher for WhirlpoolState {
     fn get_price(&self) -> Result<f64, PoolError> {
         self.calculate_price()
     }
+
+    fn get_mints(&self) -> Option<(Pubkey, Pubkey)> {
+        Some((self.token_mint_a, self.token_mint_b))
+    }
+
+    fn get_fee_rate(&self) -> Option<u32> {
+        Some(self.fee_rate as u32)
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool, fee_rate: u32) -> Option<u64> {
+        quote_across_ticks(
+            self.sqrt_price,
+            self.liquidity,
+            self.tick_current_index,
+            self.ticks.as_ref()?,
+            amount_in,
+            a_to_b,
+            fee_rate,
+        )
+    }
+
+    fn get_liquidity(&self) -> Option<u128> {
+        Some(self.liquidity)
+    }
+
+    fn spot_rate(&self, a_to_b: bool, fee_rate: u32) -> Option<f64> {
+        spot_rate(self.sqrt_price, a_to_b, fee_rate)
+    }
+
+    fn get_static_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.whirlpools_config,
+            self.token_mint_a,
+            self.token_vault_a,
+            self.token_mint_b,
+            self.token_vault_b,
+        ]
+    }
+
+    fn get_dependent_accounts(&self, pool_id: &Pubkey) -> Vec<Pubkey> {
+        let mut accounts = vec![self.token_vault_a, self.token_vault_b];
+        accounts.extend(
+            self.tick_arrays(pool_id)
+                .into_iter()
+                .map(|(_, tick_array)| tick_array),
+        );
+        accounts
+    }
+
+    fn load_dependent_accounts(&mut self, pool_id: &Pubkey, accounts: &HashMap<Pubkey, Vec<u8>>) {
+        let tick_arrays = self.tick_arrays(pool_id);
+        let Some(&(current_start, _)) = tick_arrays.get(1) else {
+            return;
+        };
+
+        let arrays = tick_arrays
+            .into_iter()
+            .map(|(start, tick_array)| {
+                let data = accounts.get(&tick_array)?;
+                parse_tick_array(data, pool_id, start, self.tick_spacing)
+            })
+            .collect::<Vec<_>>();
+        if let Ok(arrays) = <[_; 3]>::try_from(arrays) {
+            let ticks_in_array = self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+            self.ticks = TickWindow::new(current_start, ticks_in_array, arrays);
+        }
+    }
+
+    fn swap_instruction(
+        &self,
+        pool_id: &Pubkey,
+        owner: &Pubkey,
+        amount_in: u64,
+        minimum_out: u64,
+        a_to_b: bool,
+    ) -> Option<Instruction> {
+        let program_id = DexType::Orca.program_id();
+        let [(_, below), (_, current), (_, above)] =
+            <[_; 3]>::try_from(self.tick_arrays(pool_id)).ok()?;
+        // Quotes stop at the neighbouring array, the swap can't need a third one
+        let next = if a_to_b { below } else { above };
+        let (oracle, _) = Pubkey::find_program_address(&[b"oracle", pool_id.as_ref()], &program_id);
+        let sqrt_price_limit = if a_to_b {
+            MIN_SQRT_PRICE_X64
+        } else {
+            MAX_SQRT_PRICE_X64
+        };
+
+        let mut data = Vec::with_capacity(42);
+        data.extend_from_slice(&SWAP_DISCRIMINATOR);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_out.to_le_bytes());
+        data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+        // amount_specified_is_input, a_to_b
+        data.extend_from_slice(&[1, a_to_b as u8]);
+
+        Some(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new(*pool_id, false),
+                AccountMeta::new(
+                    get_associated_token_address(owner, &self.token_mint_a),
+                    false,
+                ),
+                AccountMeta::new(self.token_vault_a, false),
+                AccountMeta::new(
+                    get_associated_token_address(owner, &self.token_mint_b),
+                    false,
+                ),
+                AccountMeta::new(self.token_vault_b, false),
+                AccountMeta::new(current, false),
+                AccountMeta::new(next, false),
+                AccountMeta::new(next, false),
+                AccountMeta::new(oracle, false),
+            ],
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_array(pool_id: &Pubkey, start: i32, initialized: &[(usize, i128)]) -> Vec<u8> {
+        let mut data = vec![0u8; TICK_ARRAY_LEN];
+        data[8..12].copy_from_slice(&start.to_le_bytes());
+        for (offset, liquidity_net) in initialized {
+            let tick = TICK_ARRAY_TICKS_OFFSET + offset * TICK_LEN;
+            data[tick] = 1;
+            data[tick + 1..tick + 17].copy_from_slice(&liquidity_net.to_le_bytes());
+        }
+        data[TICK_ARRAY_LEN - 32..].copy_from_slice(pool_id.as_ref());
+        data
+    }
+
+    #[test]
+    fn reads_initialized_ticks_at_their_spacing() {
+        let pool_id = Pubkey::new_unique();
+        let data = tick_array(&pool_id, -5632, &[(0, 7), (87, -7)]);
+
+        assert_eq!(
+            parse_tick_array(&data, &pool_id, -5632, 64),
+            Some(vec![
+                Tick {
+                    index: -5632,
+                    liquidity_net: 7
+                },
+                Tick {
+                    index: -64,
+                    liquidity_net: -7
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_arrays_of_other_pools_or_starts() {
+        let pool_id = Pubkey::new_unique();
+        let data = tick_array(&pool_id, 0, &[]);
+
+        assert_eq!(parse_tick_array(&data, &pool_id, 0, 64), Some(vec![]));
+        assert_eq!(parse_tick_array(&data, &Pubkey::new_unique(), 0, 64), None);
+        assert_eq!(parse_tick_array(&data, &pool_id, 5632, 64), None);
+        assert_eq!(parse_tick_array(&data[1..], &pool_id, 0, 64), None);
+    }
+
+    #[test]
+    fn swaps_through_the_arrays_the_price_moves_into() {
+        let (pool_id, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut state = WhirlpoolState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        state.tick_spacing = 64;
+        let [(_, below), (_, current), (_, above)] =
+            <[_; 3]>::try_from(state.tick_arrays(&pool_id)).unwrap();
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, true)
+            .unwrap();
+        let accounts = swap
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(accounts[7..10], [current, below, below]);
+        assert!(swap.accounts[1].is_signer);
+        assert_eq!(swap.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(swap.data[16..24], 990u64.to_le_bytes());
+        assert_eq!(swap.data[24..40], MIN_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(swap.data[40..], [1, 1]);
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, false)
+            .unwrap();
+        let accounts = swap
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(accounts[7..10], [current, above, above]);
+        assert_eq!(swap.data[24..40], MAX_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(swap.data[40..], [1, 0]);
+    }
 }
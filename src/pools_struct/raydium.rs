@@ -1,11 +1,27 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::message::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
 
-use crate::pools_struct::{error::PoolError, structs::PriceFetcher};
+use crate::pools_struct::{
+    clmm::{quote_across_ticks, spot_rate, tick_array_start_indices, Tick, TickWindow},
+    error::PoolError,
+    structs::{DexType, PriceFetcher, SWAP_DISCRIMINATOR},
+};
+use crate::utils::{get_associated_token_address, TOKEN_PROGRAM_ID};
 
 // Number of reward tokens
 pub const REWARD_NUM: usize = 3;
 pub const TICK_ARRAY_BITMAP_SIZE: usize = 16;
+// Ticks held by one tick array
+pub const TICK_ARRAY_SIZE: i32 = 60;
+// TickArrayState: discriminator, pool, start tick index, ticks, then counters and padding
+const TICK_ARRAY_LEN: usize = 10240;
+const TICK_ARRAY_TICKS_OFFSET: usize = 44;
+// TickState: tick, liquidity_net, liquidity_gross, fee and reward growths, padding
+const TICK_LEN: usize = 168;
+// AmmConfig keeps the trade fee after discriminator, bump, index, owner and protocol fee
+const AMM_CONFIG_TRADE_FEE_RATE_OFFSET: usize = 47;
 
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
 pub struct RewardInfo {
@@ -73,6 +89,12 @@ pub struct RaydiumPoolState {
     pub recent_epoch: u64,
     pub padding1: [u64; 24],
     pub padding2: [u64; 32],
+    /// Trade fee of the pool's AmmConfig, once it's loaded
+    #[borsh(skip)]
+    pub trade_fee_rate: Option<u32>,
+    /// Initialized ticks around the current one, once the tick arrays are loaded
+    #[borsh(skip)]
+    pub ticks: Option<TickWindow>,
 }
 
 impl RaydiumPoolState {
@@ -86,6 +108,63 @@ impl RaydiumPoolState {
 
         Ok(price)
     }
+
+    /// Start index and address of the tick arrays below, holding and above the current tick
+    fn tick_arrays(&self, pool_id: &Pubkey) -> Vec<(i32, Pubkey)> {
+        let program_id = DexType::Raydium.program_id();
+
+        tick_array_start_indices(self.tick_current, self.tick_spacing, TICK_ARRAY_SIZE)
+            .into_iter()
+            .map(|start| {
+                let (tick_array, _) = Pubkey::find_program_address(
+                    &[b"tick_array", pool_id.as_ref(), &start.to_be_bytes()],
+                    &program_id,
+                );
+                (start, tick_array)
+            })
+            .collect()
+    }
+}
+
+/// Tick arrays past the pool's own bitmap are tracked in this extension
+fn bitmap_extension(pool_id: &Pubkey) -> Pubkey {
+    let (bitmap_extension, _) = Pubkey::find_program_address(
+        &[b"pool_tick_array_bitmap_extension", pool_id.as_ref()],
+        &DexType::Raydium.program_id(),
+    );
+    bitmap_extension
+}
+
+/// Trade fee of an AmmConfig account, in hundredths of a basis point
+pub fn amm_config_trade_fee_rate(data: &[u8]) -> Option<u32> {
+    let bytes = data.get(AMM_CONFIG_TRADE_FEE_RATE_OFFSET..AMM_CONFIG_TRADE_FEE_RATE_OFFSET + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Initialized ticks of a tick array, None unless it's the array of `pool_id` starting at
+/// `start`
+pub fn parse_tick_array(data: &[u8], pool_id: &Pubkey, start: i32) -> Option<Vec<Tick>> {
+    if data.len() != TICK_ARRAY_LEN
+        || data[8..40] != pool_id.to_bytes()
+        || i32::from_le_bytes(data[40..44].try_into().ok()?) != start
+    {
+        return None;
+    }
+
+    let ticks = data[TICK_ARRAY_TICKS_OFFSET..]
+        .as_chunks::<TICK_LEN>()
+        .0
+        .iter()
+        .take(TICK_ARRAY_SIZE as usize)
+        // Ticks no position references have no gross liquidity
+        .filter(|tick| tick[20..36].iter().any(|byte| *byte != 0))
+        .map(|tick| Tick {
+            index: i32::from_le_bytes(tick[0..4].try_into().unwrap()),
+            liquidity_net: i128::from_le_bytes(tick[4..20].try_into().unwrap()),
+        })
+        .collect();
+
+    Some(ticks)
 }
 
 impl PriceFetcher for RaydiumPoolState {
@@ -96,4 +175,223 @@ impl PriceFetcher for RaydiumPoolState {
     fn get_price(&self) -> Result<f64, PoolError> {
         self.calculate_price()
     }
+
+    fn get_mints(&self) -> Option<(Pubkey, Pubkey)> {
+        Some((self.token_mint_0, self.token_mint_1))
+    }
+
+    fn get_fee_rate(&self) -> Option<u32> {
+        self.trade_fee_rate
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool, fee_rate: u32) -> Option<u64> {
+        quote_across_ticks(
+            self.sqrt_price_x64,
+            self.liquidity,
+            self.tick_current,
+            self.ticks.as_ref()?,
+            amount_in,
+            a_to_b,
+            fee_rate,
+        )
+    }
+
+    fn get_liquidity(&self) -> Option<u128> {
+        Some(self.liquidity)
+    }
+
+    fn spot_rate(&self, a_to_b: bool, fee_rate: u32) -> Option<f64> {
+        spot_rate(self.sqrt_price_x64, a_to_b, fee_rate)
+    }
+
+    fn get_static_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.amm_config,
+            self.token_mint_0,
+            self.token_vault_0,
+            self.token_mint_1,
+            self.token_vault_1,
+            self.observation_key,
+        ]
+    }
+
+    fn get_dependent_accounts(&self, pool_id: &Pubkey) -> Vec<Pubkey> {
+        let mut accounts = vec![
+            self.amm_config,
+            self.token_vault_0,
+            self.token_vault_1,
+            bitmap_extension(pool_id),
+        ];
+        accounts.extend(
+            self.tick_arrays(pool_id)
+                .into_iter()
+                .map(|(_, tick_array)| tick_array),
+        );
+        accounts
+    }
+
+    fn load_dependent_accounts(&mut self, pool_id: &Pubkey, accounts: &HashMap<Pubkey, Vec<u8>>) {
+        if let Some(data) = accounts.get(&self.amm_config) {
+            self.trade_fee_rate = amm_config_trade_fee_rate(data);
+        }
+
+        let tick_arrays = self.tick_arrays(pool_id);
+        let Some(&(current_start, _)) = tick_arrays.get(1) else {
+            return;
+        };
+
+        let arrays = tick_arrays
+            .into_iter()
+            .map(|(start, tick_array)| parse_tick_array(accounts.get(&tick_array)?, pool_id, start))
+            .collect::<Vec<_>>();
+        if let Ok(arrays) = <[_; 3]>::try_from(arrays) {
+            let ticks_in_array = self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+            self.ticks = TickWindow::new(current_start, ticks_in_array, arrays);
+        }
+    }
+
+    fn swap_instruction(
+        &self,
+        pool_id: &Pubkey,
+        owner: &Pubkey,
+        amount_in: u64,
+        minimum_out: u64,
+        a_to_b: bool,
+    ) -> Option<Instruction> {
+        let [(_, below), (_, current), (_, above)] =
+            <[_; 3]>::try_from(self.tick_arrays(pool_id)).ok()?;
+        let (input_mint, input_vault, output_mint, output_vault, next) = if a_to_b {
+            (
+                self.token_mint_0,
+                self.token_vault_0,
+                self.token_mint_1,
+                self.token_vault_1,
+                below,
+            )
+        } else {
+            (
+                self.token_mint_1,
+                self.token_vault_1,
+                self.token_mint_0,
+                self.token_vault_0,
+                above,
+            )
+        };
+
+        let mut data = Vec::with_capacity(41);
+        data.extend_from_slice(&SWAP_DISCRIMINATOR);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_out.to_le_bytes());
+        // No price limit, the program swaps up to its tick range bound
+        data.extend_from_slice(&0u128.to_le_bytes());
+        // is_base_input
+        data.push(1);
+
+        Some(Instruction {
+            program_id: DexType::Raydium.program_id(),
+            accounts: vec![
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new_readonly(self.amm_config, false),
+                AccountMeta::new(*pool_id, false),
+                AccountMeta::new(get_associated_token_address(owner, &input_mint), false),
+                AccountMeta::new(get_associated_token_address(owner, &output_mint), false),
+                AccountMeta::new(input_vault, false),
+                AccountMeta::new(output_vault, false),
+                AccountMeta::new(self.observation_key, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new(current, false),
+                // Remaining accounts, the program tells the extension apart by address
+                AccountMeta::new(bitmap_extension(pool_id), false),
+                AccountMeta::new(next, false),
+            ],
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_array(pool_id: &Pubkey, start: i32, initialized: &[(usize, i32, i128)]) -> Vec<u8> {
+        let mut data = vec![0u8; TICK_ARRAY_LEN];
+        data[8..40].copy_from_slice(pool_id.as_ref());
+        data[40..44].copy_from_slice(&start.to_le_bytes());
+        for (offset, index, liquidity_net) in initialized {
+            let tick = TICK_ARRAY_TICKS_OFFSET + offset * TICK_LEN;
+            data[tick..tick + 4].copy_from_slice(&index.to_le_bytes());
+            data[tick + 4..tick + 20].copy_from_slice(&liquidity_net.to_le_bytes());
+            data[tick + 20..tick + 36].copy_from_slice(&liquidity_net.unsigned_abs().to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn reads_ticks_with_gross_liquidity() {
+        let pool_id = Pubkey::new_unique();
+        let data = tick_array(&pool_id, -600, &[(3, -570, -9), (59, -10, 9)]);
+
+        assert_eq!(
+            parse_tick_array(&data, &pool_id, -600),
+            Some(vec![
+                Tick {
+                    index: -570,
+                    liquidity_net: -9
+                },
+                Tick {
+                    index: -10,
+                    liquidity_net: 9
+                },
+            ])
+        );
+        assert_eq!(parse_tick_array(&data, &Pubkey::new_unique(), -600), None);
+        assert_eq!(parse_tick_array(&data, &pool_id, 0), None);
+    }
+
+    #[test]
+    fn reads_the_trade_fee_of_an_amm_config() {
+        let mut config = vec![0u8; 117];
+        config[AMM_CONFIG_TRADE_FEE_RATE_OFFSET..AMM_CONFIG_TRADE_FEE_RATE_OFFSET + 4]
+            .copy_from_slice(&2500u32.to_le_bytes());
+
+        assert_eq!(amm_config_trade_fee_rate(&config), Some(2500));
+        assert_eq!(amm_config_trade_fee_rate(&config[..50]), None);
+    }
+
+    #[test]
+    fn swaps_from_the_input_vault_through_the_next_arrays() {
+        let (pool_id, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut state = RaydiumPoolState::deserialize(&mut &[0u8; 2048][..]).unwrap();
+        state.tick_spacing = 10;
+        state.token_vault_0 = Pubkey::new_unique();
+        state.token_vault_1 = Pubkey::new_unique();
+        let [(_, below), (_, current), (_, above)] =
+            <[_; 3]>::try_from(state.tick_arrays(&pool_id)).unwrap();
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, true)
+            .unwrap();
+        let accounts = swap
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(accounts[5..7], [state.token_vault_0, state.token_vault_1]);
+        assert_eq!(accounts[9..], [current, bitmap_extension(&pool_id), below]);
+        assert!(swap.accounts[0].is_signer);
+        assert_eq!(swap.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(swap.data[16..24], 990u64.to_le_bytes());
+        assert_eq!(swap.data[40], 1);
+
+        let swap = state
+            .swap_instruction(&pool_id, &owner, 1_000, 990, false)
+            .unwrap();
+        let accounts = swap
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(accounts[5..7], [state.token_vault_1, state.token_vault_0]);
+        assert_eq!(accounts[11], above);
+    }
 }
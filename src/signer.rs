@@ -0,0 +1,872 @@
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::message::{compiled_instruction::CompiledInstruction, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::assembler::{FeePolicy, LAMPORTS_PER_SIGNATURE, MAX_COMPUTE_UNIT_LIMIT};
+use crate::config::Secret;
+use crate::feed::endpoint_label;
+use crate::flash_providers::marginfi::marginfi_program_id;
+use crate::flash_providers::save::save_program_id;
+use crate::kamino::kamino_program_id;
+use crate::payer::get_payer;
+use crate::pools_struct::structs::DexType;
+use crate::utils::{COMPUTE_BUDGET_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+// Compute units an instruction gets when the transaction doesn't set a limit
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u32 = 200_000;
+// Give up on the signing service after this long
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(10);
+const SIGNER_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    Pubkey::new_from_array(spl_associated_token_account::ID.to_bytes());
+
+// System instructions the policy lets through, both fund an account from the first one
+const SYSTEM_CREATE_ACCOUNT: u32 = 0;
+const SYSTEM_TRANSFER: u32 = 2;
+// Token instructions the policy lets through
+const TOKEN_CLOSE_ACCOUNT: u8 = 9;
+const TOKEN_SYNC_NATIVE: u8 = 17;
+// Lookup table instructions, closing one hands its rent to the recipient
+const LOOKUP_TABLE_CREATE: u32 = 0;
+const LOOKUP_TABLE_EXTEND: u32 = 2;
+const LOOKUP_TABLE_CLOSE: u32 = 4;
+const LOOKUP_TABLE_META_SIZE: u64 = 56;
+
+// Rent exemption for two years at the current rate, per byte and per account
+const RENT_PER_BYTE: u64 = 6_960;
+const RENT_ACCOUNT_OVERHEAD: u64 = 128;
+// 170 bytes for a Token-2022 account with ImmutableOwner, a little more than a Token
+// account's 165
+const ASSOCIATED_TOKEN_ACCOUNT_SIZE: u64 = 170;
+
+fn rent_exempt_minimum(size: u64) -> u64 {
+    (RENT_ACCOUNT_OVERHEAD + size).saturating_mul(RENT_PER_BYTE)
+}
+
+/// Signs the transactions we send as their only signer and fee payer
+pub trait TxSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+
+    fn sign_message(&self, message: &VersionedMessage) -> Result<Signature, anyhow::Error>;
+}
+
+impl TxSigner for Keypair {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    fn sign_message(&self, message: &VersionedMessage) -> Result<Signature, anyhow::Error> {
+        Ok(Signer::sign_message(self, &message.serialize()))
+    }
+}
+
+/// What the remote signer agrees to sign. The signing service should enforce its own
+/// policy too, this catches a misbehaving bot before anything leaves the host
+#[derive(Debug, Clone)]
+pub struct SigningPolicy {
+    pub allowed_programs: HashSet<Pubkey>,
+    /// Most lamports a transaction may spend from the signer, fees included
+    pub max_lamports: u64,
+}
+
+/// Every program the bot's transactions invoke
+pub fn default_allowed_programs() -> HashSet<Pubkey> {
+    let mut programs = HashSet::from([
+        COMPUTE_BUDGET_PROGRAM_ID,
+        TOKEN_2022_PROGRAM_ID,
+        solana_system_interface::program::ID,
+        solana_address_lookup_table_interface::program::ID,
+        TOKEN_PROGRAM_ID,
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+        kamino_program_id(),
+        marginfi_program_id(),
+        save_program_id(),
+    ]);
+    programs.extend(DexType::ALL.iter().map(|dex| dex.program_id()));
+
+    programs
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// The instruction's account at `position`. Indexes past the static keys point into
+/// lookup tables, which never hold the signer
+fn account_at<'a>(
+    account_keys: &'a [Pubkey],
+    instruction: &CompiledInstruction,
+    position: usize,
+) -> Option<&'a Pubkey> {
+    let index = *instruction.accounts.get(position)?;
+    account_keys.get(index as usize)
+}
+
+impl SigningPolicy {
+    /// Lamports the message spends from its signer: signature and priority fees,
+    /// transfers and the rent of accounts it funds
+    pub fn spent_lamports(message: &VersionedMessage) -> u64 {
+        let mut compute_unit_limit = None;
+        let mut compute_unit_price = 0;
+        let mut instruction_count = 0u32;
+        let mut transferred = 0u64;
+
+        for instruction in message.instructions() {
+            // Programs are always static keys, lookup tables can't hold them
+            let program_id = message.static_account_keys()[instruction.program_id_index as usize];
+            let data = instruction.data.as_slice();
+
+            if program_id == COMPUTE_BUDGET_PROGRAM_ID {
+                match data.first() {
+                    Some(2) => compute_unit_limit = read_u32(data, 1),
+                    Some(3) => compute_unit_price = read_u64(data, 1).unwrap_or(0),
+                    _ => {}
+                }
+                continue;
+            }
+            instruction_count += 1;
+
+            // Only the signer can fund these, so every lamport they move is its
+            let moved = if program_id == solana_system_interface::program::ID {
+                match read_u32(data, 0) {
+                    Some(SYSTEM_CREATE_ACCOUNT) | Some(SYSTEM_TRANSFER) => {
+                        read_u64(data, 4).unwrap_or(0)
+                    }
+                    _ => 0,
+                }
+            } else if program_id == ASSOCIATED_TOKEN_PROGRAM_ID {
+                rent_exempt_minimum(ASSOCIATED_TOKEN_ACCOUNT_SIZE)
+            } else if program_id == solana_address_lookup_table_interface::program::ID {
+                match read_u32(data, 0) {
+                    Some(LOOKUP_TABLE_CREATE) => rent_exempt_minimum(LOOKUP_TABLE_META_SIZE),
+                    // The new addresses follow as a length prefixed vector
+                    Some(LOOKUP_TABLE_EXTEND) => read_u64(data, 4)
+                        .unwrap_or(0)
+                        .saturating_mul(32 * RENT_PER_BYTE),
+                    _ => 0,
+                }
+            } else {
+                0
+            };
+            transferred = transferred.saturating_add(moved);
+        }
+
+        let compute_unit_limit = compute_unit_limit.unwrap_or(
+            (instruction_count * DEFAULT_INSTRUCTION_COMPUTE_UNITS).min(MAX_COMPUTE_UNIT_LIMIT),
+        );
+        let priority_fee = FeePolicy {
+            compute_unit_limit,
+            compute_unit_price,
+            tip: 0,
+        }
+        .priority_fee(compute_unit_limit);
+
+        (message.header().num_required_signatures as u64 * LAMPORTS_PER_SIGNATURE)
+            .saturating_add(priority_fee)
+            .saturating_add(transferred)
+    }
+
+    /// Top-level instructions of the programs the signer's funds go through are checked
+    /// one by one, anything that could hand over tokens or authority is refused. Swaps
+    /// and flash loans move tokens by CPI from the programs they call
+    fn check_instruction(
+        account_keys: &[Pubkey],
+        program_id: &Pubkey,
+        instruction: &CompiledInstruction,
+        signer: &Pubkey,
+    ) -> Result<(), anyhow::Error> {
+        let data = instruction.data.as_slice();
+
+        if *program_id == solana_system_interface::program::ID {
+            match read_u32(data, 0) {
+                Some(SYSTEM_CREATE_ACCOUNT) | Some(SYSTEM_TRANSFER) => Ok(()),
+                other => bail!(
+                    "Signing policy doesn't allow System instruction {:?}",
+                    other
+                ),
+            }
+        } else if *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID {
+            match data.first() {
+                Some(&TOKEN_SYNC_NATIVE) => Ok(()),
+                Some(&TOKEN_CLOSE_ACCOUNT) => {
+                    if account_at(account_keys, instruction, 1) != Some(signer) {
+                        bail!("Signing policy only allows closing token accounts to the signer");
+                    }
+                    Ok(())
+                }
+                other => bail!("Signing policy doesn't allow token instruction {:?}", other),
+            }
+        } else if *program_id == ASSOCIATED_TOKEN_PROGRAM_ID {
+            // Create is also sent without data
+            match data.first() {
+                None | Some(0) | Some(1) => Ok(()),
+                other => bail!(
+                    "Signing policy doesn't allow associated token instruction {:?}",
+                    other
+                ),
+            }
+        } else if *program_id == solana_address_lookup_table_interface::program::ID {
+            if read_u32(data, 0) == Some(LOOKUP_TABLE_CLOSE)
+                && account_at(account_keys, instruction, 2) != Some(signer)
+            {
+                bail!("Signing policy only allows closing lookup tables to the signer");
+            }
+            Ok(())
+        } else if *program_id == COMPUTE_BUDGET_PROGRAM_ID {
+            match data.first() {
+                Some(1..=4) => Ok(()),
+                other => bail!(
+                    "Signing policy doesn't allow compute budget instruction {:?}",
+                    other
+                ),
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check(&self, message: &VersionedMessage, signer: &Pubkey) -> Result<(), anyhow::Error> {
+        let account_keys = message.static_account_keys();
+
+        for instruction in message.instructions() {
+            let program_id = account_keys
+                .get(instruction.program_id_index as usize)
+                .ok_or_else(|| anyhow!("Instruction program isn't in the message"))?;
+            if !self.allowed_programs.contains(program_id) {
+                bail!("Signing policy doesn't allow program {}", program_id);
+            }
+            Self::check_instruction(account_keys, program_id, instruction, signer)?;
+        }
+
+        let spent = Self::spent_lamports(message);
+        if spent > self.max_lamports {
+            bail!(
+                "Transaction spends {} lamports, the signing policy allows {}",
+                spent,
+                self.max_lamports
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the signing service listens
+#[derive(Clone)]
+pub enum SignerTransport {
+    /// HTTP or HTTPS, plain HTTP only on a private network
+    Http(url::Url),
+    Unix(PathBuf),
+}
+
+#[derive(Clone)]
+pub struct RemoteSignerConfig {
+    pub transport: SignerTransport,
+    pub pubkey: Pubkey,
+    pub policy: SigningPolicy,
+}
+
+/// How the bot's transactions get signed
+#[derive(Clone)]
+pub enum SignerConfig {
+    Local(Secret),
+    Remote(RemoteSignerConfig),
+}
+
+pub fn load_signer(config: &SignerConfig) -> Result<Box<dyn TxSigner>, anyhow::Error> {
+    match config {
+        SignerConfig::Local(source) => Ok(Box::new(get_payer(source)?)),
+        SignerConfig::Remote(remote) => Ok(Box::new(RemoteSigner::new(remote.clone()))),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignRequest {
+    pubkey: String,
+    /// Base64 of the serialized message, v0 for everything the bot sends
+    message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignResponse {
+    signature: Option<String>,
+    error: Option<String>,
+}
+
+/// Sends message bytes to a signing service and checks the signature it returns. The
+/// request is one JSON object, `{"pubkey", "message"}` with the message in base64, over
+/// a Unix socket or as an HTTP POST body. The reply is `{"signature"}` in base58 or
+/// `{"error"}`
+pub struct RemoteSigner {
+    config: RemoteSignerConfig,
+}
+
+impl RemoteSigner {
+    pub fn new(config: RemoteSignerConfig) -> Self {
+        Self { config }
+    }
+
+    fn request(&self, body: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        off_runtime(|| match &self.config.transport {
+            SignerTransport::Unix(path) => {
+                let mut stream = UnixStream::connect(path)
+                    .with_context(|| format!("Can't reach signer at {}", path.display()))?;
+                stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+                stream.set_write_timeout(Some(SIGNER_TIMEOUT))?;
+
+                stream.write_all(body)?;
+                stream.write_all(b"\n")?;
+                stream.shutdown(std::net::Shutdown::Write)?;
+
+                let mut response = vec![];
+                stream.read_to_end(&mut response)?;
+                Ok(response)
+            }
+            SignerTransport::Http(url) => http_post(url, body)
+                .with_context(|| format!("Signer at {}", endpoint_label(url.as_str()))),
+        })
+    }
+}
+
+/// Wait on the signer from sync code that may run on a runtime worker. On the
+/// multi-thread runtime the worker hands its other tasks to another thread for as long as
+/// the signer takes, instead of stalling them for up to `SIGNER_TIMEOUT`
+fn off_runtime<T>(request: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(request)
+        }
+        _ => request(),
+    }
+}
+
+/// POST the request to the signer. reqwest's blocking client refuses to run on a runtime
+/// thread and some commands sign from one, so the request gets a thread of its own
+fn http_post(url: &url::Url, body: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let client = reqwest::blocking::Client::builder()
+                    .connect_timeout(SIGNER_CONNECT_TIMEOUT)
+                    .timeout(SIGNER_TIMEOUT)
+                    .build()?;
+                // The URL may carry a token, errors leave it out
+                let response = client
+                    .post(url.as_str())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.to_vec())
+                    .send()
+                    .map_err(reqwest::Error::without_url)?;
+                let status = response.status();
+                let body = response.bytes().map_err(reqwest::Error::without_url)?;
+
+                if !status.is_success() {
+                    bail!("HTTP {}: {}", status, String::from_utf8_lossy(&body).trim());
+                }
+                Ok(body.to_vec())
+            })
+            .join()
+            .map_err(|_| anyhow!("signer request panicked"))?
+    })
+}
+
+impl TxSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.config.pubkey
+    }
+
+    fn sign_message(&self, message: &VersionedMessage) -> Result<Signature, anyhow::Error> {
+        self.config.policy.check(message, &self.config.pubkey)?;
+
+        let message_bytes = message.serialize();
+        let request = serde_json::to_vec(&SignRequest {
+            pubkey: self.config.pubkey.to_string(),
+            message: base64::engine::general_purpose::STANDARD.encode(&message_bytes),
+        })?;
+
+        let response = serde_json::from_slice::<SignResponse>(&self.request(&request)?)
+            .context("Invalid response from the signer")?;
+        let signature = match (response.signature, response.error) {
+            (_, Some(error)) => bail!("Signer refused: {}", error),
+            (Some(signature), None) => Signature::from_str(&signature)
+                .map_err(|_| anyhow!("Signer returned an invalid signature"))?,
+            (None, None) => bail!("Signer returned no signature"),
+        };
+
+        // A signature from another key would only fail once the transaction is sent
+        if !signature.verify(self.config.pubkey.as_ref(), &message_bytes) {
+            bail!(
+                "Signer returned a signature that isn't {}'s",
+                self.config.pubkey
+            );
+        }
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::sign_transaction;
+    use crate::utils::{from_program_instruction, program_pubkey};
+    use crate::utils::{set_compute_unit_limit, set_compute_unit_price};
+    use solana_address_lookup_table_interface::instruction::{
+        close_lookup_table, extend_lookup_table,
+    };
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::{v0, AddressLookupTableAccount, Message};
+    use solana_sdk::transaction::VersionedTransaction;
+    use solana_system_interface::instruction::{
+        assign, create_account, transfer, transfer_with_seed,
+    };
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+    use std::time::Instant;
+
+    fn legacy(
+        instructions: &[solana_sdk::message::Instruction],
+        payer: &Pubkey,
+    ) -> VersionedMessage {
+        VersionedMessage::Legacy(Message::new(instructions, Some(payer)))
+    }
+
+    fn verified(tx: &VersionedTransaction) -> bool {
+        tx.verify_with_results().into_iter().all(|valid| valid)
+    }
+
+    fn policy(max_lamports: u64) -> SigningPolicy {
+        SigningPolicy {
+            allowed_programs: default_allowed_programs(),
+            max_lamports,
+        }
+    }
+
+    /// Signs whatever it's sent with `keypair`, like the signing service would
+    fn stub_response(keypair: &Keypair, request: &[u8]) -> Vec<u8> {
+        let request = serde_json::from_slice::<SignRequest>(request).unwrap();
+        let message = base64::engine::general_purpose::STANDARD
+            .decode(request.message)
+            .unwrap();
+
+        serde_json::to_vec(&SignResponse {
+            signature: Some(Signer::sign_message(keypair, &message).to_string()),
+            error: None,
+        })
+        .unwrap()
+    }
+
+    fn unix_stub(keypair: Keypair, delay: Duration) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "arbitrage-signer-{}-{}.sock",
+            std::process::id(),
+            Signer::pubkey(&keypair)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            thread::sleep(delay);
+            (&stream)
+                .write_all(&stub_response(&keypair, request.as_bytes()))
+                .unwrap();
+        });
+
+        path
+    }
+
+    fn http_stub(keypair: Keypair) -> url::Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = stub_response(&keypair, &body);
+            write!(
+                &stream,
+                "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n",
+                response.len()
+            )
+            .unwrap();
+            (&stream).write_all(&response).unwrap();
+        });
+
+        url::Url::parse(&format!("http://{}/sign", address)).unwrap()
+    }
+
+    fn remote(transport: SignerTransport, pubkey: Pubkey, max_lamports: u64) -> RemoteSigner {
+        RemoteSigner::new(RemoteSignerConfig {
+            transport,
+            pubkey,
+            policy: policy(max_lamports),
+        })
+    }
+
+    fn transfer_of(signer: &Pubkey, lamports: u64) -> Vec<solana_sdk::message::Instruction> {
+        vec![
+            set_compute_unit_limit(200_000),
+            set_compute_unit_price(10_000),
+            transfer(signer, &Pubkey::new_unique(), lamports),
+        ]
+    }
+
+    #[test]
+    fn counts_fees_and_transfers() {
+        let signer = Pubkey::new_unique();
+        let message = legacy(&transfer_of(&signer, 1_000_000), &signer);
+
+        // Signature, 200k units at 10k micro-lamports and the transfer
+        assert_eq!(
+            SigningPolicy::spent_lamports(&message),
+            5_000 + 2_000 + 1_000_000
+        );
+    }
+
+    #[test]
+    fn policy_rejects_unknown_programs_and_large_spends() {
+        let signer = Pubkey::new_unique();
+        let message = legacy(&transfer_of(&signer, 1_000_000), &signer);
+        assert!(policy(1_007_000).check(&message, &signer).is_ok());
+        assert!(policy(1_006_999).check(&message, &signer).is_err());
+
+        let unknown =
+            solana_sdk::message::Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let message = legacy(&[unknown], &signer);
+        assert!(policy(u64::MAX).check(&message, &signer).is_err());
+    }
+
+    fn token_instruction(
+        instruction: Result<
+            spl_token::solana_program::instruction::Instruction,
+            spl_token::solana_program::program_error::ProgramError,
+        >,
+    ) -> solana_sdk::message::Instruction {
+        from_program_instruction(instruction.unwrap())
+    }
+
+    fn check(instruction: solana_sdk::message::Instruction, signer: &Pubkey) -> bool {
+        let message = legacy(&[instruction], signer);
+        policy(u64::MAX).check(&message, signer).is_ok()
+    }
+
+    #[test]
+    fn only_funding_system_instructions_are_allowed() {
+        let signer = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+
+        assert!(check(transfer(&signer, &account, 1), &signer));
+        assert!(check(
+            create_account(&signer, &account, 1, 0, &Pubkey::new_unique()),
+            &signer
+        ));
+        assert!(!check(assign(&signer, &Pubkey::new_unique()), &signer));
+        assert!(!check(
+            transfer_with_seed(&account, &signer, "seed".into(), &signer, &account, 1),
+            &signer
+        ));
+    }
+
+    #[test]
+    fn created_accounts_count_as_spent() {
+        let signer = Pubkey::new_unique();
+        let message = legacy(
+            &[create_account(
+                &signer,
+                &Pubkey::new_unique(),
+                1_000_000,
+                0,
+                &Pubkey::new_unique(),
+            )],
+            &signer,
+        );
+
+        // The payer's and new account's signatures, no priority fee and the new account's
+        // lamports
+        assert_eq!(SigningPolicy::spent_lamports(&message), 10_000 + 1_000_000);
+        assert!(policy(1_009_999).check(&message, &signer).is_err());
+    }
+
+    #[test]
+    fn token_transfers_and_authority_changes_are_refused() {
+        let signer = Pubkey::new_unique();
+        let (owner, source, destination) = (
+            program_pubkey(&signer),
+            program_pubkey(&Pubkey::new_unique()),
+            program_pubkey(&Pubkey::new_unique()),
+        );
+        let mint = program_pubkey(&Pubkey::new_unique());
+
+        for instruction in [
+            token_instruction(spl_token::instruction::transfer(
+                &spl_token::ID,
+                &source,
+                &destination,
+                &owner,
+                &[],
+                1,
+            )),
+            token_instruction(spl_token::instruction::transfer_checked(
+                &spl_token::ID,
+                &source,
+                &mint,
+                &destination,
+                &owner,
+                &[],
+                1,
+                6,
+            )),
+            token_instruction(spl_token::instruction::approve(
+                &spl_token::ID,
+                &source,
+                &destination,
+                &owner,
+                &[],
+                u64::MAX,
+            )),
+            token_instruction(spl_token::instruction::burn(
+                &spl_token::ID,
+                &source,
+                &mint,
+                &owner,
+                &[],
+                1,
+            )),
+            token_instruction(spl_token::instruction::set_authority(
+                &spl_token::ID,
+                &source,
+                Some(&destination),
+                spl_token::instruction::AuthorityType::AccountOwner,
+                &owner,
+                &[],
+            )),
+        ] {
+            assert!(!check(instruction, &signer));
+        }
+    }
+
+    #[test]
+    fn token_accounts_only_close_to_the_signer() {
+        let signer = Pubkey::new_unique();
+        let account = program_pubkey(&Pubkey::new_unique());
+        let close_to = |destination: &Pubkey| {
+            token_instruction(spl_token::instruction::close_account(
+                &spl_token::ID,
+                &account,
+                &program_pubkey(destination),
+                &program_pubkey(&signer),
+                &[],
+            ))
+        };
+
+        assert!(check(close_to(&signer), &signer));
+        assert!(!check(close_to(&Pubkey::new_unique()), &signer));
+        assert!(check(
+            token_instruction(spl_token::instruction::sync_native(
+                &spl_token::ID,
+                &account
+            )),
+            &signer
+        ));
+    }
+
+    #[test]
+    fn lookup_tables_only_close_to_the_signer() {
+        let signer = Pubkey::new_unique();
+        let table = Pubkey::new_unique();
+
+        assert!(check(close_lookup_table(table, signer, signer), &signer));
+        assert!(!check(
+            close_lookup_table(table, signer, Pubkey::new_unique()),
+            &signer
+        ));
+    }
+
+    #[test]
+    fn extending_lookup_tables_counts_their_rent() {
+        let signer = Pubkey::new_unique();
+        let addresses = vec![Pubkey::new_unique(); 3];
+        let message = legacy(
+            &[extend_lookup_table(
+                Pubkey::new_unique(),
+                signer,
+                Some(signer),
+                addresses,
+            )],
+            &signer,
+        );
+
+        assert_eq!(
+            SigningPolicy::spent_lamports(&message),
+            5_000 + 3 * 32 * RENT_PER_BYTE
+        );
+    }
+
+    #[test]
+    fn local_keypair_signs_assembled_transaction() {
+        let keypair = Keypair::new();
+        let instructions = transfer_of(&Signer::pubkey(&keypair), 1);
+
+        let tx = sign_transaction(&keypair, &instructions, Hash::new_unique(), &[]).unwrap();
+        assert!(verified(&tx));
+    }
+
+    #[test]
+    fn accounts_are_looked_up_through_the_table() {
+        let keypair = Keypair::new();
+        let destination = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![destination],
+        };
+        let instructions = [transfer(&Signer::pubkey(&keypair), &destination, 1)];
+
+        let tx = sign_transaction(&keypair, &instructions, Hash::new_unique(), &[table]).unwrap();
+        assert!(verified(&tx));
+        assert_eq!(tx.message.address_table_lookups().unwrap().len(), 1);
+        assert!(!tx.message.static_account_keys().contains(&destination));
+    }
+
+    #[test]
+    fn policy_checks_v0_messages() {
+        let signer = Pubkey::new_unique();
+        let account = program_pubkey(&Pubkey::new_unique());
+        let elsewhere = Pubkey::new_unique();
+        let tables = [AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![elsewhere],
+        }];
+        let v0 = |destination: &Pubkey| {
+            let close = token_instruction(spl_token::instruction::close_account(
+                &spl_token::ID,
+                &account,
+                &program_pubkey(destination),
+                &program_pubkey(&signer),
+                &[],
+            ));
+            VersionedMessage::V0(
+                v0::Message::try_compile(&signer, &[close], &tables, Hash::new_unique()).unwrap(),
+            )
+        };
+
+        assert!(policy(u64::MAX).check(&v0(&signer), &signer).is_ok());
+        // The destination is only reachable through the table
+        assert!(policy(u64::MAX).check(&v0(&elsewhere), &signer).is_err());
+    }
+
+    #[test]
+    fn remote_signer_over_unix_socket() {
+        let keypair = Keypair::new();
+        let pubkey = Signer::pubkey(&keypair);
+        let path = unix_stub(keypair, Duration::ZERO);
+        let signer = remote(SignerTransport::Unix(path.clone()), pubkey, u64::MAX);
+
+        let tx =
+            sign_transaction(&signer, &transfer_of(&pubkey, 1), Hash::new_unique(), &[]).unwrap();
+        assert!(verified(&tx));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn remote_signing_keeps_the_runtime_running() {
+        let keypair = Keypair::new();
+        let pubkey = Signer::pubkey(&keypair);
+        let path = unix_stub(keypair, Duration::from_millis(300));
+        let signer = remote(SignerTransport::Unix(path.clone()), pubkey, u64::MAX);
+
+        // Both tasks share the one worker, the timer only fires in time if signing hands
+        // it over
+        let ticked = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Instant::now()
+        });
+        let signing = tokio::spawn(async move {
+            let tx = sign_transaction(&signer, &transfer_of(&pubkey, 1), Hash::new_unique(), &[]);
+            (tx.map(|tx| verified(&tx)), Instant::now())
+        });
+
+        let (verified, signed) = signing.await.unwrap();
+        assert!(verified.unwrap());
+        assert!(ticked.await.unwrap() < signed);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn remote_signer_over_http() {
+        let keypair = Keypair::new();
+        let pubkey = Signer::pubkey(&keypair);
+        let signer = remote(SignerTransport::Http(http_stub(keypair)), pubkey, u64::MAX);
+
+        let tx =
+            sign_transaction(&signer, &transfer_of(&pubkey, 1), Hash::new_unique(), &[]).unwrap();
+        assert!(verified(&tx));
+    }
+
+    #[test]
+    fn remote_signature_from_another_key_is_rejected() {
+        let pubkey = Pubkey::new_unique();
+        let path = unix_stub(Keypair::new(), Duration::ZERO);
+        let signer = remote(SignerTransport::Unix(path.clone()), pubkey, u64::MAX);
+
+        assert!(
+            sign_transaction(&signer, &transfer_of(&pubkey, 1), Hash::new_unique(), &[]).is_err()
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn policy_is_checked_before_contacting_the_signer() {
+        let pubkey = Pubkey::new_unique();
+        // Nothing listens there, the policy has to refuse first
+        let signer = remote(
+            SignerTransport::Unix(PathBuf::from("/nonexistent/signer.sock")),
+            pubkey,
+            1,
+        );
+
+        let err = sign_transaction(&signer, &transfer_of(&pubkey, 1), Hash::new_unique(), &[])
+            .unwrap_err();
+        assert!(err.to_string().contains("signing policy"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use crate::assembler::{Funding, FundingContext};
+use crate::graph::TokenGraph;
+use crate::opportunity::{quote_route, Leg, Opportunity};
+
+// 1/φ, the ratio golden-section search shrinks the bracket by
+const INV_PHI: f64 = 0.618_033_988_749_895;
+
+// Stop searching once the bracket is narrower than this fraction of the upper bound
+pub const SIZING_PRECISION: u64 = 10_000;
+
+/// Best input found by the search, with every size tried and its net profit
+#[derive(Debug, Clone)]
+pub struct Sizing {
+    pub amount: u64,
+    pub net_profit: i128,
+    /// (amount, net profit) sorted by amount
+    pub curve: Vec<(u64, i128)>,
+}
+
+/// Route sized to maximize net profit, with how it gets funded
+#[derive(Debug, Clone)]
+pub struct SizedTrade {
+    pub opportunity: Opportunity,
+    pub funding: Funding,
+    /// After swap fees, flash loan fee, priority fee and tip
+    pub net_profit: i128,
+    pub curve: Vec<(u64, i128)>,
+}
+
+/// Golden-section search for the input in `min..=max` maximizing `profit`,
+/// which returns None for sizes that can't be traded
+pub fn golden_section_search<F>(min: u64, max: u64, tolerance: u64, mut profit: F) -> Option<Sizing>
+where
+    F: FnMut(u64) -> Option<i128>,
+{
+    if min > max {
+        return None;
+    }
+
+    let mut curve = BTreeMap::new();
+    let mut evaluate = |amount: u64| {
+        curve
+            .entry(amount)
+            .or_insert_with(|| profit(amount))
+            .unwrap_or(i128::MIN)
+    };
+
+    let (mut low, mut high) = (min, max);
+    evaluate(low);
+    evaluate(high);
+
+    while high - low > tolerance.max(2) {
+        let step = ((high - low) as f64 * INV_PHI) as u64;
+        let left = high - step;
+        let right = low + step;
+
+        if evaluate(left) < evaluate(right) {
+            low = left;
+        } else {
+            high = right;
+        }
+    }
+    evaluate(low + (high - low) / 2);
+
+    let curve = curve
+        .into_iter()
+        .filter_map(|(amount, profit)| Some((amount, profit?)))
+        .collect::<Vec<_>>();
+    let (amount, net_profit) = curve.iter().copied().max_by_key(|(_, profit)| *profit)?;
+
+    Some(Sizing {
+        amount,
+        net_profit,
+        curve,
+    })
+}
+
+/// Size the cycle along `legs`, bounded by what we can fund and `max_trade_size`
+pub fn size_route(
+    graph: &TokenGraph,
+    legs: &[Leg],
+    funding: &FundingContext,
+    max_trade_size: u64,
+) -> Option<SizedTrade> {
+    let base_mint = &graph.base_mint;
+    let max = funding.max_amount(base_mint).min(max_trade_size);
+    // Fees and tips are lamports, without a SOL price they can't be netted
+    let lamport_rate = graph.lamport_rate()?;
+    if max == 0 {
+        return None;
+    }
+
+    let sizing = golden_section_search(1, max, max / SIZING_PRECISION, |amount| {
+        let opportunity = quote_route(graph, legs, amount)?;
+        let plan = funding.plan(base_mint, amount, opportunity.expected_out, lamport_rate)?;
+        Some(plan.net_profit)
+    })?;
+
+    let opportunity = quote_route(graph, legs, sizing.amount)?;
+    let plan = funding.plan(
+        base_mint,
+        sizing.amount,
+        opportunity.expected_out,
+        lamport_rate,
+    )?;
+
+    Some(SizedTrade {
+        opportunity,
+        funding: plan.funding,
+        net_profit: sizing.net_profit,
+        curve: sizing.curve,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Concave like a real route: gains grow slower than the price impact past `peak`
+    fn parabola(peak: u64) -> impl FnMut(u64) -> Option<i128> {
+        move |amount| {
+            let distance = amount as i128 - peak as i128;
+            Some(1_000_000_000 - distance * distance / 1_000)
+        }
+    }
+
+    #[test]
+    fn finds_the_optimum_within_tolerance() {
+        let sizing = golden_section_search(1, 10_000_000, 1_000, parabola(3_141_592)).unwrap();
+
+        assert!(sizing.amount.abs_diff(3_141_592) <= 1_000);
+        assert_eq!(
+            sizing.net_profit,
+            sizing
+                .curve
+                .iter()
+                .map(|(_, profit)| *profit)
+                .max()
+                .unwrap()
+        );
+        assert!(sizing.curve.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn optimum_at_the_bounds_is_found() {
+        let increasing = golden_section_search(10, 1_000_000, 100, |amount| Some(amount as i128));
+        assert_eq!(increasing.unwrap().amount, 1_000_000);
+
+        let decreasing =
+            golden_section_search(10, 1_000_000, 100, |amount| Some(-(amount as i128)));
+        assert_eq!(decreasing.unwrap().amount, 10);
+    }
+
+    #[test]
+    fn never_tries_sizes_outside_the_bounds() {
+        let mut tried = vec![];
+        golden_section_search(500, 600, 1, |amount| {
+            tried.push(amount);
+            Some(0)
+        });
+
+        assert!(tried.iter().all(|amount| (500..=600).contains(amount)));
+        assert_eq!(
+            golden_section_search(2, 1, 1, |_| Some(0)).map(|s| s.amount),
+            None
+        );
+        assert_eq!(
+            golden_section_search(7, 7, 1, |_| Some(0)).map(|s| s.amount),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn untradeable_sizes_are_left_out() {
+        // Pools run dry past 400_000
+        let sizing = golden_section_search(1, 1_000_000, 100, |amount| {
+            (amount <= 400_000).then_some(amount as i128)
+        })
+        .unwrap();
+
+        assert!(sizing.amount <= 400_000);
+        assert!(sizing.curve.iter().all(|(amount, _)| *amount <= 400_000));
+        assert!(golden_section_search(1, 1_000, 10, |_| None).is_none());
+    }
+}
@@ -4,45 +4,51 @@ use crate::pools_struct::orca::WhirlpoolState;
 use crate::pools_struct::raydium::RaydiumPoolState;
 use crate::pools_struct::structs::DexType;
 use crate::pools_struct::structs::PriceFetcher;
-use base64::engine::{self, general_purpose};
+use base64::engine::general_purpose;
 use base64::Engine;
 use borsh::BorshDeserialize;
-use solana_sdk::message::Instruction;
+use solana_sdk::message::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
+use solana_system_interface::instruction::transfer;
+use spl_token::solana_program::program_pack::Pack;
 use std::io::Cursor;
-use std::str::FromStr;
 use zstd::decode_all;
 
-pub fn parse_encoded_data(encoded: &str, dex: DexType) -> Result<Box<dyn PriceFetcher>, PoolError> {
+/// Decode base64+zstd account data as sent by the websocket
+pub fn decode_account_data(encoded: &str) -> Result<Vec<u8>, PoolError> {
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| PoolError::InvalidPoolData)?;
+
+    decode_all(Cursor::new(bytes)).map_err(|_| PoolError::InvalidPoolData)
+}
+
+pub fn parse_account_data(
+    raw_bytes: &[u8],
+    dex: DexType,
+) -> Result<Box<dyn PriceFetcher>, PoolError> {
     match dex {
         DexType::Meteora => {
-            let bytes = base64::Engine::decode(&general_purpose::STANDARD, encoded).unwrap();
-            let raw_bytes = decode_all(Cursor::new(bytes)).expect("faled decoind all bytes");
+            let data_without_discriminator =
+                raw_bytes.get(8..).ok_or(PoolError::InvalidPoolData)?;
 
-            let data_without_discriminator = &raw_bytes[8..];
-
-            let decoded = MeteoraPoolState::try_from_slice(&data_without_discriminator)
-                .expect("failed decoding pool state");
+            let decoded = MeteoraPoolState::try_from_slice(data_without_discriminator)
+                .map_err(|_| PoolError::InvalidPoolData)?;
 
             Ok(Box::new(decoded))
         }
         DexType::Raydium => {
-            let bytes = base64::Engine::decode(&general_purpose::STANDARD, encoded).unwrap();
-            let raw_bytes = decode_all(Cursor::new(bytes)).expect("faled decoind all bytes");
-
-            let data_without_discriminator = &raw_bytes[8..];
+            let data_without_discriminator =
+                raw_bytes.get(8..).ok_or(PoolError::InvalidPoolData)?;
 
-            let decoded = RaydiumPoolState::try_from_slice(&data_without_discriminator)
-                .expect("failed decoding pool state");
+            let decoded = RaydiumPoolState::try_from_slice(data_without_discriminator)
+                .map_err(|_| PoolError::InvalidPoolData)?;
 
             Ok(Box::new(decoded))
         }
         DexType::Orca => {
-            let bytes = general_purpose::STANDARD.decode(encoded).unwrap();
-
-            let raw_bytes = decode_all(Cursor::new(bytes)).expect("faled decoind all bytes");
-            let decoded =
-                WhirlpoolState::try_from_slice(&raw_bytes).expect("failed decoding pool state");
+            let decoded = WhirlpoolState::try_from_slice(raw_bytes)
+                .map_err(|_| PoolError::InvalidPoolData)?;
 
             Ok(Box::new(decoded))
         }
@@ -68,11 +74,78 @@ pub fn parse_encoded_data(encoded: &str, dex: DexType) -> Result<Box<dyn PriceFe
         // }
         _ => {
             eprintln!("Unknown pool type");
-            Err(PoolError::InvalidPoolData.into())
+            Err(PoolError::InvalidPoolData)
         }
     }
 }
 
+/// Mint of wrapped SOL
+pub fn wsol_mint() -> Pubkey {
+    Pubkey::new_from_array(spl_token::native_mint::ID.to_bytes())
+}
+
+/// Same address as the SPL crates' solana-program 2 pubkey
+pub fn program_pubkey(pubkey: &Pubkey) -> spl_token::solana_program::pubkey::Pubkey {
+    spl_token::solana_program::pubkey::Pubkey::new_from_array(pubkey.to_bytes())
+}
+
+pub fn get_associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    let address = spl_associated_token_account::get_associated_token_address(
+        &program_pubkey(owner),
+        &program_pubkey(mint),
+    );
+
+    Pubkey::new_from_array(address.to_bytes())
+}
+
+/// Move `lamports` from `owner` into its native WSOL account, which has to exist
+pub fn wrap_sol(
+    owner: &Pubkey,
+    wsol_account: &Pubkey,
+    lamports: u64,
+) -> Result<Vec<Instruction>, anyhow::Error> {
+    Ok(vec![
+        transfer(owner, wsol_account, lamports),
+        // The token amount only follows the lamports once synced
+        from_program_instruction(spl_token::instruction::sync_native(
+            &spl_token::ID,
+            &program_pubkey(wsol_account),
+        )?),
+    ])
+}
+
+/// Convert an instruction built by the SPL crates, which still use solana-program 2
+pub fn from_program_instruction(
+    instruction: spl_token::solana_program::instruction::Instruction,
+) -> Instruction {
+    Instruction {
+        program_id: Pubkey::new_from_array(instruction.program_id.to_bytes()),
+        accounts: instruction
+            .accounts
+            .into_iter()
+            .map(|meta| AccountMeta {
+                pubkey: Pubkey::new_from_array(meta.pubkey.to_bytes()),
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect(),
+        data: instruction.data,
+    }
+}
+
+/// Balance of an SPL token account, None if the data isn't one
+pub fn token_account_amount(data: &[u8]) -> Option<u64> {
+    spl_token::state::Account::unpack(data)
+        .ok()
+        .map(|account| account.amount)
+}
+
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("ComputeBudget111111111111111111111111111111");
+pub const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array(spl_token::ID.to_bytes());
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
 pub fn set_compute_unit_limit(units: u32) -> Instruction {
     let data = [
         2u8,                  // Instruction index for SetComputeUnitLimit (from Solscan: 02)
@@ -83,7 +156,7 @@ pub fn set_compute_unit_limit(units: u32) -> Instruction {
     ]
     .to_vec();
     Instruction {
-        program_id: Pubkey::from_str("ComputeBudget111111111111111111111111111111").unwrap(),
+        program_id: COMPUTE_BUDGET_PROGRAM_ID,
         accounts: vec![],
         data,
     }
@@ -103,7 +176,7 @@ pub fn set_compute_unit_price(micro_lamports: u64) -> Instruction {
     ]
     .to_vec();
     Instruction {
-        program_id: Pubkey::from_str("ComputeBudget111111111111111111111111111111").unwrap(),
+        program_id: COMPUTE_BUDGET_PROGRAM_ID,
         accounts: vec![],
         data,
     }